@@ -0,0 +1,517 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use payeng::payment_engine::{DEFAULT_CHECKPOINT_INTERVAL, DEFAULT_INDEX_CACHE};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "A simple payments engine for processing transactions"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Process transactions and write the client accounts
+    Process(Box<ProcessArgs>),
+    /// Validate the input file without booking any transactions
+    Validate(ValidateArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ProcessArgs {
+    /// Paths or glob patterns of the input files, processed as a single stream; use '-' to read from stdin
+    #[arg(required = true)]
+    pub input: Vec<String>,
+    /// Format of the input files; detected by the file extension if not set ('.jsonl' or '.ndjson' for JSON Lines)
+    #[arg(long, value_enum)]
+    pub input_format: Option<InputFormat>,
+    /// Path to the output file; defaults to stdout
+    #[arg(short, long)]
+    pub output: Option<String>,
+    /// Output format of the client accounts
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    pub format: Format,
+    /// Abort processing or skip transactions which can't be booked
+    #[arg(long, value_enum, default_value_t = OnError::Abort)]
+    pub on_error: OnError,
+    /// Apply transaction events grouped under the referenced transaction (legacy batch processing)
+    #[arg(long)]
+    pub group_events: bool,
+    /// Path to the report of rejected transactions and ignored transaction events (csv, or JSON Lines for '.jsonl')
+    #[arg(long)]
+    pub rejections: Option<String>,
+    /// Path to the engine snapshot written after processing
+    #[arg(long, conflicts_with = "group_events")]
+    pub snapshot: Option<String>,
+    /// Path to the engine snapshot of a previous run to continue from
+    #[arg(long, conflicts_with = "group_events")]
+    pub restore: Option<String>,
+    /// Path to the client accounts of a previous run in csv format used as opening balances
+    #[arg(long, conflicts_with = "restore")]
+    pub opening_balances: Option<String>,
+    /// Directory of the write-ahead log; an interrupted run is recovered from it when restarted with the same input
+    #[arg(long, group = "recovery", conflicts_with = "group_events")]
+    pub wal: Option<String>,
+    /// Path to the checkpoint file with the position of the next input record and the client accounts
+    #[arg(long, group = "recovery", conflicts_with_all = ["group_events", "wal"])]
+    pub checkpoint: Option<String>,
+    /// Continue an interrupted run from the checkpoint file if it exists (csv input files only)
+    #[arg(long, requires = "checkpoint")]
+    pub resume: bool,
+    /// Number of worker threads; records are partitioned by client id
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..), conflicts_with_all = ["group_events", "recovery"])]
+    pub threads: u16,
+    /// Read, validate and book records on separate threads connected by bounded channels
+    #[arg(long, conflicts_with_all = ["group_events", "recovery", "threads"])]
+    pub pipeline: bool,
+    /// Path to a disk-backed index of the processed transactions for inputs larger than memory; the file is removed after the run
+    #[arg(long, conflicts_with_all = ["group_events", "recovery", "threads"])]
+    pub index: Option<String>,
+    /// Number of transactions cached in memory by the disk-backed index
+    #[arg(long, requires = "index", default_value_t = DEFAULT_INDEX_CACHE)]
+    pub index_cache: usize,
+    /// Number of subsequent records in which a transaction can be disputed; afterwards it is finalized and evicted from memory
+    #[arg(long, value_name = "RECORDS", conflicts_with_all = ["group_events", "recovery", "threads"])]
+    pub dispute_window: Option<u64>,
+    /// Number of seconds after its timestamp in which a transaction can be disputed; afterwards it is finalized and evicted from memory
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        requires = "order_by_time",
+        conflicts_with_all = ["dispute_window", "group_events", "recovery", "threads"]
+    )]
+    pub dispute_window_seconds: Option<u32>,
+    /// Book the records in the order of the 'timestamp' column instead of file order; stable on ties
+    #[arg(long, conflicts_with_all = ["group_events", "recovery", "threads"])]
+    pub order_by_time: bool,
+    /// Seconds a record may arrive after later records and still be reordered; later arrivals are rejected
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        requires = "order_by_time",
+        default_value_t = 0
+    )]
+    pub time_tolerance: u32,
+    /// Number of records between two checkpoints of the write-ahead log or the checkpoint file
+    #[arg(long, requires = "recovery", default_value_t = DEFAULT_CHECKPOINT_INTERVAL)]
+    pub checkpoint_interval: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct ValidateArgs {
+    /// Path to the input file; use '-' to read from stdin
+    pub input: String,
+    /// Format of the input file; detected by the file extension if not set ('.jsonl' or '.ndjson' for JSON Lines)
+    #[arg(long, value_enum)]
+    pub input_format: Option<InputFormat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum InputFormat {
+    Csv,
+    // JSON Lines, i.e. one transaction per line
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    Csv,
+    // JSON array
+    Json,
+    // JSON Lines, i.e. one account per line
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OnError {
+    Abort,
+    Skip,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_process() {
+        let cli = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "transactions.csv",
+            "-o",
+            "accounts.json",
+            "--format",
+            "json",
+            "--on-error",
+            "skip",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Command::Process(args) => {
+                assert_eq!(args.input, vec![String::from("transactions.csv")]);
+                assert_eq!(args.output, Some(String::from("accounts.json")));
+                assert_eq!(args.format, Format::Json);
+                assert_eq!(args.on_error, OnError::Skip);
+                assert!(!args.group_events);
+                assert_eq!(args.rejections, None);
+                assert_eq!(args.snapshot, None);
+                assert_eq!(args.restore, None);
+                assert_eq!(args.opening_balances, None);
+                assert_eq!(args.wal, None);
+                assert_eq!(args.checkpoint, None);
+                assert!(!args.resume);
+                assert_eq!(args.threads, 1);
+                assert!(!args.pipeline);
+                assert_eq!(args.index, None);
+                assert_eq!(args.index_cache, DEFAULT_INDEX_CACHE);
+                assert_eq!(args.dispute_window, None);
+                assert!(!args.order_by_time);
+                assert_eq!(args.time_tolerance, 0);
+                assert_eq!(args.checkpoint_interval, DEFAULT_CHECKPOINT_INTERVAL);
+            }
+            _ => panic!("Unexpected subcommand"),
+        }
+    }
+
+    #[test]
+    fn test_parse_missing_input() {
+        let res = Cli::try_parse_from(["payeng", "process"]);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_parse_multiple_inputs() {
+        let cli = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "gateway_a.csv",
+            "gateways/*.csv",
+            "--order-by-time",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Command::Process(args) => {
+                assert_eq!(args.input, vec!["gateway_a.csv", "gateways/*.csv"]);
+                assert!(args.order_by_time);
+            }
+            _ => panic!("Unexpected subcommand"),
+        }
+    }
+
+    #[test]
+    fn test_parse_format_json_lines() {
+        let cli = Cli::try_parse_from(["payeng", "process", "-", "--format", "jsonl"]).unwrap();
+
+        match cli.command {
+            Command::Process(args) => assert_eq!(args.format, Format::Jsonl),
+            _ => panic!("Unexpected subcommand"),
+        }
+    }
+
+    #[test]
+    fn test_parse_input_format() {
+        let cli =
+            Cli::try_parse_from(["payeng", "validate", "-", "--input-format", "jsonl"]).unwrap();
+
+        match cli.command {
+            Command::Validate(args) => assert_eq!(args.input_format, Some(InputFormat::Jsonl)),
+            _ => panic!("Unexpected subcommand"),
+        }
+    }
+
+    #[test]
+    fn test_parse_snapshot() {
+        let cli = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "transactions.csv",
+            "--restore",
+            "monday.json",
+            "--snapshot",
+            "tuesday.json",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Command::Process(args) => {
+                assert_eq!(args.restore, Some(String::from("monday.json")));
+                assert_eq!(args.snapshot, Some(String::from("tuesday.json")));
+            }
+            _ => panic!("Unexpected subcommand"),
+        }
+
+        let res = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "transactions.csv",
+            "--snapshot",
+            "tuesday.json",
+            "--group-events",
+        ]);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_parse_opening_balances() {
+        let cli = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "transactions.csv",
+            "--opening-balances",
+            "accounts.csv",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Command::Process(args) => {
+                assert_eq!(args.opening_balances, Some(String::from("accounts.csv")))
+            }
+            _ => panic!("Unexpected subcommand"),
+        }
+
+        let res = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "transactions.csv",
+            "--opening-balances",
+            "accounts.csv",
+            "--restore",
+            "snapshot.json",
+        ]);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_parse_wal() {
+        let cli = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "transactions.csv",
+            "--wal",
+            "wal",
+            "--checkpoint-interval",
+            "1000",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Command::Process(args) => {
+                assert_eq!(args.wal, Some(String::from("wal")));
+                assert_eq!(args.checkpoint_interval, 1000);
+            }
+            _ => panic!("Unexpected subcommand"),
+        }
+
+        let res = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "transactions.csv",
+            "--checkpoint-interval",
+            "1000",
+        ]);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_parse_resume() {
+        let cli = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "transactions.csv",
+            "--checkpoint",
+            "checkpoint.json",
+            "--checkpoint-interval",
+            "1000000",
+            "--resume",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Command::Process(args) => {
+                assert_eq!(args.checkpoint, Some(String::from("checkpoint.json")));
+                assert_eq!(args.checkpoint_interval, 1_000_000);
+                assert!(args.resume);
+            }
+            _ => panic!("Unexpected subcommand"),
+        }
+
+        let res = Cli::try_parse_from(["payeng", "process", "transactions.csv", "--resume"]);
+        assert!(res.is_err());
+
+        let res = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "transactions.csv",
+            "--checkpoint",
+            "checkpoint.json",
+            "--wal",
+            "wal",
+        ]);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_parse_threads() {
+        let cli = Cli::try_parse_from(["payeng", "process", "-", "--threads", "4"]).unwrap();
+
+        match cli.command {
+            Command::Process(args) => assert_eq!(args.threads, 4),
+            _ => panic!("Unexpected subcommand"),
+        }
+
+        let res = Cli::try_parse_from(["payeng", "process", "-", "--threads", "0"]);
+        assert!(res.is_err());
+
+        let res = Cli::try_parse_from(["payeng", "process", "-", "--threads", "4", "--wal", "wal"]);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_parse_pipeline() {
+        let cli = Cli::try_parse_from(["payeng", "process", "-", "--pipeline"]).unwrap();
+
+        match cli.command {
+            Command::Process(args) => assert!(args.pipeline),
+            _ => panic!("Unexpected subcommand"),
+        }
+
+        let res = Cli::try_parse_from(["payeng", "process", "-", "--pipeline", "--threads", "4"]);
+        assert!(res.is_err());
+
+        let res = Cli::try_parse_from(["payeng", "process", "-", "--pipeline", "--group-events"]);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_parse_index() {
+        let cli = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "-",
+            "--index",
+            "index.bin",
+            "--index-cache",
+            "1000",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Command::Process(args) => {
+                assert_eq!(args.index, Some(String::from("index.bin")));
+                assert_eq!(args.index_cache, 1000);
+            }
+            _ => panic!("Unexpected subcommand"),
+        }
+
+        let res = Cli::try_parse_from(["payeng", "process", "-", "--index-cache", "1000"]);
+        assert!(res.is_err());
+
+        let res = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "-",
+            "--index",
+            "index.bin",
+            "--group-events",
+        ]);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_parse_dispute_window() {
+        let cli =
+            Cli::try_parse_from(["payeng", "process", "-", "--dispute-window", "1000"]).unwrap();
+
+        match cli.command {
+            Command::Process(args) => assert_eq!(args.dispute_window, Some(1000)),
+            _ => panic!("Unexpected subcommand"),
+        }
+
+        let cli = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "-",
+            "--order-by-time",
+            "--dispute-window-seconds",
+            "3600",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Command::Process(args) => assert_eq!(args.dispute_window_seconds, Some(3600)),
+            _ => panic!("Unexpected subcommand"),
+        }
+
+        // The window by time needs the timestamps of the records
+        let res =
+            Cli::try_parse_from(["payeng", "process", "-", "--dispute-window-seconds", "3600"]);
+        assert!(res.is_err());
+
+        let res = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "-",
+            "--order-by-time",
+            "--dispute-window-seconds",
+            "3600",
+            "--dispute-window",
+            "1000",
+        ]);
+        assert!(res.is_err());
+
+        let res = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "-",
+            "--dispute-window",
+            "1000",
+            "--threads",
+            "4",
+        ]);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_parse_order_by_time() {
+        let cli = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "-",
+            "--order-by-time",
+            "--time-tolerance",
+            "60",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Command::Process(args) => {
+                assert!(args.order_by_time);
+                assert_eq!(args.time_tolerance, 60);
+            }
+            _ => panic!("Unexpected subcommand"),
+        }
+
+        let res = Cli::try_parse_from(["payeng", "process", "-", "--time-tolerance", "60"]);
+        assert!(res.is_err());
+
+        let res =
+            Cli::try_parse_from(["payeng", "process", "-", "--order-by-time", "--wal", "wal"]);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid_format() {
+        let res = Cli::try_parse_from(["payeng", "process", "-", "--format", "xml"]);
+        assert!(res.is_err());
+    }
+}
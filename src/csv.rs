@@ -1,17 +1,22 @@
-use crate::models::{RawAccount, RawTransaction};
-use anyhow::Context;
-
-pub fn read(csv_file: &str) -> Result<Vec<RawTransaction>, anyhow::Error> {
-    // Prepare csv reader and remove/ignore all whitespaces
-    let mut csv_reader = csv::ReaderBuilder::new()
-        .trim(csv::Trim::All)
-        .from_path(csv_file)
-        .context(format!("Can't find csv file at path '{}'", csv_file))?;
+use crate::{
+    errors::EngineError,
+    input,
+    models::{Amount, EventType, Position, RawAccount, RawTransaction, Rejection, TransactionType},
+    output::AccountSink,
+};
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Stdout, Write},
+    str::{self, FromStr},
+    sync::Arc,
+};
 
+pub fn read(csv_file: &str) -> Result<Vec<RawTransaction>, EngineError> {
     let mut raw_transactions = vec![];
 
     // Read from file and deserialize to RawTransaction type
-    for record in csv_reader.deserialize() {
+    for record in read_stream(csv_file)? {
         let raw_transaction: RawTransaction = record?;
         raw_transactions.push(raw_transaction);
     }
@@ -19,16 +24,574 @@ pub fn read(csv_file: &str) -> Result<Vec<RawTransaction>, anyhow::Error> {
     Ok(raw_transactions)
 }
 
-pub fn write(raw_accounts: Vec<RawAccount>) -> Result<(), anyhow::Error> {
-    // Prepare csv writer and configure to write csv records to stdout
-    let mut csv_writer = csv::Writer::from_writer(std::io::stdout());
+// Read client accounts, e.g. the output of a previous run as opening balances
+pub fn read_accounts(csv_file: &str) -> Result<Vec<RawAccount>, EngineError> {
+    let (reader, file) = input::open_reader(csv_file, "csv")?;
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+
+    let mut raw_accounts = vec![];
 
-    // Serialize and write raw accounts to stdout
-    for raw_account in raw_accounts {
-        csv_writer.serialize(raw_account)?;
+    for record in csv_reader.deserialize() {
+        let raw_account: RawAccount = record.map_err(|err| locate(err, &file))?;
+        raw_accounts.push(raw_account);
+    }
+
+    Ok(raw_accounts)
+}
+
+// Read lazily from file; raw transactions are deserialized one by one while iterating
+pub fn read_stream(csv_file: &str) -> Result<CsvSource, EngineError> {
+    CsvSource::open(csv_file)
+}
+
+/*
+    Records are read as byte records and parsed without serde on the fast path: the type token is matched to the known transaction and event types
+    and the amount is parsed directly into the fixed-point representation, so that a valid record doesn't allocate
+    Records which can't be parsed on the fast path, e.g. with an unknown type or an invalid amount, are deserialized via serde for error reporting
+*/
+
+// Transaction source reading csv records from a file, stdin or any other reader
+pub struct CsvSource {
+    reader: csv::Reader<input::Reader>,
+    headers: csv::ByteRecord,
+    // Buffer of the current record; reused so that reading doesn't allocate
+    record: csv::ByteRecord,
+    // Column indices for the fast path; none if a column is missing
+    columns: Option<Columns>,
+    file: Option<Arc<str>>,
+    // Position of the first record read; records are read from the middle of the file when resuming
+    offset: Option<Position>,
+}
+
+impl CsvSource {
+    // Open the csv file; the path '-' refers to stdin
+    pub fn open(csv_file: &str) -> Result<Self, EngineError> {
+        let (reader, file) = input::open_reader(csv_file, "csv")?;
+        Self::with_file(reader, file)
+    }
+
+    // Open the csv file and continue reading at the position of a record, e.g. from a checkpoint
+    pub fn resume(csv_file: &str, position: &Position) -> Result<Self, EngineError> {
+        // The header is only part of the beginning of the file
+        let headers = Self::open(csv_file)?.headers;
+
+        let mut file = File::open(csv_file).map_err(|source| EngineError::File {
+            message: format!("Can't find csv file at path '{}'", csv_file),
+            source,
+        })?;
+        file.seek(SeekFrom::Start(position.byte))?;
+
+        let csv_reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .has_headers(false)
+            .from_reader(Box::new(file) as input::Reader);
+
+        Ok(Self {
+            reader: csv_reader,
+            columns: Columns::new(&headers),
+            headers,
+            record: csv::ByteRecord::new(),
+            file: Some(Arc::from(csv_file)),
+            offset: Some(position.clone()),
+        })
+    }
+
+    pub fn stdin() -> Result<Self, EngineError> {
+        Self::open(input::STDIN)
+    }
+
+    pub fn from_reader(reader: impl Read + Send + 'static) -> Result<Self, EngineError> {
+        Self::with_file(Box::new(reader), None)
+    }
+
+    fn with_file(reader: input::Reader, file: Option<Arc<str>>) -> Result<Self, EngineError> {
+        // Prepare csv reader and remove/ignore all whitespaces
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+
+        let headers = csv_reader.byte_headers()?.clone();
+
+        Ok(Self {
+            reader: csv_reader,
+            columns: Columns::new(&headers),
+            headers,
+            record: csv::ByteRecord::new(),
+            file,
+            offset: None,
+        })
+    }
+}
+
+impl Iterator for CsvSource {
+    type Item = Result<RawTransaction, EngineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.reader.read_byte_record(&mut self.record) {
+            Ok(true) => Some(self.deserialize().map_err(|err| self.locate(err))),
+            Ok(false) => None,
+            Err(err) => Some(Err(self.locate(err))),
+        }
+    }
+}
+
+impl CsvSource {
+    fn deserialize(&self) -> Result<RawTransaction, csv::Error> {
+        let mut raw_transaction = match self.parse(&self.record) {
+            Some(raw_transaction) => raw_transaction,
+            None => {
+                // The csv reader doesn't trim the first record if it reads without headers, e.g. when resuming
+                let mut record = self.record.clone();
+                record.trim();
+                record.deserialize(Some(&self.headers))?
+            }
+        };
+
+        // Keep track of the position in the input file for error reporting
+        if let Some(position) = self.record.position() {
+            raw_transaction.position = self.position(position);
+        }
+
+        Ok(raw_transaction)
+    }
+
+    // Fast path; none if the record has to be deserialized via serde
+    fn parse(&self, record: &csv::ByteRecord) -> Option<RawTransaction> {
+        let columns = self.columns?;
+
+        if record.len() != self.headers.len() {
+            return None;
+        }
+
+        let amount = match &record[columns.amount] {
+            b"" => None,
+            amount => Some(Amount::parse_bytes(amount).ok()?),
+        };
+
+        let mut raw_transaction = RawTransaction::new(
+            type_token(&record[columns.r#type])?,
+            parse_field(&record[columns.client])?,
+            parse_field(&record[columns.tx])?,
+            amount,
+        );
+
+        if let Some(timestamp) = columns.timestamp {
+            raw_transaction.timestamp = match &record[timestamp] {
+                b"" => None,
+                timestamp => Some(parse_field(timestamp)?),
+            };
+        }
+
+        Some(raw_transaction)
+    }
+
+    fn locate(&self, err: csv::Error) -> EngineError {
+        match err.position().map(|position| self.position(position)) {
+            Some(position) => locate_at(err, position),
+            None => err.into(),
+        }
+    }
+
+    // Position in the input file; the csv reader counts from the offset when resuming
+    fn position(&self, position: &csv::Position) -> Position {
+        let mut position = Position::from(position);
+
+        if let Some(offset) = &self.offset {
+            position.line += offset.line - 1;
+            position.record += offset.record;
+            position.byte += offset.byte;
+        }
+
+        position.with_file(self.file.clone())
+    }
+}
+
+// Indices of the columns of a transaction record; the order of the columns is given by the header
+#[derive(Debug, Clone, Copy)]
+struct Columns {
+    r#type: usize,
+    client: usize,
+    tx: usize,
+    amount: usize,
+    // Optional column
+    timestamp: Option<usize>,
+}
+
+impl Columns {
+    fn new(headers: &csv::ByteRecord) -> Option<Self> {
+        let index = |name: &[u8]| headers.iter().position(|header| header == name);
+
+        Some(Self {
+            r#type: index(b"type")?,
+            client: index(b"client")?,
+            tx: index(b"tx")?,
+            amount: index(b"amount")?,
+            timestamp: index(b"timestamp"),
+        })
+    }
+}
+
+// Static token of a known transaction or event type; none for unknown types
+fn type_token(field: &[u8]) -> Option<&'static str> {
+    [
+        TransactionType::Deposit.as_str(),
+        TransactionType::Withdrawal.as_str(),
+        EventType::Dispute.as_str(),
+        EventType::Resolve.as_str(),
+        EventType::Chargeback.as_str(),
+    ]
+    .into_iter()
+    .find(|token| token.as_bytes() == field)
+}
+
+fn parse_field<T: FromStr>(field: &[u8]) -> Option<T> {
+    str::from_utf8(field).ok()?.parse().ok()
+}
+
+// Attach the position in the input file to csv errors; io errors are not related to a single record
+fn locate(err: csv::Error, file: &Option<Arc<str>>) -> EngineError {
+    let position = match err.position() {
+        Some(position) => Position::from(position).with_file(file.clone()),
+        None => return err.into(),
+    };
+
+    locate_at(err, position)
+}
+
+fn locate_at(err: csv::Error, position: Position) -> EngineError {
+    match err.kind() {
+        // The position is already part of the error; only keep the cause of deserialization errors
+        csv::ErrorKind::Deserialize { err, .. } => EngineError::from(err.clone()).at(&position),
+        _ => EngineError::from(err).at(&position),
+    }
+}
+
+pub fn write(writer: impl Write, raw_accounts: Vec<RawAccount>) -> Result<(), EngineError> {
+    // Serialize and write raw accounts, e.g. to stdout
+    write_records(writer, raw_accounts)
+}
+
+// Serialize and write records including the header
+fn write_records<T: Serialize>(
+    writer: impl Write,
+    records: impl IntoIterator<Item = T>,
+) -> Result<(), EngineError> {
+    let mut csv_writer = csv::Writer::from_writer(writer);
+
+    for record in records {
+        csv_writer.serialize(record)?;
     }
 
     csv_writer.flush()?;
 
     Ok(())
 }
+
+// Account sink writing csv records to stdout, a file or any other writer
+// Rejections are only written to a separate writer, so that they don't mix with the accounts
+pub struct CsvSink<W, R = io::Sink> {
+    writer: W,
+    rejections: Option<R>,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            rejections: None,
+        }
+    }
+
+    // Write the rejections to the given writer, e.g. a rejection report file; rejections are ignored otherwise
+    pub fn with_rejections<R: Write>(self, rejections: R) -> CsvSink<W, R> {
+        CsvSink {
+            writer: self.writer,
+            rejections: Some(rejections),
+        }
+    }
+}
+
+impl<W, R> CsvSink<W, R> {
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl CsvSink<Stdout> {
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+}
+
+impl<W: Write, R: Write> AccountSink for CsvSink<W, R> {
+    fn write_accounts(&mut self, raw_accounts: Vec<RawAccount>) -> Result<(), EngineError> {
+        write(&mut self.writer, raw_accounts)
+    }
+
+    fn write_rejections(&mut self, rejections: &[Rejection]) -> Result<(), EngineError> {
+        match &mut self.rejections {
+            Some(writer) => write_records(writer, rejections),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{errors::ErrorCategory, models::RejectionReason};
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_read_position() {
+        let res = read("test_data/transactions_with_events.csv");
+        assert!(res.is_ok());
+
+        let raw_transactions = res.unwrap();
+        let lines: Vec<u64> = raw_transactions
+            .iter()
+            .map(|raw_transaction| raw_transaction.position.line)
+            .collect();
+        assert_eq!(lines, vec![2, 3, 4, 5, 6, 7, 8]);
+
+        let position = &raw_transactions[1].position;
+        assert_eq!(
+            position.file.as_deref(),
+            Some("test_data/transactions_with_events.csv")
+        );
+        assert_eq!(position.record, 2);
+        assert!(position.byte > 0);
+    }
+
+    #[test]
+    fn test_read_deserialize_error() {
+        let res = read("test_data/transactions_deserialize_error.csv");
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert!(matches!(err.root(), EngineError::Deserialize(_)));
+
+        let position = err.position().unwrap();
+        assert_eq!(
+            position.file.as_deref(),
+            Some("test_data/transactions_deserialize_error.csv")
+        );
+        assert_eq!(position.line, 2);
+        assert_eq!(position.record, 1);
+    }
+
+    #[test]
+    fn test_csv_source_fast_path() {
+        let raw_transactions = read("test_data/transactions_with_events.csv").unwrap();
+
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path("test_data/transactions_with_events.csv")
+            .unwrap();
+        let deserialized: Vec<RawTransaction> =
+            csv_reader.deserialize().map(Result::unwrap).collect();
+
+        assert_eq!(raw_transactions.len(), deserialized.len());
+
+        for (raw_transaction, expected) in raw_transactions.iter().zip(&deserialized) {
+            // Known type tokens are not allocated
+            assert!(matches!(raw_transaction.r#type, Cow::Borrowed(_)));
+            assert_eq!(raw_transaction.r#type, expected.r#type);
+            assert_eq!(raw_transaction.client, expected.client);
+            assert_eq!(raw_transaction.tx, expected.tx);
+            assert_eq!(raw_transaction.amount, expected.amount);
+        }
+    }
+
+    #[test]
+    fn test_csv_source_column_order() {
+        let data = "amount,tx,type,client\n1.5,1,deposit,2\n,1,dispute,2\n";
+
+        let raw_transactions: Vec<RawTransaction> = CsvSource::from_reader(data.as_bytes())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(raw_transactions[0].r#type, "deposit");
+        assert_eq!(raw_transactions[0].client, 2);
+        assert_eq!(raw_transactions[0].amount, Some("1.5".parse().unwrap()));
+        assert_eq!(raw_transactions[1].r#type, "dispute");
+        assert_eq!(raw_transactions[1].amount, None);
+    }
+
+    #[test]
+    fn test_csv_source_timestamp() {
+        let data = "type,client,tx,amount,timestamp\ndeposit,1,1,1.0,2024-05-01T12:00:00Z\ndispute,1,1,,\ndeposit,1,2,1.0,yesterday\n";
+        let mut source = CsvSource::from_reader(data.as_bytes()).unwrap();
+
+        let raw_transaction = source.next().unwrap().unwrap();
+        assert!(matches!(raw_transaction.r#type, Cow::Borrowed(_)));
+        assert_eq!(
+            raw_transaction.timestamp,
+            Some("2024-05-01T12:00:00Z".parse().unwrap())
+        );
+
+        let raw_transaction = source.next().unwrap().unwrap();
+        assert_eq!(raw_transaction.timestamp, None);
+
+        // Invalid timestamps are reported by the serde fallback
+        let err = source.next().unwrap().unwrap_err();
+        assert_eq!(err.position().unwrap().line, 4);
+        assert_eq!(err.category(), ErrorCategory::Format);
+    }
+
+    #[test]
+    fn test_csv_source_fallback() {
+        let data = "type,client,tx,amount\nunknown,1,1,1.0\ndeposit,1,2,abc\n";
+        let mut source = CsvSource::from_reader(data.as_bytes()).unwrap();
+
+        // Unknown types are deserialized and rejected by the engine
+        let raw_transaction = source.next().unwrap().unwrap();
+        assert!(matches!(raw_transaction.r#type, Cow::Owned(_)));
+        assert_eq!(raw_transaction.r#type, "unknown");
+        assert_eq!(raw_transaction.position.line, 2);
+
+        let err = source.next().unwrap().unwrap_err();
+        assert!(matches!(err.root(), EngineError::Deserialize(_)));
+        assert_eq!(err.position().unwrap().line, 3);
+        assert_eq!(
+            err.to_string(),
+            "Unexpected format: invalid amount 'abc' (line 3, record 2, byte 38)"
+        );
+    }
+
+    #[test]
+    fn test_read_missing_file() {
+        let res = read("test_data/missing.csv");
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert!(matches!(err, EngineError::File { .. }));
+        assert_eq!(
+            err.to_string(),
+            "Can't find csv file at path 'test_data/missing.csv'"
+        );
+    }
+
+    #[test]
+    fn test_read_accounts() {
+        let res = read_accounts("test_data/accounts.csv");
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap(),
+            vec![
+                RawAccount::new(
+                    1,
+                    "1.5".parse().unwrap(),
+                    Amount::ZERO,
+                    "1.5".parse().unwrap(),
+                    false
+                ),
+                RawAccount::new(
+                    2,
+                    "2.0".parse().unwrap(),
+                    "1.0".parse().unwrap(),
+                    "3.0".parse().unwrap(),
+                    true
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_csv_source_resume() {
+        let raw_transactions = read("test_data/transactions_with_events.csv").unwrap();
+        let position = raw_transactions[3].position.clone();
+
+        let res = CsvSource::resume("test_data/transactions_with_events.csv", &position);
+        assert!(res.is_ok());
+
+        let resumed: Vec<RawTransaction> = res.unwrap().map(Result::unwrap).collect();
+        assert_eq!(resumed.len(), 4);
+
+        for (raw_transaction, resumed_transaction) in raw_transactions[3..].iter().zip(&resumed) {
+            assert_eq!(raw_transaction.r#type, resumed_transaction.r#type);
+            assert_eq!(raw_transaction.tx, resumed_transaction.tx);
+            assert_eq!(raw_transaction.position, resumed_transaction.position);
+        }
+    }
+
+    #[test]
+    fn test_write() {
+        let raw_accounts = vec![RawAccount::new(
+            1,
+            "1.5".parse().unwrap(),
+            Amount::ZERO,
+            "1.5".parse().unwrap(),
+            false,
+        )];
+        let mut output = vec![];
+
+        let res = write(&mut output, raw_accounts);
+        assert!(res.is_ok());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked\n1,1.5000,0.0000,1.5000,false\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_source_from_reader() {
+        let data = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndispute, 1, 1,\n";
+
+        let res = CsvSource::from_reader(data.as_bytes());
+        assert!(res.is_ok());
+
+        let raw_transactions: Vec<RawTransaction> = res.unwrap().map(Result::unwrap).collect();
+        assert_eq!(raw_transactions.len(), 2);
+        assert_eq!(raw_transactions[1].r#type, "dispute");
+        assert_eq!(raw_transactions[1].position.line, 3);
+        assert_eq!(raw_transactions[1].position.file, None);
+    }
+
+    #[test]
+    fn test_csv_sink() {
+        let mut sink = CsvSink::new(vec![]);
+
+        let res = sink.write_accounts(vec![RawAccount::new(
+            1,
+            "1.5".parse().unwrap(),
+            Amount::ZERO,
+            "1.5".parse().unwrap(),
+            false,
+        )]);
+        assert!(res.is_ok());
+        assert_eq!(
+            String::from_utf8(sink.into_inner()).unwrap(),
+            "client,available,held,total,locked\n1,1.5000,0.0000,1.5000,false\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_sink_rejections() {
+        let mut report = vec![];
+        let mut sink = CsvSink::new(vec![]).with_rejections(&mut report);
+
+        let rejections = vec![Rejection {
+            file: Some(String::from("transactions.csv")),
+            input: 0,
+            line: 5,
+            r#type: String::from("withdrawal"),
+            client: 1,
+            tx: 4,
+            reason: RejectionReason::InsufficientFunds,
+        }];
+
+        let res = sink.write_rejections(&rejections);
+        assert!(res.is_ok());
+
+        // The accounts are not mixed with the rejections
+        assert!(sink.into_inner().is_empty());
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "file,line,type,client,tx,reason\ntransactions.csv,5,withdrawal,1,4,insufficient_funds\n"
+        );
+    }
+}
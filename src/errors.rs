@@ -1,5 +1,101 @@
+use crate::models::{Position, Timestamp};
+use chrono::SecondsFormat;
+use std::io;
 use thiserror::Error;
 
+// Top-level error of the payment engine; wraps the typed errors of all modules so that failures can be matched by category
+#[derive(Error, Debug)]
+pub enum EngineError {
+    #[error(transparent)]
+    Format(#[from] FormatError),
+    #[error(transparent)]
+    Deserialize(#[from] csv::DeserializeError),
+    #[error(transparent)]
+    Deposit(#[from] DepositError),
+    #[error(transparent)]
+    Withdrawal(#[from] WithdrawalError),
+    #[error(transparent)]
+    Dispute(#[from] DisputeError),
+    #[error(transparent)]
+    Resolve(#[from] ResolveError),
+    #[error(transparent)]
+    Chargeback(#[from] ChargebackError),
+    #[error(transparent)]
+    Order(#[from] OrderError),
+    // Error raised for a single record; the position locates the record even if transaction ids are not unique
+    #[error("{} ({})", .source, .position)]
+    Record {
+        position: Position,
+        source: Box<EngineError>,
+    },
+    #[error(transparent)]
+    Snapshot(#[from] SnapshotError),
+    #[error(transparent)]
+    Wal(#[from] WalError),
+    #[error("{}", .message)]
+    File { message: String, source: io::Error },
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+// Failure category of an engine error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorCategory {
+    // The input data doesn't have the expected format
+    Format,
+    // A transaction or transaction event can't be booked on the client account
+    Booking,
+    // The input can't be read or the output can't be written
+    Io,
+}
+
+impl EngineError {
+    // Attach the position of the record in the input file
+    pub fn at(self, position: &Position) -> Self {
+        EngineError::Record {
+            position: position.clone(),
+            source: Box::new(self),
+        }
+    }
+
+    pub fn position(&self) -> Option<&Position> {
+        match self {
+            EngineError::Record { position, .. } => Some(position),
+            _ => None,
+        }
+    }
+
+    // Error without the position of the record
+    pub fn root(&self) -> &EngineError {
+        match self {
+            EngineError::Record { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        match self.root() {
+            EngineError::Format(_)
+            | EngineError::Deserialize(_)
+            | EngineError::Snapshot(_)
+            | EngineError::Wal(_) => ErrorCategory::Format,
+            EngineError::Deposit(_)
+            | EngineError::Withdrawal(_)
+            | EngineError::Dispute(_)
+            | EngineError::Resolve(_)
+            | EngineError::Chargeback(_)
+            | EngineError::Order(_) => ErrorCategory::Booking,
+            EngineError::Csv(err) if !err.is_io_error() => ErrorCategory::Format,
+            EngineError::Json(err) if !err.is_io() => ErrorCategory::Format,
+            _ => ErrorCategory::Io,
+        }
+    }
+}
+
 #[derive(Error, Debug, PartialEq)]
 pub enum FormatError {
     #[error(
@@ -11,10 +107,24 @@ pub enum FormatError {
     MissingAmount(u32, String),
     #[error("Unexpected format: amount should be none for transaction id {} and transaction type '{}'", .0, .1)]
     UnexpectedAmount(u32, String),
-    #[error("Unexpected format: amount is negative, infinite or NaN for transaction id {} and transaction type '{}'", .0, .1)]
+    #[error("Unexpected format: amount is negative for transaction id {} and transaction type '{}'", .0, .1)]
     InvalidAmount(u32, String),
+    #[error("Unexpected format: missing timestamp for transaction id {} and transaction type '{}'", .0, .1)]
+    MissingTimestamp(u32, String),
     #[error("Unexpected format: transaction id {} is not unique", .0)]
     UniqueTransactionId(u32),
+    #[error("Unexpected format: client id {} is not unique", .0)]
+    UniqueClientId(u16),
+    #[error("Unexpected format: total amount doesn't equal available plus held amount for client id {}", .0)]
+    UnbalancedAccount(u16),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum AmountError {
+    #[error("Unexpected format: invalid amount '{}'", .0)]
+    InvalidFormat(String),
+    #[error("Unexpected format: amount '{}' is out of range", .0)]
+    OutOfRange(String),
 }
 
 #[derive(Error, Debug, PartialEq)]
@@ -25,6 +135,8 @@ pub enum DepositError {
     FrozenAccount(u16),
     #[error("Can't deposit transaction: invalid transaction type for transaction id {}", .0)]
     InvalidTransactionType(u32),
+    #[error("Can't deposit transaction: amount overflow for client id {}", .0)]
+    AmountOverflow(u16),
 }
 
 #[derive(Error, Debug, PartialEq)]
@@ -37,28 +149,129 @@ pub enum WithdrawalError {
     FrozenAccount(u16),
     #[error("Can't withdraw transaction: invalid transaction type for transaction id {}", .0)]
     InvalidTransactionType(u32),
+    #[error("Can't withdraw transaction: amount overflow for client id {}", .0)]
+    AmountOverflow(u16),
 }
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, PartialEq)]
 pub enum DisputeError {
     #[error("Can't dispute transaction: invalid client id")]
     InvalidClientId,
     #[error("Can't dispute transaction: invalid event type for transaction id {}", .0)]
     InvalidEventType(u32),
+    #[error("Can't dispute transaction: amount overflow for client id {}", .0)]
+    AmountOverflow(u16),
 }
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, PartialEq)]
 pub enum ResolveError {
     #[error("Can't resolve transaction: invalid client id")]
     InvalidClientId,
     #[error("Can't resolve transaction: invalid event type for transaction id {}", .0)]
     InvalidEventType(u32),
+    #[error("Can't resolve transaction: amount overflow for client id {}", .0)]
+    AmountOverflow(u16),
 }
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, PartialEq)]
 pub enum ChargebackError {
     #[error("Can't chargeback transaction: invalid client id")]
     InvalidClientId,
     #[error("Can't chargeback transaction: invalid event type for transaction id {}", .0)]
     InvalidEventType(u32),
+    #[error("Can't chargeback transaction: amount overflow for client id {}", .0)]
+    AmountOverflow(u16),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum OrderError {
+    #[error(
+        "Can't book {}: timestamp {} of transaction id {} is earlier than the last booked timestamp {}",
+        .0, rfc3339(.2), .1, rfc3339(.3)
+    )]
+    LateArrival(String, u32, Timestamp, Timestamp),
+}
+
+// Timestamps are written in the format of the input
+fn rfc3339(timestamp: &Timestamp) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ReferenceError {
+    #[error("Unexpected reference: {} references unknown transaction id {}", .0, .1)]
+    UnknownTransaction(String, u32),
+    #[error("Unexpected reference: {} of client id {} references transaction id {} of client id {}", .0, .1, .2, .3)]
+    ClientMismatch(String, u16, u32, u16),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SnapshotError {
+    #[error("Can't restore snapshot: unsupported version {} (expected version {})", .0, .1)]
+    UnsupportedVersion(u32, u32),
+    #[error("Can't restore snapshot: client id {} is not unique", .0)]
+    DuplicateClientId(u16),
+    #[error("Can't restore snapshot: transaction id {} is not unique", .0)]
+    DuplicateTransactionId(u32),
+    #[error("Can't restore snapshot: transaction id {} references unknown client id {}", .0, .1)]
+    UnknownClientId(u32, u16),
+    #[error("Can't resume from checkpoint: missing position of the next input record")]
+    MissingPosition,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum WalError {
+    #[error("Can't recover from write-ahead log: invalid entry in line {}", .0)]
+    InvalidEntry(u64),
+    #[error("Can't recover from write-ahead log: expected record {} but found record {}", .0, .1)]
+    UnexpectedRecord(u64, u64),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engine_error_position() {
+        let position = Position::new(3);
+        let err = EngineError::from(FormatError::UniqueTransactionId(1)).at(&position);
+
+        assert_eq!(err.position(), Some(&position));
+        assert!(matches!(
+            err.root(),
+            EngineError::Format(FormatError::UniqueTransactionId(1))
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Unexpected format: transaction id 1 is not unique (line 3, record 0, byte 0)"
+        );
+    }
+
+    #[test]
+    fn test_engine_error_category() {
+        let err = EngineError::from(FormatError::UniqueTransactionId(1));
+        assert_eq!(err.category(), ErrorCategory::Format);
+
+        let err = EngineError::from(DisputeError::InvalidClientId).at(&Position::new(3));
+        assert_eq!(err.category(), ErrorCategory::Booking);
+
+        let err = EngineError::from(io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(err.category(), ErrorCategory::Io);
+    }
+
+    #[test]
+    fn test_late_arrival() {
+        let err = EngineError::from(OrderError::LateArrival(
+            String::from("deposit"),
+            4,
+            "2024-05-01T11:59:00Z".parse().unwrap(),
+            "2024-05-01T12:00:00.5Z".parse().unwrap(),
+        ));
+
+        assert_eq!(err.category(), ErrorCategory::Booking);
+        assert_eq!(
+            err.to_string(),
+            "Can't book deposit: timestamp 2024-05-01T11:59:00Z of transaction id 4 is earlier than the last booked timestamp 2024-05-01T12:00:00.500Z"
+        );
+    }
 }
@@ -0,0 +1,195 @@
+use crate::{csv, errors::EngineError, json, models::RawTransaction};
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+    sync::Arc,
+};
+
+// Path of the input file which refers to stdin
+pub const STDIN: &str = "-";
+
+// Format of the input file; all formats are validated identically via CheckedTransaction
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum InputFormat {
+    #[default]
+    Csv,
+    // One JSON object per transaction and line with the same fields as the csv format
+    JsonLines,
+}
+
+impl InputFormat {
+    // Detect the format by the file extension; stdin and unknown extensions default to csv
+    pub fn detect(path: &str) -> Self {
+        match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("jsonl") | Some("ndjson") => InputFormat::JsonLines,
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
+// Source of raw transactions, e.g. a file, stdin or a message consumer; every iterator over raw transactions is a source
+pub trait TransactionSource: Iterator<Item = Result<RawTransaction, EngineError>> {}
+
+impl<I> TransactionSource for I where I: Iterator<Item = Result<RawTransaction, EngineError>> {}
+
+// Raw transactions which are deserialized one by one while iterating
+pub type RawTransactions = Box<dyn TransactionSource + Send>;
+
+// Transaction source for raw transactions which are already in memory, e.g. in tests
+#[derive(Debug, Default)]
+pub struct MemorySource {
+    raw_transactions: std::vec::IntoIter<RawTransaction>,
+}
+
+impl MemorySource {
+    pub fn new(raw_transactions: Vec<RawTransaction>) -> Self {
+        Self {
+            raw_transactions: raw_transactions.into_iter(),
+        }
+    }
+}
+
+impl Iterator for MemorySource {
+    type Item = Result<RawTransaction, EngineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.raw_transactions.next().map(Ok)
+    }
+}
+
+// Read lazily from file or stdin; the path '-' refers to stdin
+pub fn read_stream(path: &str, format: InputFormat) -> Result<RawTransactions, EngineError> {
+    let raw_transactions: RawTransactions = match format {
+        InputFormat::Csv => Box::new(csv::read_stream(path)?),
+        InputFormat::JsonLines => Box::new(json::read_stream(path)?),
+    };
+
+    Ok(raw_transactions)
+}
+
+pub(crate) type Reader = Box<dyn Read + Send>;
+
+// Open the input file or stdin; the file path is kept for the position of the records
+pub(crate) fn open_reader(
+    path: &str,
+    file_type: &str,
+) -> Result<(Reader, Option<Arc<str>>), EngineError> {
+    if path == STDIN {
+        return Ok((Box::new(io::stdin()), None));
+    }
+
+    let file = File::open(path).map_err(|source| EngineError::File {
+        message: format!("Can't find {} file at path '{}'", file_type, path),
+        source,
+    })?;
+
+    Ok((Box::new(file), Some(Arc::from(path))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(path: &str, format: InputFormat) -> Result<Vec<RawTransaction>, EngineError> {
+        read_stream(path, format)?.collect()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(InputFormat::detect("transactions.csv"), InputFormat::Csv);
+        assert_eq!(
+            InputFormat::detect("archive/transactions.jsonl"),
+            InputFormat::JsonLines
+        );
+        assert_eq!(
+            InputFormat::detect("transactions.ndjson"),
+            InputFormat::JsonLines
+        );
+        assert_eq!(InputFormat::detect(STDIN), InputFormat::Csv);
+    }
+
+    #[test]
+    fn test_memory_source() {
+        let source = MemorySource::new(vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some("1.0".parse().unwrap())),
+            RawTransaction::new(String::from("dispute"), 1, 1, None),
+        ]);
+
+        let raw_transactions: Vec<RawTransaction> = source.map(Result::unwrap).collect();
+        assert_eq!(raw_transactions.len(), 2);
+        assert_eq!(raw_transactions[1].r#type, "dispute");
+    }
+
+    #[test]
+    fn test_read_identical() {
+        let csv_transactions =
+            read("test_data/transactions_with_events.csv", InputFormat::Csv).unwrap();
+        let json_transactions = read(
+            "test_data/transactions_with_events.jsonl",
+            InputFormat::JsonLines,
+        )
+        .unwrap();
+
+        assert_eq!(csv_transactions.len(), json_transactions.len());
+
+        for (csv_transaction, json_transaction) in csv_transactions.iter().zip(&json_transactions) {
+            assert_eq!(csv_transaction.r#type, json_transaction.r#type);
+            assert_eq!(csv_transaction.client, json_transaction.client);
+            assert_eq!(csv_transaction.tx, json_transaction.tx);
+            assert_eq!(csv_transaction.amount, json_transaction.amount);
+        }
+    }
+
+    #[test]
+    fn test_read_identical_errors() {
+        let csv_err = read(
+            "test_data/transactions_deserialize_error.csv",
+            InputFormat::Csv,
+        )
+        .unwrap_err();
+        let json_err = read(
+            "test_data/transactions_deserialize_error.jsonl",
+            InputFormat::JsonLines,
+        )
+        .unwrap_err();
+
+        assert_eq!(csv_err.root().to_string(), json_err.root().to_string());
+    }
+
+    #[test]
+    fn test_read_identical_error_records() {
+        let csv_data = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,abc\n";
+        let json_data = concat!(
+            "{\"type\": \"deposit\", \"client\": 1, \"tx\": 1, \"amount\": \"1.0\"}\n",
+            "{\"type\": \"deposit\", \"client\": 1, \"tx\": 2, \"amount\": \"abc\"}\n",
+        );
+
+        let csv_err = csv::CsvSource::from_reader(csv_data.as_bytes())
+            .unwrap()
+            .nth(1)
+            .unwrap()
+            .unwrap_err();
+        let json_err = json::JsonLinesSource::from_reader(json_data.as_bytes())
+            .nth(1)
+            .unwrap()
+            .unwrap_err();
+
+        // Same message and record number; line and byte differ by the csv header
+        assert_eq!(
+            csv_err.to_string(),
+            "Unexpected format: invalid amount 'abc' (line 3, record 2, byte 38)"
+        );
+        assert_eq!(
+            json_err.to_string(),
+            "Unexpected format: invalid amount 'abc' (line 2, record 2, byte 59)"
+        );
+        assert_eq!(
+            csv_err.position().unwrap().record,
+            json_err.position().unwrap().record
+        );
+    }
+}
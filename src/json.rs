@@ -0,0 +1,509 @@
+use crate::{
+    errors::EngineError,
+    input,
+    models::{Position, RawAccount, RawTransaction, Rejection},
+    output::AccountSink,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    io::{self, BufRead, BufReader, BufWriter, Read, Stdout, Write},
+    sync::Arc,
+};
+
+// Read lazily from file in JSON Lines format; raw transactions are deserialized one by one while iterating
+pub fn read_stream(json_file: &str) -> Result<JsonLinesSource, EngineError> {
+    JsonLinesSource::open(json_file)
+}
+
+// Transaction source reading JSON Lines from a file, stdin or any other reader
+pub struct JsonLinesSource {
+    reader: BufReader<input::Reader>,
+    file: Option<Arc<str>>,
+    buffer: String,
+    // Position after the last line read
+    line: u64,
+    record: u64,
+    byte: u64,
+    // Stop reading after io errors
+    is_done: bool,
+}
+
+impl JsonLinesSource {
+    // Open the JSON Lines file; the path '-' refers to stdin
+    pub fn open(json_file: &str) -> Result<Self, EngineError> {
+        let (reader, file) = input::open_reader(json_file, "json")?;
+        Ok(Self::with_file(reader, file))
+    }
+
+    pub fn stdin() -> Result<Self, EngineError> {
+        Self::open(input::STDIN)
+    }
+
+    pub fn from_reader(reader: impl Read + Send + 'static) -> Self {
+        Self::with_file(Box::new(reader), None)
+    }
+
+    fn with_file(reader: input::Reader, file: Option<Arc<str>>) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            file,
+            buffer: String::new(),
+            line: 0,
+            record: 0,
+            byte: 0,
+            is_done: false,
+        }
+    }
+}
+
+impl Iterator for JsonLinesSource {
+    type Item = Result<RawTransaction, EngineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.is_done {
+            self.buffer.clear();
+
+            match self.reader.read_line(&mut self.buffer) {
+                Ok(0) => self.is_done = true,
+                Ok(length) => {
+                    let byte = self.byte;
+
+                    self.line += 1;
+                    self.byte += length as u64;
+
+                    // Ignore empty lines, e.g. at the end of the file
+                    if self.buffer.trim().is_empty() {
+                        continue;
+                    }
+
+                    // Records are counted from 1 like the data records of the csv format, where the header is record 0
+                    self.record += 1;
+
+                    let position = Position {
+                        file: self.file.clone(),
+                        input: 0,
+                        line: self.line,
+                        record: self.record,
+                        byte,
+                    };
+
+                    return Some(
+                        deserialize(&self.buffer, position.clone())
+                            .map_err(|err| err.at(&position)),
+                    );
+                }
+                Err(err) => {
+                    self.is_done = true;
+                    return Some(Err(err.into()));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+fn deserialize(line: &str, position: Position) -> Result<RawTransaction, EngineError> {
+    let mut value: Value = serde_json::from_str(line)?;
+
+    // Amounts may be given as JSON numbers; their literal text is parsed like the decimal strings of the csv format, without a detour via f64
+    if let Some(amount) = value.get_mut("amount") {
+        if let Value::Number(number) = amount {
+            *amount = Value::String(number.to_string());
+        }
+    }
+
+    // Deserialize from the parsed value so that error messages don't depend on the position within the line
+    let mut raw_transaction: RawTransaction = serde_json::from_value(value)?;
+    raw_transaction.position = position;
+
+    Ok(raw_transaction)
+}
+
+// Write raw accounts as JSON array
+pub fn write(writer: impl Write, raw_accounts: Vec<RawAccount>) -> Result<(), EngineError> {
+    write_array(writer, &raw_accounts)
+}
+
+fn write_array<T: Serialize>(writer: impl Write, records: &[T]) -> Result<(), EngineError> {
+    let mut writer = BufWriter::new(writer);
+
+    serde_json::to_writer_pretty(&mut writer, records)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+// Write raw accounts in JSON Lines format
+pub fn write_lines(writer: impl Write, raw_accounts: Vec<RawAccount>) -> Result<(), EngineError> {
+    write_records(writer, raw_accounts)
+}
+
+// Write in JSON Lines format, i.e. one JSON object per line
+fn write_records<T: Serialize>(
+    writer: impl Write,
+    records: impl IntoIterator<Item = T>,
+) -> Result<(), EngineError> {
+    let mut writer = BufWriter::new(writer);
+
+    for record in records {
+        serde_json::to_writer(&mut writer, &record)?;
+        writer.write_all(b"\n")?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+// Account sink writing a JSON array to stdout, a file or any other writer
+// Rejections are written as a separate JSON array to their own writer, so that the output stays a single JSON document
+pub struct JsonSink<W, R = io::Sink> {
+    writer: W,
+    rejections: Option<R>,
+}
+
+impl<W: Write> JsonSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            rejections: None,
+        }
+    }
+
+    // Write the rejections to the given writer, e.g. a rejection report file; rejections are ignored otherwise
+    pub fn with_rejections<R: Write>(self, rejections: R) -> JsonSink<W, R> {
+        JsonSink {
+            writer: self.writer,
+            rejections: Some(rejections),
+        }
+    }
+}
+
+impl<W, R> JsonSink<W, R> {
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl JsonSink<Stdout> {
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+}
+
+impl<W: Write, R: Write> AccountSink for JsonSink<W, R> {
+    fn write_accounts(&mut self, raw_accounts: Vec<RawAccount>) -> Result<(), EngineError> {
+        write(&mut self.writer, raw_accounts)
+    }
+
+    fn write_rejections(&mut self, rejections: &[Rejection]) -> Result<(), EngineError> {
+        match &mut self.rejections {
+            Some(writer) => write_array(writer, rejections),
+            None => Ok(()),
+        }
+    }
+}
+
+// Account sink writing JSON Lines to stdout, a file or any other writer
+pub struct JsonLinesSink<W, R = io::Sink> {
+    writer: W,
+    rejections: Option<R>,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            rejections: None,
+        }
+    }
+
+    pub fn with_rejections<R: Write>(self, rejections: R) -> JsonLinesSink<W, R> {
+        JsonLinesSink {
+            writer: self.writer,
+            rejections: Some(rejections),
+        }
+    }
+}
+
+impl<W, R> JsonLinesSink<W, R> {
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl JsonLinesSink<Stdout> {
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+}
+
+impl<W: Write, R: Write> AccountSink for JsonLinesSink<W, R> {
+    fn write_accounts(&mut self, raw_accounts: Vec<RawAccount>) -> Result<(), EngineError> {
+        write_lines(&mut self.writer, raw_accounts)
+    }
+
+    fn write_rejections(&mut self, rejections: &[Rejection]) -> Result<(), EngineError> {
+        match &mut self.rejections {
+            Some(writer) => write_records(writer, rejections),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        csv::CsvSource,
+        models::{Amount, RejectionReason},
+        payment_engine::{self, ErrorPolicy, EventOrder},
+    };
+
+    #[test]
+    fn test_read_stream() {
+        let res = read_stream("test_data/transactions_with_events.jsonl");
+        assert!(res.is_ok());
+
+        let raw_transactions: Vec<RawTransaction> = res.unwrap().map(Result::unwrap).collect();
+        assert_eq!(raw_transactions.len(), 7);
+        assert_eq!(raw_transactions[0].amount, Some("1.0".parse().unwrap()));
+        assert_eq!(raw_transactions[4].r#type, "dispute");
+        assert_eq!(raw_transactions[4].amount, None);
+
+        let position = &raw_transactions[1].position;
+        assert_eq!(
+            position.file.as_deref(),
+            Some("test_data/transactions_with_events.jsonl")
+        );
+        assert_eq!(position.line, 2);
+        assert_eq!(position.record, 2);
+        assert!(position.byte > 0);
+    }
+
+    #[test]
+    fn test_read_stream_deserialize_error() {
+        let res = read_stream("test_data/transactions_deserialize_error.jsonl");
+        assert!(res.is_ok());
+
+        let err = res.unwrap().next().unwrap().unwrap_err();
+        assert!(matches!(err.root(), EngineError::Json(_)));
+        assert_eq!(
+            err.to_string(),
+            "Unexpected format: invalid amount 'abc' (file 'test_data/transactions_deserialize_error.jsonl', line 1, record 1, byte 0)"
+        );
+    }
+
+    #[test]
+    fn test_json_lines_source_from_reader() {
+        let data = "{\"type\": \"deposit\", \"client\": 1, \"tx\": 1, \"amount\": 1.5}\n\n{\"type\": \"dispute\", \"client\": 1, \"tx\": 1}\n";
+
+        let raw_transactions: Vec<RawTransaction> = JsonLinesSource::from_reader(data.as_bytes())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(raw_transactions.len(), 2);
+        assert_eq!(raw_transactions[0].amount, Some("1.5".parse().unwrap()));
+        assert_eq!(raw_transactions[1].position.line, 3);
+        assert_eq!(raw_transactions[1].position.record, 2);
+    }
+
+    #[test]
+    fn test_json_lines_source_number_amount() {
+        let data = concat!(
+            "{\"type\": \"deposit\", \"client\": 1, \"tx\": 1, \"amount\": 0.00001}\n",
+            "{\"type\": \"deposit\", \"client\": 1, \"tx\": 2, \"amount\": 922337203685477.5807}\n",
+            "{\"type\": \"deposit\", \"client\": 1, \"tx\": 3, \"amount\": 123456789012.3456}\n",
+        );
+
+        let raw_transactions: Vec<RawTransaction> = JsonLinesSource::from_reader(data.as_bytes())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(raw_transactions[0].amount, Some("0.00001".parse().unwrap()));
+        assert_eq!(
+            raw_transactions[1].amount,
+            Some("922337203685477.5807".parse().unwrap())
+        );
+        assert_eq!(
+            raw_transactions[2].amount,
+            Some("123456789012.3456".parse().unwrap())
+        );
+
+        // Out of range like the same decimal string
+        let data =
+            "{\"type\": \"deposit\", \"client\": 1, \"tx\": 1, \"amount\": 922337203685477.5808}\n";
+        let err = JsonLinesSource::from_reader(data.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            err.root().to_string(),
+            "Unexpected format: amount '922337203685477.5808' is out of range"
+        );
+    }
+
+    #[test]
+    fn test_json_lines_source_timestamp() {
+        let data = "{\"type\": \"deposit\", \"client\": 1, \"tx\": 1, \"amount\": 1.5, \"timestamp\": \"2024-05-01T14:00:00+02:00\"}\n";
+
+        let raw_transactions: Vec<RawTransaction> = JsonLinesSource::from_reader(data.as_bytes())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            raw_transactions[0].timestamp,
+            Some("2024-05-01T12:00:00Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_json_sink_run() {
+        let source = CsvSource::open("test_data/transactions_insufficient_funds.csv").unwrap();
+        let mut report = vec![];
+        let mut sink = JsonSink::new(vec![]).with_rejections(&mut report);
+
+        let res = payment_engine::run(source, &mut sink, EventOrder::FileOrder, ErrorPolicy::Skip);
+        assert!(res.is_ok());
+
+        // Accounts and rejections are each a single JSON document
+        let accounts: Value = serde_json::from_slice(&sink.into_inner()).unwrap();
+        assert_eq!(accounts.as_array().unwrap().len(), 2);
+
+        let rejections: Value = serde_json::from_slice(&report).unwrap();
+        assert_eq!(rejections[0]["reason"], "insufficient_funds");
+
+        // Without a rejection writer, the rejections are not written at all
+        let source = CsvSource::open("test_data/transactions_insufficient_funds.csv").unwrap();
+        let mut sink = JsonSink::new(vec![]);
+
+        payment_engine::run(source, &mut sink, EventOrder::FileOrder, ErrorPolicy::Skip).unwrap();
+        let accounts: Value = serde_json::from_slice(&sink.into_inner()).unwrap();
+        assert_eq!(accounts.as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_json_lines_sink() {
+        let mut report = vec![];
+        let mut sink = JsonLinesSink::new(vec![]).with_rejections(&mut report);
+
+        let rejections = [Rejection {
+            file: None,
+            input: 0,
+            line: 5,
+            r#type: String::from("withdrawal"),
+            client: 1,
+            tx: 4,
+            reason: RejectionReason::InsufficientFunds,
+        }];
+
+        let res = sink.write_rejections(&rejections);
+        assert!(res.is_ok());
+        assert!(sink.into_inner().is_empty());
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "{\"file\":null,\"line\":5,\"type\":\"withdrawal\",\"client\":1,\"tx\":4,\"reason\":\"insufficient_funds\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        let raw_accounts = vec![RawAccount::new(
+            1,
+            "1.5".parse().unwrap(),
+            Amount::ZERO,
+            "1.5".parse().unwrap(),
+            true,
+        )];
+        let mut output = vec![];
+
+        let res = write(&mut output, raw_accounts);
+        assert!(res.is_ok());
+
+        let content = String::from_utf8(output).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!([{
+                "client": 1,
+                "available": "1.5000",
+                "held": "0.0000",
+                "total": "1.5000",
+                "locked": true
+            }])
+        );
+    }
+
+    #[test]
+    fn test_write_lines() {
+        let raw_accounts = vec![
+            RawAccount::new(
+                1,
+                "1.5".parse().unwrap(),
+                Amount::ZERO,
+                "1.5".parse().unwrap(),
+                false,
+            ),
+            RawAccount::new(
+                2,
+                Amount::ZERO,
+                "2".parse().unwrap(),
+                "2".parse().unwrap(),
+                true,
+            ),
+        ];
+        let mut output = vec![];
+
+        let res = write_lines(&mut output, raw_accounts);
+        assert!(res.is_ok());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                r#"{"client":1,"available":"1.5000","held":"0.0000","total":"1.5000","locked":false}"#,
+                "\n",
+                r#"{"client":2,"available":"0.0000","held":"2.0000","total":"2.0000","locked":true}"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_json_lines_sink_rejections() {
+        let mut report = vec![];
+        let mut sink = JsonLinesSink::new(vec![]).with_rejections(&mut report);
+
+        let rejections = vec![
+            Rejection {
+                file: Some(String::from("transactions.jsonl")),
+                input: 0,
+                line: 5,
+                r#type: String::from("withdrawal"),
+                client: 1,
+                tx: 4,
+                reason: RejectionReason::InsufficientFunds,
+            },
+            Rejection {
+                file: Some(String::from("transactions.jsonl")),
+                input: 0,
+                line: 6,
+                r#type: String::from("dispute"),
+                client: 2,
+                tx: 9,
+                reason: RejectionReason::UnknownTransaction,
+            },
+        ];
+
+        let res = sink.write_rejections(&rejections);
+        assert!(res.is_ok());
+        drop(sink);
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            concat!(
+                r#"{"file":"transactions.jsonl","line":5,"type":"withdrawal","client":1,"tx":4,"reason":"insufficient_funds"}"#,
+                "\n",
+                r#"{"file":"transactions.jsonl","line":6,"type":"dispute","client":2,"tx":9,"reason":"unknown_transaction"}"#,
+                "\n"
+            )
+        );
+    }
+}
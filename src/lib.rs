@@ -0,0 +1,10 @@
+pub mod csv;
+pub mod errors;
+pub mod input;
+pub mod json;
+pub mod models;
+pub mod output;
+pub mod payment_engine;
+pub mod validation;
+
+pub use payment_engine::Engine;
@@ -1,118 +1,1308 @@
-mod csv;
-mod errors;
-mod models;
-mod payment_engine;
-
 use anyhow::anyhow;
-use std::env;
+use chrono::TimeDelta;
+use clap::Parser;
+use cli::{Cli, Command, Format, OnError, ProcessArgs, ValidateArgs};
+use payeng::{
+    csv::{self, CsvSink, CsvSource},
+    errors::{EngineError, ErrorCategory},
+    input::{self, InputFormat, RawTransactions},
+    json::JsonLinesSink,
+    models::{RawAccount, Rejection},
+    output::{self, AccountSink, OutputFormat},
+    payment_engine::{
+        self, Checkpointer, ChronologicalSource, DiskIndex, DisputeWindow, Engine, ErrorPolicy,
+        EventOrder, MergedSource, Snapshot, TransactionStore, Wal,
+    },
+    validation,
+};
+use std::{collections::HashMap, fs::File, io, mem, process};
+
+mod cli;
 
 /*
-    Output is parsed to stdout
+    Output is parsed to stdout unless an output file is given
     Errors are parsed to stderr via anyhow
     If an error occurs processing is aborted; the output file will remain empty
+    Exit codes: 0 on success, 2 on usage errors and distinct codes per error category (see exit_code)
 */
 
-fn main() -> Result<(), anyhow::Error> {
-    // Parse the command line arguments
-    let args: Vec<String> = env::args().collect();
+const EXIT_FAILURE: i32 = 1;
+const EXIT_FORMAT_ERROR: i32 = 3;
+const EXIT_BOOKING_ERROR: i32 = 4;
+const EXIT_IO_ERROR: i32 = 5;
 
-    let csv_file = if args.len() > 1 {
-        // Second argument (index 1) is the path to the input csv file
-        &args[1]
-    } else {
+// Processing options parsed from the command line arguments
+#[derive(Debug, Default)]
+struct Options {
+    // Format of the input files; detected by the file extension if not set
+    input_format: Option<InputFormat>,
+    // Apply transaction events grouped under the referenced transaction instead of in the order of the input file
+    event_order: EventOrder,
+    // Skip transactions which can't be booked instead of aborting processing
+    error_policy: ErrorPolicy,
+    // Path to the report of rejected transactions and ignored transaction events
+    rejections_file: Option<String>,
+    // Path to the output file; stdout if not set
+    output_file: Option<String>,
+    // Output format of the client accounts
+    format: OutputFormat,
+    // Path to the engine snapshot written after processing
+    snapshot_file: Option<String>,
+    // Path to the engine snapshot of a previous run
+    restore_file: Option<String>,
+    // Path to the client accounts of a previous run used as opening balances
+    opening_balances_file: Option<String>,
+    // Directory of the write-ahead log
+    wal_dir: Option<String>,
+    // Path to the checkpoint with the position of the next input record
+    checkpoint_file: Option<String>,
+    // Continue an interrupted run from the checkpoint
+    resume: bool,
+    // Number of records between two checkpoints
+    checkpoint_interval: u64,
+    // Number of worker threads; single-threaded processing if not greater than 1
+    threads: usize,
+    // Read, validate and book records on separate threads
+    pipelined: bool,
+    // Path to the disk-backed index of the processed transactions; kept in memory if not set
+    index_file: Option<String>,
+    // Number of transactions cached in memory by the disk-backed index
+    index_cache: usize,
+    // Period in which a processed transaction can be disputed
+    dispute_window: DisputeWindow,
+    // Tolerance for reordering records by their timestamp; file order if not set
+    time_tolerance: Option<TimeDelta>,
+}
+
+impl From<ProcessArgs> for Options {
+    fn from(args: ProcessArgs) -> Self {
+        Self {
+            input_format: args.input_format.map(InputFormat::from),
+            event_order: if args.group_events {
+                EventOrder::Grouped
+            } else {
+                EventOrder::FileOrder
+            },
+            error_policy: match args.on_error {
+                OnError::Abort => ErrorPolicy::Abort,
+                OnError::Skip => ErrorPolicy::Skip,
+            },
+            rejections_file: args.rejections,
+            output_file: args.output,
+            format: match args.format {
+                Format::Csv => OutputFormat::Csv,
+                Format::Json => OutputFormat::Json,
+                Format::Jsonl => OutputFormat::JsonLines,
+            },
+            snapshot_file: args.snapshot,
+            restore_file: args.restore,
+            opening_balances_file: args.opening_balances,
+            wal_dir: args.wal,
+            checkpoint_file: args.checkpoint,
+            resume: args.resume,
+            checkpoint_interval: args.checkpoint_interval,
+            threads: usize::from(args.threads),
+            pipelined: args.pipeline,
+            index_file: args.index,
+            index_cache: args.index_cache,
+            dispute_window: match (args.dispute_window, args.dispute_window_seconds) {
+                (Some(records), _) => DisputeWindow::Records(records),
+                (None, Some(seconds)) => {
+                    DisputeWindow::Duration(TimeDelta::seconds(i64::from(seconds)))
+                }
+                (None, None) => DisputeWindow::Unlimited,
+            },
+            time_tolerance: args
+                .order_by_time
+                .then(|| TimeDelta::seconds(i64::from(args.time_tolerance))),
+        }
+    }
+}
+
+impl Options {
+    fn input_format(&self, input_file: &str) -> InputFormat {
+        self.input_format
+            .unwrap_or_else(|| InputFormat::detect(input_file))
+    }
+}
+
+impl From<cli::InputFormat> for InputFormat {
+    fn from(format: cli::InputFormat) -> Self {
+        match format {
+            cli::InputFormat::Csv => InputFormat::Csv,
+            cli::InputFormat::Jsonl => InputFormat::JsonLines,
+        }
+    }
+}
+
+fn main() {
+    // Parse the command line arguments; clap exits with code 2 on usage errors
+    let cli = Cli::parse();
+
+    let res = match cli.command {
+        Command::Process(args) => input_files(&args.input)
+            .and_then(|input_files| wrapper(&input_files, &Options::from(*args))),
+        Command::Validate(ValidateArgs {
+            input,
+            input_format: format,
+        }) => validate(&input, input_format(&input, format)),
+    };
+
+    if let Err(err) = res {
+        eprintln!("Error: {:?}", err);
+        process::exit(exit_code(&err));
+    }
+}
+
+// Input format selected by flag or detected by the file extension
+fn input_format(input: &str, format: Option<cli::InputFormat>) -> InputFormat {
+    format
+        .map(InputFormat::from)
+        .unwrap_or_else(|| InputFormat::detect(input))
+}
+
+// Expand glob patterns to the matching files in alphabetical order; plain paths are kept, so that missing files are reported when opened
+fn input_files(inputs: &[String]) -> Result<Vec<String>, anyhow::Error> {
+    let mut input_files = vec![];
+
+    for input in inputs {
+        if !input.contains(['*', '?', '[']) {
+            input_files.push(input.clone());
+            continue;
+        }
+
+        let paths = glob::glob(input)
+            .map_err(|err| anyhow!("Invalid glob pattern '{}': {}", input, err))?
+            .map(|path| path.map(|path| path.to_string_lossy().into_owned()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if paths.is_empty() {
+            return Err(anyhow!("No input file matches pattern '{}'", input));
+        }
+
+        input_files.extend(paths);
+    }
+
+    if input_files.len() > 1
+        && input_files
+            .iter()
+            .any(|input_file| input_file == input::STDIN)
+    {
         return Err(anyhow!(
-            "Missing input file: please specify the path as argument"
+            "Reading from stdin is only supported for a single input"
         ));
-    };
+    }
 
-    wrapper(csv_file)
+    Ok(input_files)
 }
 
-// Thin wrapper for testing
-fn wrapper(csv_file: &str) -> Result<(), anyhow::Error> {
-    // Read raw transactions from csv file
-    let raw_transactions = csv::read(csv_file)?;
+// Read the input files as a single stream; merged by timestamp in chronological order, otherwise one after the other
+fn read_inputs(input_files: &[&str], options: &Options) -> Result<RawTransactions, EngineError> {
+    let sources = input_files
+        .iter()
+        .enumerate()
+        .map(|(index, input_file)| {
+            let source = input::read_stream(input_file, options.input_format(input_file))?;
 
-    // Prepare transactions for processing and convert raw transactions to business objects
-    let (transaction_history, mut transactions) = payment_engine::preprocess(raw_transactions)?;
+            // Tag the records with the index of the input file, e.g. to order the rejection report
+            let source: RawTransactions = Box::new(source.map(move |raw_transaction| {
+                raw_transaction.map(|mut raw_transaction| {
+                    raw_transaction.position = raw_transaction.position.with_input(index);
+                    raw_transaction
+                })
+            }));
 
-    // Process all transactions
-    let accounts = payment_engine::process_transactions(&transaction_history, &mut transactions)?;
+            Ok(source)
+        })
+        .collect::<Result<Vec<_>, EngineError>>()?;
 
-    // Convert business objects from Account to RawAccount
-    let raw_accounts = payment_engine::postprocess(accounts)?;
+    Ok(match options.time_tolerance {
+        // Hold back records within the tolerance to book them in the order of their timestamps
+        Some(tolerance) => Box::new(ChronologicalSource::new(
+            MergedSource::new(sources),
+            tolerance,
+        )),
+        None => Box::new(sources.into_iter().flatten()),
+    })
+}
+
+// Map the category of engine errors to distinct exit codes so that callers can tell them apart
+fn exit_code(err: &anyhow::Error) -> i32 {
+    match err.downcast_ref::<EngineError>().map(EngineError::category) {
+        Some(ErrorCategory::Format) => EXIT_FORMAT_ERROR,
+        Some(ErrorCategory::Booking) => EXIT_BOOKING_ERROR,
+        Some(ErrorCategory::Io) => EXIT_IO_ERROR,
+        None => EXIT_FAILURE,
+    }
+}
 
-    // Write raw accounts to stdout in csv format
-    csv::write(raw_accounts)?;
+// Check all records of the input file without booking any transactions and print the validation report
+fn validate(input_file: &str, input_format: InputFormat) -> Result<(), anyhow::Error> {
+    let report = validation::validate(input::read_stream(input_file, input_format)?)?;
+    println!("{}", report);
+
+    if !report.is_valid() {
+        return Err(anyhow!(
+            "Validation failed: {} of {} records in '{}' are invalid",
+            report.issues.len(),
+            report.records,
+            input_file
+        ));
+    }
 
     Ok(())
 }
 
+// Thin wrapper for testing; the input files are processed as a single stream
+fn wrapper(input_files: &[impl AsRef<str>], options: &Options) -> Result<(), anyhow::Error> {
+    let input_files: Vec<&str> = input_files.iter().map(AsRef::as_ref).collect();
+    let mut sink = OutputSink { options };
+
+    // Seed the client accounts with the opening balances if given
+    let accounts = match &options.opening_balances_file {
+        Some(opening_balances_file) => {
+            payment_engine::open_accounts(csv::read_accounts(opening_balances_file)?)?
+        }
+        None => HashMap::new(),
+    };
+
+    if options.event_order == EventOrder::Grouped {
+        let source = read_inputs(&input_files, options)?;
+        let mut rejections = vec![];
+        let accounts =
+            payment_engine::process_batch(source, accounts, options.error_policy, &mut rejections)?;
+        payment_engine::write_results(&mut sink, accounts, rejections)?;
+
+        return Ok(());
+    }
+
+    // Continue with the state of a previous run if a snapshot is given
+    let mut engine = match &options.restore_file {
+        Some(restore_file) => Engine::restore(Snapshot::load(restore_file)?, options.error_policy)?,
+        None => Engine::with_accounts(accounts, options.error_policy),
+    };
+
+    // Keep the processed transactions on disk for inputs larger than memory
+    if let Some(index_file) = &options.index_file {
+        let index = DiskIndex::create(index_file, options.index_cache)?;
+        engine = engine.with_store(TransactionStore::on_disk(index))?;
+    }
+
+    engine = engine.with_dispute_window(options.dispute_window)?;
+
+    if options.time_tolerance.is_some() {
+        engine = engine.with_chronological_order();
+    }
+
+    let recovery = apply(&mut engine, &input_files, options)?;
+
+    if let Some(snapshot_file) = &options.snapshot_file {
+        engine.snapshot()?.save(snapshot_file)?;
+    }
+
+    let (accounts, rejections) = engine.finish();
+    payment_engine::write_results(&mut sink, accounts, rejections)?;
+
+    // Write-ahead log and checkpoint are only removed after the output is written
+    match recovery {
+        Some(Recovery::Wal(wal)) => wal.close()?,
+        Some(Recovery::Checkpoint(checkpointer)) => checkpointer.close()?,
+        None => {}
+    }
+
+    Ok(())
+}
+
+// Crash recovery of the current run
+enum Recovery {
+    Wal(Wal),
+    Checkpoint(Checkpointer),
+}
+
+// Apply all records of the input files to the engine; an interrupted run is recovered from the write-ahead log or resumed from the checkpoint
+fn apply(
+    engine: &mut Engine,
+    input_files: &[&str],
+    options: &Options,
+) -> Result<Option<Recovery>, anyhow::Error> {
+    if options.wal_dir.is_some() || options.checkpoint_file.is_some() {
+        let [input_file] = input_files else {
+            return Err(anyhow!(
+                "Write-ahead log and checkpoint are only supported for a single input file"
+            ));
+        };
+
+        return recover(engine, input_file, options);
+    }
+
+    let source = read_inputs(input_files, options)?;
+
+    if options.threads > 1 {
+        *engine = payment_engine::process_parallel(mem::take(engine), source, options.threads)?;
+    } else if options.pipelined {
+        *engine = payment_engine::process_pipelined(mem::take(engine), source)?;
+    } else {
+        engine.apply_all(source)?;
+    }
+
+    Ok(None)
+}
+
+// Apply all records of the input file with crash recovery
+fn recover(
+    engine: &mut Engine,
+    input_file: &str,
+    options: &Options,
+) -> Result<Option<Recovery>, anyhow::Error> {
+    if let Some(wal_dir) = &options.wal_dir {
+        // Read raw transactions lazily from the input file
+        let source = input::read_stream(input_file, options.input_format(input_file))?;
+        let mut wal = Wal::open(wal_dir, options.checkpoint_interval, engine)?;
+        wal.apply_all(engine, source)?;
+
+        return Ok(Some(Recovery::Wal(wal)));
+    }
+
+    if let Some(checkpoint_file) = &options.checkpoint_file {
+        let mut checkpointer = Checkpointer::new(checkpoint_file, options.checkpoint_interval);
+
+        let position = if options.resume {
+            if input_file == input::STDIN || options.input_format(input_file) != InputFormat::Csv {
+                return Err(anyhow!("Resuming is only supported for csv input files"));
+            }
+
+            checkpointer.resume(engine)?
+        } else {
+            None
+        };
+
+        // Seek the input file to the next record after the checkpoint
+        let source = match position {
+            Some(position) => Box::new(CsvSource::resume(input_file, &position)?),
+            None => input::read_stream(input_file, options.input_format(input_file))?,
+        };
+        checkpointer.apply_all(engine, source)?;
+
+        return Ok(Some(Recovery::Checkpoint(checkpointer)));
+    }
+
+    Ok(None)
+}
+
+// Writes the client accounts to the output file or stdout and the rejections to the rejection report
+// Files are only created after processing succeeded
+struct OutputSink<'a> {
+    options: &'a Options,
+}
+
+impl AccountSink for OutputSink<'_> {
+    fn write_accounts(&mut self, raw_accounts: Vec<RawAccount>) -> Result<(), EngineError> {
+        let mut sink = match &self.options.output_file {
+            Some(output_file) => output::sink(create(output_file, "output")?, self.options.format),
+            None => output::sink(io::stdout(), self.options.format),
+        };
+
+        sink.write_accounts(raw_accounts)
+    }
+
+    // Write the rejection report in JSON Lines format for '.jsonl' files and in csv format otherwise
+    fn write_rejections(&mut self, rejections: &[Rejection]) -> Result<(), EngineError> {
+        match &self.options.rejections_file {
+            Some(rejections_file) if rejections_file.ends_with(".jsonl") => {
+                JsonLinesSink::new(io::sink())
+                    .with_rejections(create(rejections_file, "json")?)
+                    .write_rejections(rejections)
+            }
+            Some(rejections_file) => CsvSink::new(io::sink())
+                .with_rejections(create(rejections_file, "csv")?)
+                .write_rejections(rejections),
+            None => Ok(()),
+        }
+    }
+}
+
+fn create(path: &str, file_type: &str) -> Result<File, EngineError> {
+    File::create(path).map_err(|source| EngineError::File {
+        message: format!("Can't create {} file at path '{}'", file_type, path),
+        source,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::RawTransaction;
     use ::csv::Writer;
+    use payeng::models::{Amount, RawTransaction};
     use std::fs;
 
+    // Lines of the output file in sorted order; accounts are written in the order of the hash map
+    fn sorted_lines(path: &str) -> Vec<String> {
+        let mut lines: Vec<String> = fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        lines.sort();
+        lines
+    }
+
     #[test]
     fn test_wrapper() {
-        let res = wrapper("test_data/transactions.csv");
+        let res = wrapper(&["test_data/transactions.csv"], &Options::default());
         assert!(res.is_ok());
     }
 
     #[test]
     fn test_wrapper_whitespaces() {
-        let res = wrapper("test_data/transactions_whitespaces.csv");
+        let res = wrapper(
+            &["test_data/transactions_whitespaces.csv"],
+            &Options::default(),
+        );
         assert!(res.is_ok());
     }
 
     #[test]
     fn test_wrapper_with_events() {
-        let res = wrapper("test_data/transactions_with_events.csv");
+        let res = wrapper(
+            &["test_data/transactions_with_events.csv"],
+            &Options::default(),
+        );
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_wrapper_with_events_grouped() {
+        let res = wrapper(
+            &["test_data/transactions_with_events.csv"],
+            &Options {
+                event_order: EventOrder::Grouped,
+                ..Default::default()
+            },
+        );
         assert!(res.is_ok());
     }
 
     #[test]
     fn test_wrapper_invalid_transaction_type() {
-        let res = wrapper("test_data/transactions_invalid_transaction_type.csv");
+        let res = wrapper(
+            &["test_data/transactions_invalid_transaction_type.csv"],
+            &Options::default(),
+        );
         assert!(res.is_err());
 
         let err = res.unwrap_err();
         assert_eq!(
             err.to_string(),
-            "Unexpected format: invalid transaction type 'unknown' in transaction id 5"
+            "Unexpected format: invalid transaction type 'unknown' in transaction id 5 (file 'test_data/transactions_invalid_transaction_type.csv', line 6, record 5, byte 203)"
         );
     }
 
     #[test]
     fn test_wrapper_invalid_transaction_id() {
-        let res = wrapper("test_data/transactions_invalid_transaction_id.csv");
+        let res = wrapper(
+            &["test_data/transactions_invalid_transaction_id.csv"],
+            &Options::default(),
+        );
         assert!(res.is_err());
 
         let err = res.unwrap_err();
         assert_eq!(
             err.to_string(),
-            "Unexpected format: transaction id 1 is not unique"
+            "Unexpected format: transaction id 1 is not unique (file 'test_data/transactions_invalid_transaction_id.csv', line 6, record 5, byte 203)"
         );
     }
 
     #[test]
     fn test_wrapper_invalid_amount() {
-        let res = wrapper("test_data/transactions_invalid_amount.csv");
+        let res = wrapper(
+            &["test_data/transactions_invalid_amount.csv"],
+            &Options::default(),
+        );
         assert!(res.is_err());
 
         let err = res.unwrap_err();
         assert_eq!(
             err.to_string(),
-            "Unexpected format: amount is negative, infinite or NaN for transaction id 1 and transaction type 'deposit'"
+            "Unexpected format: amount is negative for transaction id 1 and transaction type 'deposit' (file 'test_data/transactions_invalid_amount.csv', line 2, record 1, byte 43)"
         );
     }
 
     #[test]
     fn test_wrapper_deserialize_error() {
-        let res = wrapper("test_data/transactions_deserialize_error.csv");
+        let res = wrapper(
+            &["test_data/transactions_deserialize_error.csv"],
+            &Options::default(),
+        );
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unexpected format: invalid amount 'abc' (file 'test_data/transactions_deserialize_error.csv', line 2, record 1, byte 43)"
+        );
+    }
+
+    #[test]
+    fn test_wrapper_json_lines() {
+        let res = wrapper(
+            &["test_data/transactions_with_events.jsonl"],
+            &Options {
+                input_format: Some(InputFormat::JsonLines),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_validate_json_lines() {
+        let res = validate(
+            "test_data/transactions_with_events.jsonl",
+            InputFormat::JsonLines,
+        );
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_wrapper_insufficient_funds() {
+        let res = wrapper(
+            &["test_data/transactions_insufficient_funds.csv"],
+            &Options::default(),
+        );
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Can't withdraw transaction: insufficient funds for client id 1 (file 'test_data/transactions_insufficient_funds.csv', line 4, record 3, byte 123)"
+        );
+    }
+
+    #[test]
+    fn test_wrapper_insufficient_funds_skip_errors() {
+        let res = wrapper(
+            &["test_data/transactions_insufficient_funds.csv"],
+            &Options {
+                error_policy: ErrorPolicy::Skip,
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_wrapper_rejections() {
+        fs::create_dir_all("tmp").unwrap();
+        let rejections_path = "tmp/wrapper_rejections.csv";
+
+        let res = wrapper(
+            &["test_data/transactions_insufficient_funds.csv"],
+            &Options {
+                error_policy: ErrorPolicy::Skip,
+                rejections_file: Some(rejections_path.to_string()),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+
+        let content = fs::read_to_string(rejections_path).unwrap();
+        assert_eq!(
+            content,
+            concat!(
+                "file,line,type,client,tx,reason\n",
+                "test_data/transactions_insufficient_funds.csv,4,withdrawal,1,3,insufficient_funds\n",
+                "test_data/transactions_insufficient_funds.csv,5,dispute,1,3,rejected_transaction\n",
+            )
+        );
+
+        fs::remove_file(rejections_path).unwrap();
+    }
+
+    #[test]
+    fn test_wrapper_dispute_window() {
+        fs::create_dir_all("tmp").unwrap();
+        let rejections_path = "tmp/wrapper_rejections_dispute_window.csv";
+
+        let res = wrapper(
+            &["test_data/transactions_dispute_window.csv"],
+            &Options {
+                rejections_file: Some(rejections_path.to_string()),
+                dispute_window: DisputeWindow::Records(2),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+
+        let content = fs::read_to_string(rejections_path).unwrap();
+        assert_eq!(
+            content,
+            concat!(
+                "file,line,type,client,tx,reason\n",
+                "test_data/transactions_dispute_window.csv,6,dispute,1,1,outside_dispute_window\n",
+            )
+        );
+
+        fs::remove_file(rejections_path).unwrap();
+    }
+
+    #[test]
+    fn test_wrapper_dispute_window_duration() {
+        fs::create_dir_all("tmp").unwrap();
+        let rejections_path = "tmp/wrapper_rejections_dispute_window_duration.csv";
+
+        let res = wrapper(
+            &["test_data/transactions_dispute_window_timestamps.csv"],
+            &Options {
+                rejections_file: Some(rejections_path.to_string()),
+                dispute_window: DisputeWindow::Duration(TimeDelta::seconds(60)),
+                time_tolerance: Some(TimeDelta::zero()),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+
+        let content = fs::read_to_string(rejections_path).unwrap();
+        assert_eq!(
+            content,
+            concat!(
+                "file,line,type,client,tx,reason\n",
+                "test_data/transactions_dispute_window_timestamps.csv,6,dispute,1,1,outside_dispute_window\n",
+            )
+        );
+
+        fs::remove_file(rejections_path).unwrap();
+    }
+
+    #[test]
+    fn test_wrapper_order_by_time() {
+        fs::create_dir_all("tmp").unwrap();
+        let rejections_path = "tmp/wrapper_rejections_order_by_time.csv";
+
+        let res = wrapper(
+            &["test_data/transactions_timestamps.csv"],
+            &Options {
+                error_policy: ErrorPolicy::Skip,
+                rejections_file: Some(rejections_path.to_string()),
+                time_tolerance: Some(TimeDelta::seconds(30)),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+
+        // The earlier dispute holds the funds of the withdrawal; the last deposit arrives beyond the tolerance
+        let content = fs::read_to_string(rejections_path).unwrap();
+        assert_eq!(
+            content,
+            concat!(
+                "file,line,type,client,tx,reason\n",
+                "test_data/transactions_timestamps.csv,4,withdrawal,1,3,insufficient_funds\n",
+                "test_data/transactions_timestamps.csv,7,deposit,2,5,late_arrival\n",
+            )
+        );
+
+        fs::remove_file(rejections_path).unwrap();
+    }
+
+    #[test]
+    fn test_wrapper_rejections_json_lines() {
+        fs::create_dir_all("tmp").unwrap();
+        let rejections_path = "tmp/wrapper_rejections.jsonl";
+
+        let res = wrapper(
+            &["test_data/transactions_insufficient_funds.csv"],
+            &Options {
+                error_policy: ErrorPolicy::Skip,
+                rejections_file: Some(rejections_path.to_string()),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+
+        let content = fs::read_to_string(rejections_path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert_eq!(
+            content.lines().next().unwrap(),
+            r#"{"file":"test_data/transactions_insufficient_funds.csv","line":4,"type":"withdrawal","client":1,"tx":3,"reason":"insufficient_funds"}"#
+        );
+
+        fs::remove_file(rejections_path).unwrap();
+    }
+
+    #[test]
+    fn test_wrapper_output_json() {
+        fs::create_dir_all("tmp").unwrap();
+        let output_path = "tmp/wrapper_accounts.json";
+
+        let res = wrapper(
+            &["test_data/transactions_with_events.csv"],
+            &Options {
+                output_file: Some(output_path.to_string()),
+                format: OutputFormat::Json,
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+
+        let content = fs::read_to_string(output_path).unwrap();
+        let accounts: Vec<serde_json::Value> = serde_json::from_str(&content).unwrap();
+        assert!(!accounts.is_empty());
+
+        fs::remove_file(output_path).unwrap();
+    }
+
+    #[test]
+    fn test_wrapper_no_output_on_error() {
+        fs::create_dir_all("tmp").unwrap();
+        let output_path = "tmp/wrapper_accounts_error.csv";
+
+        let res = wrapper(
+            &["test_data/transactions_insufficient_funds.csv"],
+            &Options {
+                output_file: Some(output_path.to_string()),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_err());
+        assert!(!std::path::Path::new(output_path).exists());
+    }
+
+    #[test]
+    fn test_wrapper_snapshot_restore() {
+        fs::create_dir_all("tmp").unwrap();
+        let snapshot_path = "tmp/wrapper_snapshot.json";
+        let output_path = "tmp/wrapper_accounts_next_day.csv";
+
+        let res = wrapper(
+            &["test_data/transactions.csv"],
+            &Options {
+                snapshot_file: Some(snapshot_path.to_string()),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+
+        // Transaction events of the next day reference transactions of the previous run
+        let res = wrapper(
+            &["test_data/transactions_next_day.csv"],
+            &Options {
+                restore_file: Some(snapshot_path.to_string()),
+                output_file: Some(output_path.to_string()),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+
+        assert_eq!(
+            sorted_lines(output_path),
+            vec![
+                "1,-0.5000,0.0000,-0.5000,true",
+                "2,1.0000,0.0000,1.0000,false",
+                "client,available,held,total,locked",
+            ]
+        );
+
+        fs::remove_file(snapshot_path).unwrap();
+        fs::remove_file(output_path).unwrap();
+    }
+
+    #[test]
+    fn test_wrapper_restore_missing_snapshot() {
+        let res = wrapper(
+            &["test_data/transactions_next_day.csv"],
+            &Options {
+                restore_file: Some(String::from("test_data/missing.json")),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Can't find snapshot file at path 'test_data/missing.json'"
+        );
+        assert_eq!(exit_code(&err), EXIT_IO_ERROR);
+    }
+
+    #[test]
+    fn test_wrapper_opening_balances() {
+        fs::create_dir_all("tmp").unwrap();
+        let output_path = "tmp/wrapper_accounts_opening_balances.csv";
+
+        for event_order in [EventOrder::FileOrder, EventOrder::Grouped] {
+            let res = wrapper(
+                &["test_data/transactions_opening_balances.csv"],
+                &Options {
+                    event_order,
+                    error_policy: ErrorPolicy::Skip,
+                    opening_balances_file: Some(String::from("test_data/accounts.csv")),
+                    output_file: Some(output_path.to_string()),
+                    ..Default::default()
+                },
+            );
+            assert!(res.is_ok());
+
+            assert_eq!(
+                sorted_lines(output_path),
+                vec![
+                    "1,2.5000,0.0000,2.5000,false",
+                    "2,2.0000,1.0000,3.0000,true",
+                    "3,1.0000,0.0000,1.0000,false",
+                    "client,available,held,total,locked",
+                ]
+            );
+        }
+
+        fs::remove_file(output_path).unwrap();
+    }
+
+    #[test]
+    fn test_wrapper_unbalanced_opening_balances() {
+        let res = wrapper(
+            &["test_data/transactions.csv"],
+            &Options {
+                opening_balances_file: Some(String::from("test_data/accounts_unbalanced.csv")),
+                ..Default::default()
+            },
+        );
         assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unexpected format: total amount doesn't equal available plus held amount for client id 2"
+        );
+        assert_eq!(exit_code(&err), EXIT_FORMAT_ERROR);
+    }
+
+    #[test]
+    fn test_wrapper_wal() {
+        fs::create_dir_all("tmp").unwrap();
+        let wal_dir = "tmp/wrapper_wal";
+        let output_path = "tmp/wrapper_accounts_wal.csv";
+        let _ = fs::remove_dir_all(wal_dir);
+
+        let res = wrapper(
+            &["test_data/transactions_with_events.csv"],
+            &Options {
+                wal_dir: Some(wal_dir.to_string()),
+                checkpoint_interval: 2,
+                output_file: Some(output_path.to_string()),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+
+        let content = fs::read_to_string(output_path).unwrap();
+        assert!(content.contains("1,3.0000,0.0000,3.0000,true"));
+
+        // The write-ahead log is removed after a successful run
+        assert_eq!(fs::read_dir(wal_dir).unwrap().count(), 0);
+
+        fs::remove_dir_all(wal_dir).unwrap();
+        fs::remove_file(output_path).unwrap();
+    }
+
+    #[test]
+    fn test_wrapper_resume() {
+        fs::create_dir_all("tmp").unwrap();
+        let checkpoint_path = "tmp/wrapper_checkpoint.json";
+        let output_path = "tmp/wrapper_accounts_resume.csv";
+        let expected_path = "tmp/wrapper_accounts_resume_expected.csv";
+        let options = Options {
+            error_policy: ErrorPolicy::Skip,
+            checkpoint_file: Some(checkpoint_path.to_string()),
+            checkpoint_interval: 2,
+            resume: true,
+            ..Default::default()
+        };
+
+        // Interrupted run which crashed after 7 records
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip);
+        let mut checkpointer = Checkpointer::new(checkpoint_path, 2);
+        checkpointer
+            .apply_all(
+                &mut engine,
+                CsvSource::open("test_data/transactions_resume.csv")
+                    .unwrap()
+                    .take(7),
+            )
+            .unwrap();
+
+        let res = wrapper(
+            &["test_data/transactions_resume.csv"],
+            &Options {
+                output_file: Some(output_path.to_string()),
+                ..options
+            },
+        );
+        assert!(res.is_ok());
+        assert!(!std::path::Path::new(checkpoint_path).exists());
+
+        let res = wrapper(
+            &["test_data/transactions_resume.csv"],
+            &Options {
+                error_policy: ErrorPolicy::Skip,
+                output_file: Some(expected_path.to_string()),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+
+        assert_eq!(sorted_lines(output_path), sorted_lines(expected_path));
+
+        fs::remove_file(output_path).unwrap();
+        fs::remove_file(expected_path).unwrap();
+    }
+
+    #[test]
+    fn test_wrapper_resume_json_lines() {
+        let res = wrapper(
+            &["test_data/transactions_with_events.jsonl"],
+            &Options {
+                input_format: Some(InputFormat::JsonLines),
+                checkpoint_file: Some(String::from("tmp/wrapper_checkpoint_jsonl.json")),
+                resume: true,
+                ..Default::default()
+            },
+        );
+        assert!(res.is_err());
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "Resuming is only supported for csv input files"
+        );
+    }
+
+    #[test]
+    fn test_wrapper_threads() {
+        fs::create_dir_all("tmp").unwrap();
+        let output_path = "tmp/wrapper_accounts_threads.csv";
+        let expected_path = "tmp/wrapper_accounts_threads_expected.csv";
+
+        for (threads, output_path) in [(4, output_path), (1, expected_path)] {
+            let res = wrapper(
+                &["test_data/transactions_resume.csv"],
+                &Options {
+                    threads,
+                    error_policy: ErrorPolicy::Skip,
+                    output_file: Some(output_path.to_string()),
+                    ..Default::default()
+                },
+            );
+            assert!(res.is_ok());
+        }
+
+        assert_eq!(sorted_lines(output_path), sorted_lines(expected_path));
+
+        let err = wrapper(
+            &["test_data/transactions_insufficient_funds.csv"],
+            &Options {
+                threads: 4,
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Can't withdraw transaction: insufficient funds for client id 1 (file 'test_data/transactions_insufficient_funds.csv', line 4, record 3, byte 123)"
+        );
+
+        fs::remove_file(output_path).unwrap();
+        fs::remove_file(expected_path).unwrap();
+    }
+
+    #[test]
+    fn test_options_from_process_args() {
+        let cli = Cli::try_parse_from([
+            "payeng",
+            "process",
+            "-",
+            "--on-error",
+            "skip",
+            "--group-events",
+        ])
+        .unwrap();
+
+        let options = match cli.command {
+            Command::Process(args) => Options::from(*args),
+            _ => panic!("Unexpected subcommand"),
+        };
+        assert_eq!(options.event_order, EventOrder::Grouped);
+        assert_eq!(options.error_policy, ErrorPolicy::Skip);
+        assert_eq!(options.format, OutputFormat::Csv);
+        assert_eq!(options.output_file, None);
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let err = wrapper(
+            &["test_data/transactions_invalid_amount.csv"],
+            &Options::default(),
+        )
+        .unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_FORMAT_ERROR);
+
+        let err = wrapper(
+            &["test_data/transactions_insufficient_funds.csv"],
+            &Options::default(),
+        )
+        .unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_BOOKING_ERROR);
+
+        let err = wrapper(&["test_data/missing.csv"], &Options::default()).unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_IO_ERROR);
+
+        let err = validate("test_data/transactions_validate.csv", InputFormat::Csv).unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_FAILURE);
+    }
+
+    #[test]
+    fn test_validate() {
+        let res = validate("test_data/transactions_with_events.csv", InputFormat::Csv);
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_validate_invalid_records() {
+        let res = validate("test_data/transactions_validate.csv", InputFormat::Csv);
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Validation failed: 7 of 9 records in 'test_data/transactions_validate.csv' are invalid"
+        );
+    }
+
+    #[test]
+    fn test_wrapper_index() {
+        fs::create_dir_all("tmp").unwrap();
+        let output_path = "tmp/wrapper_accounts_index.csv";
+        let expected_path = "tmp/wrapper_accounts_index_expected.csv";
+        let index_path = "tmp/wrapper_index.bin";
+
+        for (index_file, output_path) in [
+            (Some(index_path.to_string()), output_path),
+            (None, expected_path),
+        ] {
+            let res = wrapper(
+                &["test_data/transactions_resume.csv"],
+                &Options {
+                    index_file,
+                    index_cache: 2,
+                    error_policy: ErrorPolicy::Skip,
+                    output_file: Some(output_path.to_string()),
+                    ..Default::default()
+                },
+            );
+            assert!(res.is_ok());
+        }
+
+        assert_eq!(sorted_lines(output_path), sorted_lines(expected_path));
+
+        // The index only lives for a single run
+        assert!(!std::path::Path::new(index_path).exists());
+
+        fs::remove_file(output_path).unwrap();
+        fs::remove_file(expected_path).unwrap();
+    }
+
+    #[test]
+    fn test_wrapper_multiple_inputs() {
+        fs::create_dir_all("tmp").unwrap();
+        let output_path = "tmp/wrapper_accounts_multiple_inputs.csv";
+        let input_files = [
+            "test_data/gateways/transactions_a.csv",
+            "test_data/gateways/transactions_b.csv",
+        ];
+
+        // One file after the other: the withdrawal is booked before the dispute
+        let res = wrapper(
+            &input_files,
+            &Options {
+                output_file: Some(output_path.to_string()),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+        assert_eq!(
+            sorted_lines(output_path),
+            vec![
+                "1,-1.5000,2.0000,0.5000,false",
+                "2,2.0000,0.0000,2.0000,false",
+                "client,available,held,total,locked",
+            ]
+        );
+
+        // Merged by timestamp: the earlier dispute holds the funds of the withdrawal
+        let res = wrapper(
+            &input_files,
+            &Options {
+                error_policy: ErrorPolicy::Skip,
+                output_file: Some(output_path.to_string()),
+                time_tolerance: Some(TimeDelta::zero()),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+        assert_eq!(
+            sorted_lines(output_path),
+            vec![
+                "1,0.0000,2.0000,2.0000,false",
+                "2,2.0000,0.0000,2.0000,false",
+                "client,available,held,total,locked",
+            ]
+        );
+
+        fs::remove_file(output_path).unwrap();
+    }
+
+    #[test]
+    fn test_wrapper_multiple_inputs_rejections() {
+        fs::create_dir_all("tmp").unwrap();
+        let rejections_path = "tmp/wrapper_rejections_multiple_inputs.csv";
+
+        let res = wrapper(
+            &[
+                "test_data/transactions_rejections_first.csv",
+                "test_data/transactions_rejections_second.csv",
+            ],
+            &Options {
+                error_policy: ErrorPolicy::Skip,
+                rejections_file: Some(rejections_path.to_string()),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+
+        // Ordered by input file first; the line numbers refer to the respective file
+        let content = fs::read_to_string(rejections_path).unwrap();
+        assert_eq!(
+            content,
+            concat!(
+                "file,line,type,client,tx,reason\n",
+                "test_data/transactions_rejections_first.csv,4,withdrawal,1,3,insufficient_funds\n",
+                "test_data/transactions_rejections_second.csv,2,withdrawal,2,4,insufficient_funds\n",
+            )
+        );
+
+        fs::remove_file(rejections_path).unwrap();
+    }
+
+    #[test]
+    fn test_wrapper_multiple_inputs_duplicate_transaction_id() {
+        let err = wrapper(
+            &[
+                "test_data/gateways/transactions_b.csv",
+                "test_data/transactions_duplicate_gateway.csv",
+            ],
+            &Options::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unexpected format: transaction id 2 is not unique (file 'test_data/transactions_duplicate_gateway.csv', line 2, record 1, byte 22)"
+        );
+        assert_eq!(exit_code(&err), EXIT_FORMAT_ERROR);
+    }
+
+    #[test]
+    fn test_wrapper_multiple_inputs_recovery() {
+        let err = wrapper(
+            &[
+                "test_data/gateways/transactions_a.csv",
+                "test_data/gateways/transactions_b.csv",
+            ],
+            &Options {
+                checkpoint_file: Some(String::from("tmp/checkpoint_multiple_inputs.json")),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Write-ahead log and checkpoint are only supported for a single input file"
+        );
+    }
+
+    fn input_files_of(inputs: &[&str]) -> Result<Vec<String>, anyhow::Error> {
+        input_files(
+            &inputs
+                .iter()
+                .map(|input| input.to_string())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn test_input_files() {
+        let input_files = input_files(&[
+            String::from("test_data/gateways/*.csv"),
+            String::from("test_data/transactions.csv"),
+        ])
+        .unwrap();
+        assert_eq!(
+            input_files,
+            vec![
+                "test_data/gateways/transactions_a.csv",
+                "test_data/gateways/transactions_b.csv",
+                "test_data/transactions.csv",
+            ]
+        );
+
+        // Plain paths are kept even if the file is missing
+        let input_files = input_files_of(&["test_data/missing.csv"]);
+        assert_eq!(input_files.unwrap(), vec!["test_data/missing.csv"]);
+
+        let err = input_files_of(&["test_data/missing/*.csv"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No input file matches pattern 'test_data/missing/*.csv'"
+        );
+
+        let err = input_files_of(&["-", "test_data/transactions.csv"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Reading from stdin is only supported for a single input"
+        );
+    }
+
+    #[test]
+    fn test_wrapper_pipelined() {
+        fs::create_dir_all("tmp").unwrap();
+        let output_path = "tmp/wrapper_accounts_pipelined.csv";
+        let expected_path = "tmp/wrapper_accounts_pipelined_expected.csv";
+
+        for (pipelined, output_path) in [(true, output_path), (false, expected_path)] {
+            let res = wrapper(
+                &["test_data/transactions_resume.csv"],
+                &Options {
+                    pipelined,
+                    error_policy: ErrorPolicy::Skip,
+                    output_file: Some(output_path.to_string()),
+                    ..Default::default()
+                },
+            );
+            assert!(res.is_ok());
+        }
+
+        assert_eq!(sorted_lines(output_path), sorted_lines(expected_path));
+
+        let err = wrapper(
+            &["test_data/transactions_insufficient_funds.csv"],
+            &Options {
+                pipelined: true,
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Can't withdraw transaction: insufficient funds for client id 1 (file 'test_data/transactions_insufficient_funds.csv', line 4, record 3, byte 123)"
+        );
+
+        fs::remove_file(output_path).unwrap();
+        fs::remove_file(expected_path).unwrap();
     }
 
     #[test]
@@ -127,20 +1317,98 @@ mod tests {
             max_value of 10_000_000 corresponds roughly to 200 MB of file size
             max_value of 100_000_000 corresponds roughly to 2 GB of file size
         */
-        let max_value = 1_000_000;
+        let max_value = 10_000_000;
+        let amount: Amount = "1.0".parse().unwrap();
         for i in 1..max_value {
-            let raw_transaction = RawTransaction::new(String::from("deposit"), 1, i, Some(1.0));
+            let raw_transaction = RawTransaction::new(String::from("deposit"), 1, i, Some(amount));
             csv_writer.serialize(raw_transaction).unwrap();
         }
         csv_writer.flush().unwrap();
 
+        let throughput =
+            |elapsed_time: u128| (max_value - 1) as f64 * 1000.0 / elapsed_time.max(1) as f64;
+
+        // Parsing only: fast path of the csv source compared to deserialization of every record via serde
         let instant = std::time::Instant::now();
-        let res = wrapper(csv_path);
+        let records = CsvSource::open(csv_path)
+            .unwrap()
+            .map(Result::unwrap)
+            .count();
+        let elapsed_time = instant.elapsed().as_millis();
+
+        assert_eq!(records as u32, max_value - 1);
+        println!("parse time (fast path): {:?} ms", elapsed_time);
+
+        let instant = std::time::Instant::now();
+        let records = ::csv::ReaderBuilder::new()
+            .trim(::csv::Trim::All)
+            .from_path(csv_path)
+            .unwrap()
+            .deserialize::<RawTransaction>()
+            .map(Result::unwrap)
+            .count();
+        let elapsed_time = instant.elapsed().as_millis();
+
+        assert_eq!(records as u32, max_value - 1);
+        println!("parse time (serde): {:?} ms", elapsed_time);
+
+        let instant = std::time::Instant::now();
+        let res = wrapper(&[csv_path], &Options::default());
         let elapsed_time = instant.elapsed().as_millis();
 
         assert!(res.is_ok());
 
         println!("response time: {:?} ms", elapsed_time);
+        println!("throughput: {:.0} records/s", throughput(elapsed_time));
+        assert!(elapsed_time < 50000);
+
+        let instant = std::time::Instant::now();
+        let res = wrapper(
+            &[csv_path],
+            &Options {
+                event_order: EventOrder::Grouped,
+                ..Default::default()
+            },
+        );
+        let elapsed_time = instant.elapsed().as_millis();
+
+        assert!(res.is_ok());
+
+        println!("response time (grouped): {:?} ms", elapsed_time);
+        assert!(elapsed_time < 50000);
+
+        let instant = std::time::Instant::now();
+        let res = wrapper(
+            &[csv_path],
+            &Options {
+                threads: 4,
+                ..Default::default()
+            },
+        );
+        let elapsed_time = instant.elapsed().as_millis();
+
+        assert!(res.is_ok());
+
+        println!("response time (4 threads): {:?} ms", elapsed_time);
+        assert!(elapsed_time < 50000);
+
+        let instant = std::time::Instant::now();
+        let res = wrapper(
+            &[csv_path],
+            &Options {
+                pipelined: true,
+                ..Default::default()
+            },
+        );
+        let elapsed_time = instant.elapsed().as_millis();
+
+        assert!(res.is_ok());
+
+        println!("response time (pipelined): {:?} ms", elapsed_time);
+        println!(
+            "throughput (pipelined): {:.0} records/s",
+            throughput(elapsed_time)
+        );
         assert!(elapsed_time < 50000);
 
         fs::remove_file(csv_path).unwrap();
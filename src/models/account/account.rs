@@ -1,16 +1,19 @@
 use crate::{
-    errors::{ChargebackError, DepositError, DisputeError, ResolveError, WithdrawalError},
+    errors::{
+        ChargebackError, DepositError, DisputeError, FormatError, ResolveError, WithdrawalError,
+    },
     models::{
-        round, EventType, RawAccount, TransactionStatus, {Transaction, TransactionType},
+        Amount, EventType, RawAccount, TransactionStatus, {Transaction, TransactionType},
     },
 };
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Account {
     pub client_id: u16,
-    pub available_amount: f64,
-    pub held_amount: f64,
-    pub total_amount: f64,
+    pub available_amount: Amount,
+    pub held_amount: Amount,
+    pub total_amount: Amount,
     pub is_locked: bool,
 }
 
@@ -18,9 +21,9 @@ impl Account {
     pub fn new(client_id: u16) -> Self {
         Self {
             client_id,
-            available_amount: 0.0,
-            held_amount: 0.0,
-            total_amount: 0.0,
+            available_amount: Amount::ZERO,
+            held_amount: Amount::ZERO,
+            total_amount: Amount::ZERO,
             is_locked: false,
         }
     }
@@ -34,8 +37,18 @@ impl Account {
             TransactionType::Deposit => {
                 if self.client_id == tx.client_id {
                     if !self.is_locked {
-                        self.available_amount += tx.amount;
-                        self.total_amount += tx.amount;
+                        let overflow = || DepositError::AmountOverflow(self.client_id);
+                        let available_amount = self
+                            .available_amount
+                            .checked_add(tx.amount)
+                            .ok_or_else(overflow)?;
+                        let total_amount = self
+                            .total_amount
+                            .checked_add(tx.amount)
+                            .ok_or_else(overflow)?;
+
+                        self.available_amount = available_amount;
+                        self.total_amount = total_amount;
                         Ok(())
                     } else {
                         Err(DepositError::FrozenAccount(self.client_id))
@@ -53,9 +66,19 @@ impl Account {
             TransactionType::Withdrawal => {
                 if self.client_id == tx.client_id {
                     if !self.is_locked {
-                        if self.available_amount - tx.amount >= 0.0 {
-                            self.available_amount -= tx.amount;
-                            self.total_amount -= tx.amount;
+                        if self.available_amount >= tx.amount {
+                            let overflow = || WithdrawalError::AmountOverflow(self.client_id);
+                            let available_amount = self
+                                .available_amount
+                                .checked_sub(tx.amount)
+                                .ok_or_else(overflow)?;
+                            let total_amount = self
+                                .total_amount
+                                .checked_sub(tx.amount)
+                                .ok_or_else(overflow)?;
+
+                            self.available_amount = available_amount;
+                            self.total_amount = total_amount;
                             Ok(())
                         } else {
                             Err(WithdrawalError::InsufficientFunds(self.client_id))
@@ -133,8 +156,18 @@ impl Account {
 
     fn dispute_deposit(&mut self, tx: &Transaction) -> Result<(), DisputeError> {
         if self.client_id == tx.client_id {
-            self.available_amount -= tx.amount;
-            self.held_amount += tx.amount;
+            let overflow = || DisputeError::AmountOverflow(self.client_id);
+            let available_amount = self
+                .available_amount
+                .checked_sub(tx.amount)
+                .ok_or_else(overflow)?;
+            let held_amount = self
+                .held_amount
+                .checked_add(tx.amount)
+                .ok_or_else(overflow)?;
+
+            self.available_amount = available_amount;
+            self.held_amount = held_amount;
             Ok(())
         } else {
             Err(DisputeError::InvalidClientId)
@@ -143,8 +176,18 @@ impl Account {
 
     fn dispute_withdrawal(&mut self, tx: &Transaction) -> Result<(), DisputeError> {
         if self.client_id == tx.client_id {
-            self.available_amount += tx.amount;
-            self.held_amount -= tx.amount;
+            let overflow = || DisputeError::AmountOverflow(self.client_id);
+            let available_amount = self
+                .available_amount
+                .checked_add(tx.amount)
+                .ok_or_else(overflow)?;
+            let held_amount = self
+                .held_amount
+                .checked_sub(tx.amount)
+                .ok_or_else(overflow)?;
+
+            self.available_amount = available_amount;
+            self.held_amount = held_amount;
             Ok(())
         } else {
             Err(DisputeError::InvalidClientId)
@@ -153,8 +196,18 @@ impl Account {
 
     fn resolve_deposit(&mut self, tx: &Transaction) -> Result<(), ResolveError> {
         if self.client_id == tx.client_id {
-            self.available_amount += tx.amount;
-            self.held_amount -= tx.amount;
+            let overflow = || ResolveError::AmountOverflow(self.client_id);
+            let available_amount = self
+                .available_amount
+                .checked_add(tx.amount)
+                .ok_or_else(overflow)?;
+            let held_amount = self
+                .held_amount
+                .checked_sub(tx.amount)
+                .ok_or_else(overflow)?;
+
+            self.available_amount = available_amount;
+            self.held_amount = held_amount;
             Ok(())
         } else {
             Err(ResolveError::InvalidClientId)
@@ -163,8 +216,18 @@ impl Account {
 
     fn resolve_withdrawal(&mut self, tx: &Transaction) -> Result<(), ResolveError> {
         if self.client_id == tx.client_id {
-            self.available_amount -= tx.amount;
-            self.held_amount += tx.amount;
+            let overflow = || ResolveError::AmountOverflow(self.client_id);
+            let available_amount = self
+                .available_amount
+                .checked_sub(tx.amount)
+                .ok_or_else(overflow)?;
+            let held_amount = self
+                .held_amount
+                .checked_add(tx.amount)
+                .ok_or_else(overflow)?;
+
+            self.available_amount = available_amount;
+            self.held_amount = held_amount;
             Ok(())
         } else {
             Err(ResolveError::InvalidClientId)
@@ -173,8 +236,18 @@ impl Account {
 
     fn chargeback_deposit(&mut self, tx: &Transaction) -> Result<(), ChargebackError> {
         if self.client_id == tx.client_id {
-            self.held_amount -= tx.amount;
-            self.total_amount -= tx.amount;
+            let overflow = || ChargebackError::AmountOverflow(self.client_id);
+            let held_amount = self
+                .held_amount
+                .checked_sub(tx.amount)
+                .ok_or_else(overflow)?;
+            let total_amount = self
+                .total_amount
+                .checked_sub(tx.amount)
+                .ok_or_else(overflow)?;
+
+            self.held_amount = held_amount;
+            self.total_amount = total_amount;
             Ok(())
         } else {
             Err(ChargebackError::InvalidClientId)
@@ -183,8 +256,18 @@ impl Account {
 
     fn chargeback_withdrawal(&mut self, tx: &Transaction) -> Result<(), ChargebackError> {
         if self.client_id == tx.client_id {
-            self.held_amount += tx.amount;
-            self.total_amount += tx.amount;
+            let overflow = || ChargebackError::AmountOverflow(self.client_id);
+            let held_amount = self
+                .held_amount
+                .checked_add(tx.amount)
+                .ok_or_else(overflow)?;
+            let total_amount = self
+                .total_amount
+                .checked_add(tx.amount)
+                .ok_or_else(overflow)?;
+
+            self.held_amount = held_amount;
+            self.total_amount = total_amount;
             Ok(())
         } else {
             Err(ChargebackError::InvalidClientId)
@@ -196,19 +279,45 @@ impl From<Account> for RawAccount {
     fn from(account: Account) -> RawAccount {
         RawAccount {
             client: account.client_id,
-            available: round(account.available_amount),
-            held: round(account.held_amount),
-            total: round(account.total_amount),
+            available: account.available_amount,
+            held: account.held_amount,
+            total: account.total_amount,
             locked: account.is_locked,
         }
     }
 }
 
+// Opening balances of a client account, e.g. from the output of a previous run
+impl TryFrom<RawAccount> for Account {
+    type Error = FormatError;
+
+    fn try_from(raw_account: RawAccount) -> Result<Self, Self::Error> {
+        let total = raw_account.available.checked_add(raw_account.held);
+
+        if total != Some(raw_account.total) {
+            return Err(FormatError::UnbalancedAccount(raw_account.client));
+        }
+
+        // Locked accounts stay locked as accounts can't be unlocked
+        Ok(Account {
+            client_id: raw_account.client,
+            available_amount: raw_account.available,
+            held_amount: raw_account.held,
+            total_amount: raw_account.total,
+            is_locked: raw_account.locked,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::models::TransactionType;
 
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
     #[test]
     fn test_new() {
         let client_id = 42;
@@ -217,19 +326,45 @@ mod tests {
             account,
             Account {
                 client_id,
-                available_amount: 0.0,
-                held_amount: 0.0,
-                total_amount: 0.0,
+                available_amount: amount("0.0"),
+                held_amount: amount("0.0"),
+                total_amount: amount("0.0"),
                 is_locked: false
             }
         )
     }
 
+    #[test]
+    fn test_try_from_raw_account() {
+        let raw_account = RawAccount::new(1, amount("1.5"), amount("0.5"), amount("2.0"), true);
+
+        let res = Account::try_from(raw_account);
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap(),
+            Account {
+                client_id: 1,
+                available_amount: amount("1.5"),
+                held_amount: amount("0.5"),
+                total_amount: amount("2.0"),
+                is_locked: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_try_from_unbalanced_raw_account() {
+        let raw_account = RawAccount::new(1, amount("1.5"), amount("0.5"), amount("1.5"), false);
+
+        let res = Account::try_from(raw_account);
+        assert_eq!(res, Err(FormatError::UnbalancedAccount(1)));
+    }
+
     #[test]
     fn test_deposit() {
         let client_id = 1;
         let mut account = Account::new(client_id);
-        let transaction = Transaction::new(TransactionType::Deposit, client_id, 1, 25.0);
+        let transaction = Transaction::new(TransactionType::Deposit, client_id, 1, amount("25.0"));
 
         let res = account.deposit(&transaction);
         assert!(res.is_ok());
@@ -237,9 +372,9 @@ mod tests {
             account,
             Account {
                 client_id,
-                available_amount: 25.0,
-                held_amount: 0.0,
-                total_amount: 25.0,
+                available_amount: amount("25.0"),
+                held_amount: amount("0.0"),
+                total_amount: amount("25.0"),
                 is_locked: false
             }
         );
@@ -248,7 +383,7 @@ mod tests {
     #[test]
     fn test_deposit_invalid_client_id() {
         let mut account = Account::new(1);
-        let transaction = Transaction::new(TransactionType::Deposit, 2, 1, 25.0);
+        let transaction = Transaction::new(TransactionType::Deposit, 2, 1, amount("25.0"));
 
         let res = account.deposit(&transaction);
         assert!(res.is_err());
@@ -261,9 +396,10 @@ mod tests {
     fn test_withdraw() {
         let client_id = 1;
         let mut account = Account::new(client_id);
-        let deposit_transaction = Transaction::new(TransactionType::Deposit, client_id, 1, 25.0);
+        let deposit_transaction =
+            Transaction::new(TransactionType::Deposit, client_id, 1, amount("25.0"));
         let withdrawal_transaction =
-            Transaction::new(TransactionType::Withdrawal, client_id, 2, 15.0);
+            Transaction::new(TransactionType::Withdrawal, client_id, 2, amount("15.0"));
 
         let res = account.deposit(&deposit_transaction);
         assert!(res.is_ok());
@@ -275,9 +411,9 @@ mod tests {
             account,
             Account {
                 client_id,
-                available_amount: 10.0,
-                held_amount: 0.0,
-                total_amount: 10.0,
+                available_amount: amount("10.0"),
+                held_amount: amount("0.0"),
+                total_amount: amount("10.0"),
                 is_locked: false
             }
         );
@@ -287,7 +423,8 @@ mod tests {
     fn test_withdraw_insufficient_funds() {
         let client_id = 1;
         let mut account = Account::new(client_id);
-        let transaction = Transaction::new(TransactionType::Withdrawal, client_id, 1, 25.0);
+        let transaction =
+            Transaction::new(TransactionType::Withdrawal, client_id, 1, amount("25.0"));
 
         let res = account.withdraw(&transaction);
         assert!(res.is_err());
@@ -299,7 +436,7 @@ mod tests {
     #[test]
     fn test_withdraw_invalid_client_id() {
         let mut account = Account::new(1);
-        let transaction = Transaction::new(TransactionType::Withdrawal, 2, 1, 15.0);
+        let transaction = Transaction::new(TransactionType::Withdrawal, 2, 1, amount("15.0"));
 
         let res = account.withdraw(&transaction);
         assert!(res.is_err());
@@ -311,7 +448,7 @@ mod tests {
     #[test]
     fn test_dispute_deposit() {
         let mut account = Account::new(1);
-        let transaction = Transaction::new(TransactionType::Deposit, 1, 1, 25.0);
+        let transaction = Transaction::new(TransactionType::Deposit, 1, 1, amount("25.0"));
         account.deposit(&transaction).unwrap();
 
         let res = account.dispute(&transaction, &EventType::Dispute);
@@ -320,9 +457,9 @@ mod tests {
             account,
             Account {
                 client_id: 1,
-                available_amount: 0.0,
-                held_amount: 25.0,
-                total_amount: 25.0,
+                available_amount: amount("0.0"),
+                held_amount: amount("25.0"),
+                total_amount: amount("25.0"),
                 is_locked: false
             }
         );
@@ -332,10 +469,11 @@ mod tests {
     fn test_dispute_withdrawal() {
         let mut account = Account::new(1);
 
-        let deposit_transaction = Transaction::new(TransactionType::Deposit, 1, 1, 25.0);
+        let deposit_transaction = Transaction::new(TransactionType::Deposit, 1, 1, amount("25.0"));
         account.deposit(&deposit_transaction).unwrap();
 
-        let withdraw_transaction = Transaction::new(TransactionType::Withdrawal, 1, 2, 15.0);
+        let withdraw_transaction =
+            Transaction::new(TransactionType::Withdrawal, 1, 2, amount("15.0"));
         account.withdraw(&withdraw_transaction).unwrap();
 
         let res = account.dispute(&withdraw_transaction, &EventType::Dispute);
@@ -344,9 +482,9 @@ mod tests {
             account,
             Account {
                 client_id: 1,
-                available_amount: 25.0,
-                held_amount: -15.0,
-                total_amount: 10.0,
+                available_amount: amount("25.0"),
+                held_amount: amount("-15.0"),
+                total_amount: amount("10.0"),
                 is_locked: false
             }
         );
@@ -356,7 +494,7 @@ mod tests {
     fn test_resolve_deposit() {
         let mut account = Account::new(1);
 
-        let mut transaction = Transaction::new(TransactionType::Deposit, 1, 1, 25.0);
+        let mut transaction = Transaction::new(TransactionType::Deposit, 1, 1, amount("25.0"));
         account.deposit(&transaction).unwrap();
         account.dispute(&transaction, &EventType::Dispute).unwrap();
         transaction.status = TransactionStatus::Disputed;
@@ -367,9 +505,9 @@ mod tests {
             account,
             Account {
                 client_id: 1,
-                available_amount: 25.0,
-                held_amount: 0.0,
-                total_amount: 25.0,
+                available_amount: amount("25.0"),
+                held_amount: amount("0.0"),
+                total_amount: amount("25.0"),
                 is_locked: false,
             }
         );
@@ -379,10 +517,11 @@ mod tests {
     fn test_resolve_withdrawal() {
         let mut account = Account::new(1);
 
-        let deposit_transaction = Transaction::new(TransactionType::Deposit, 1, 1, 25.0);
+        let deposit_transaction = Transaction::new(TransactionType::Deposit, 1, 1, amount("25.0"));
         account.deposit(&deposit_transaction).unwrap();
 
-        let mut withdraw_transaction = Transaction::new(TransactionType::Withdrawal, 1, 2, 15.0);
+        let mut withdraw_transaction =
+            Transaction::new(TransactionType::Withdrawal, 1, 2, amount("15.0"));
         account.withdraw(&withdraw_transaction).unwrap();
 
         account
@@ -396,9 +535,9 @@ mod tests {
             account,
             Account {
                 client_id: 1,
-                available_amount: 10.0,
-                held_amount: 0.0,
-                total_amount: 10.0,
+                available_amount: amount("10.0"),
+                held_amount: amount("0.0"),
+                total_amount: amount("10.0"),
                 is_locked: false
             }
         );
@@ -408,7 +547,7 @@ mod tests {
     fn test_chargeback_deposit() {
         let mut account = Account::new(1);
 
-        let mut transaction = Transaction::new(TransactionType::Deposit, 1, 1, 25.0);
+        let mut transaction = Transaction::new(TransactionType::Deposit, 1, 1, amount("25.0"));
         account.deposit(&transaction).unwrap();
         account.dispute(&transaction, &EventType::Dispute).unwrap();
         transaction.status = TransactionStatus::Disputed;
@@ -419,9 +558,9 @@ mod tests {
             account,
             Account {
                 client_id: 1,
-                available_amount: 0.0,
-                held_amount: 0.0,
-                total_amount: 0.0,
+                available_amount: amount("0.0"),
+                held_amount: amount("0.0"),
+                total_amount: amount("0.0"),
                 is_locked: true,
             }
         );
@@ -431,10 +570,11 @@ mod tests {
     fn test_chargeback_withdrawal() {
         let mut account = Account::new(1);
 
-        let deposit_transaction = Transaction::new(TransactionType::Deposit, 1, 1, 25.0);
+        let deposit_transaction = Transaction::new(TransactionType::Deposit, 1, 1, amount("25.0"));
         account.deposit(&deposit_transaction).unwrap();
 
-        let mut withdraw_transaction = Transaction::new(TransactionType::Withdrawal, 1, 2, 15.0);
+        let mut withdraw_transaction =
+            Transaction::new(TransactionType::Withdrawal, 1, 2, amount("15.0"));
         account.withdraw(&withdraw_transaction).unwrap();
 
         account
@@ -448,11 +588,47 @@ mod tests {
             account,
             Account {
                 client_id: 1,
-                available_amount: 25.0,
-                held_amount: 0.0,
-                total_amount: 25.0,
+                available_amount: amount("25.0"),
+                held_amount: amount("0.0"),
+                total_amount: amount("25.0"),
                 is_locked: true
             }
         );
     }
+
+    #[test]
+    fn test_dispute_invalid_client_id() {
+        let mut account = Account::new(1);
+        let transaction = Transaction::new(TransactionType::Deposit, 2, 1, amount("25.0"));
+
+        let res = account.dispute(&transaction, &EventType::Dispute);
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert_eq!(err, DisputeError::InvalidClientId);
+    }
+
+    #[test]
+    fn test_resolve_invalid_event_type() {
+        let mut account = Account::new(1);
+        let transaction = Transaction::new(TransactionType::Deposit, 1, 1, amount("25.0"));
+
+        let res = account.resolve(&transaction, &EventType::Dispute);
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert_eq!(err, ResolveError::InvalidEventType(1));
+    }
+
+    #[test]
+    fn test_chargeback_invalid_event_type() {
+        let mut account = Account::new(1);
+        let transaction = Transaction::new(TransactionType::Deposit, 1, 1, amount("25.0"));
+
+        let res = account.chargeback(&transaction, &EventType::Resolve);
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert_eq!(err, ChargebackError::InvalidEventType(1));
+    }
 }
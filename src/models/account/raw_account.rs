@@ -1,18 +1,19 @@
-use serde::Serialize;
+use crate::models::Amount;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct RawAccount {
     pub client: u16,
-    pub available: f64,
-    pub held: f64,
-    pub total: f64,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
     pub locked: bool,
 }
 
 impl RawAccount {
     // Used in tests
     #[allow(dead_code)]
-    pub fn new(client: u16, available: f64, held: f64, total: f64, locked: bool) -> Self {
+    pub fn new(client: u16, available: Amount, held: Amount, total: Amount, locked: bool) -> Self {
         Self {
             client,
             available,
@@ -0,0 +1,274 @@
+use crate::errors::AmountError;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+// Number of decimal places supported by the payment engine
+const DECIMALS: usize = 4;
+const SCALE: i64 = 10_000;
+
+// Fixed-point decimal type for monetary amounts. The amount is stored as an integer number of
+// ten-thousandths so that booking many transactions does not accumulate rounding errors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(i64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    // Number of ten-thousandths, e.g. for binary storage
+    pub(crate) fn to_raw(self) -> i64 {
+        self.0
+    }
+
+    pub(crate) fn from_raw(raw: i64) -> Self {
+        Amount(raw)
+    }
+}
+
+impl Amount {
+    // Parse the bytes of a decimal number without allocation, e.g. a field of a csv byte record
+    pub fn parse_bytes(bytes: &[u8]) -> Result<Self, AmountError> {
+        parse(bytes.trim_ascii())
+            .map_err(|error| error(String::from_utf8_lossy(bytes).into_owned()))
+    }
+}
+
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s.trim().as_bytes()).map_err(|error| error(s.to_string()))
+    }
+}
+
+// Parse a decimal number like "-42.3458"; additional decimal places are rounded half away from zero
+// Returns the constructor of the error so that the caller can attach the input
+fn parse(bytes: &[u8]) -> Result<Amount, fn(String) -> AmountError> {
+    let invalid: fn(String) -> AmountError = AmountError::InvalidFormat;
+    let out_of_range: fn(String) -> AmountError = AmountError::OutOfRange;
+
+    let (is_negative, digits) = match bytes {
+        [b'-', digits @ ..] => (true, digits),
+        [b'+', digits @ ..] => (false, digits),
+        digits => (false, digits),
+    };
+
+    let (integer_part, fractional_part) = match digits.iter().position(|b| *b == b'.') {
+        Some(index) => (&digits[..index], &digits[index + 1..]),
+        None => (digits, &[][..]),
+    };
+
+    if integer_part.is_empty() && fractional_part.is_empty() {
+        return Err(invalid);
+    }
+
+    if !integer_part
+        .iter()
+        .chain(fractional_part)
+        .all(|b| b.is_ascii_digit())
+    {
+        return Err(invalid);
+    }
+
+    let mut value: i64 = 0;
+
+    for digit in integer_part {
+        value = value
+            .checked_mul(10)
+            .and_then(|value| value.checked_add(i64::from(digit - b'0')))
+            .ok_or(out_of_range)?;
+    }
+
+    let mut fraction: i64 = 0;
+
+    for i in 0..DECIMALS {
+        let digit = fractional_part.get(i).map_or(0, |digit| digit - b'0');
+        fraction = fraction * 10 + i64::from(digit);
+    }
+
+    // Round half away from zero if there are more decimal places than supported
+    if let Some(digit) = fractional_part.get(DECIMALS) {
+        if *digit >= b'5' {
+            fraction += 1;
+        }
+    }
+
+    let value = value
+        .checked_mul(SCALE)
+        .and_then(|value| value.checked_add(fraction))
+        .ok_or(out_of_range)?;
+
+    if is_negative {
+        Ok(Amount(-value))
+    } else {
+        Ok(Amount(value))
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.is_negative() { "-" } else { "" };
+        let value = self.0.unsigned_abs();
+        let scale = SCALE.unsigned_abs();
+
+        write!(
+            f,
+            "{}{}.{:0width$}",
+            sign,
+            value / scale,
+            value % scale,
+            width = DECIMALS
+        )
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(AmountVisitor)
+    }
+}
+
+struct AmountVisitor;
+
+impl<'de> de::Visitor<'de> for AmountVisitor {
+    type Value = Amount;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "a decimal number with at most {} decimal places",
+            DECIMALS
+        )
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        value.parse().map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("1".parse::<Amount>().unwrap(), Amount(10000));
+        assert_eq!("1.5".parse::<Amount>().unwrap(), Amount(15000));
+        assert_eq!(".5".parse::<Amount>().unwrap(), Amount(5000));
+        assert_eq!("-0.0001".parse::<Amount>().unwrap(), Amount(-1));
+        assert_eq!(" 2.0 ".parse::<Amount>().unwrap(), Amount(20000));
+    }
+
+    #[test]
+    fn test_parse_invalid_format() {
+        for input in ["", ".", "abc", "1.2.3", "NaN", "inf", "-inf", "1e3"] {
+            assert_eq!(
+                input.parse::<Amount>().unwrap_err(),
+                AmountError::InvalidFormat(input.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_bytes() {
+        assert_eq!(Amount::parse_bytes(b"1.5").unwrap(), Amount(15000));
+        assert_eq!(Amount::parse_bytes(b" -0.00015 ").unwrap(), Amount(-2));
+        assert_eq!(
+            Amount::parse_bytes(b"1.2.3").unwrap_err(),
+            AmountError::InvalidFormat(String::from("1.2.3"))
+        );
+        assert_eq!(
+            Amount::parse_bytes(b"99999999999999999999").unwrap_err(),
+            AmountError::OutOfRange(String::from("99999999999999999999"))
+        );
+    }
+
+    #[test]
+    fn test_parse_out_of_range() {
+        let input = "99999999999999999999";
+        assert_eq!(
+            input.parse::<Amount>().unwrap_err(),
+            AmountError::OutOfRange(input.to_string())
+        );
+    }
+
+    #[test]
+    fn test_round_up() {
+        assert_eq!(
+            "42.34578".parse::<Amount>().unwrap(),
+            "42.3458".parse::<Amount>().unwrap()
+        )
+    }
+
+    #[test]
+    fn test_round_down() {
+        assert_eq!(
+            "42.34574".parse::<Amount>().unwrap(),
+            "42.3457".parse::<Amount>().unwrap()
+        )
+    }
+
+    #[test]
+    fn test_round_incorrect() {
+        assert_ne!(
+            "42.34578".parse::<Amount>().unwrap(),
+            "42.3457".parse::<Amount>().unwrap()
+        )
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Amount(15000).to_string(), "1.5000");
+        assert_eq!(Amount(-1).to_string(), "-0.0001");
+        assert_eq!(Amount::ZERO.to_string(), "0.0000");
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let amount = Amount(i64::MAX);
+        assert_eq!(amount.checked_add(Amount(1)), None);
+        assert_eq!(Amount(-1).checked_sub(amount).unwrap(), Amount(i64::MIN));
+        assert_eq!(
+            Amount(10000).checked_sub(Amount(15000)).unwrap(),
+            Amount(-5000)
+        );
+    }
+
+    #[test]
+    fn test_no_drift() {
+        let deposit: Amount = "0.0001".parse().unwrap();
+        let mut total = Amount::ZERO;
+
+        for _ in 0..1_000_000 {
+            total = total.checked_add(deposit).unwrap();
+        }
+
+        assert_eq!(total, "100".parse().unwrap());
+    }
+}
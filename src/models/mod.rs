@@ -1,34 +1,16 @@
 mod account;
+mod amount;
+mod position;
+mod rejection;
+mod timestamp;
 mod transaction;
 
 pub use account::{Account, RawAccount};
+pub use amount::Amount;
+pub use position::Position;
+pub use rejection::{Rejection, RejectionReason};
+pub use timestamp::Timestamp;
 pub use transaction::{
     CheckedTransaction, EventType, RawTransaction, Transaction, TransactionEvent,
     TransactionStatus, TransactionType,
 };
-
-const PRECISION: f64 = 10000.;
-
-pub fn round(amount: f64) -> f64 {
-    (amount * PRECISION).round() / PRECISION
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_round_up() {
-        assert_eq!(round(42.34578), 42.3458)
-    }
-
-    #[test]
-    fn test_round_down() {
-        assert_eq!(round(42.34574), 42.3457)
-    }
-
-    #[test]
-    fn test_round_incorrect() {
-        assert_ne!(round(42.34578), 42.3457)
-    }
-}
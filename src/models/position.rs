@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::Arc};
+
+// Position of a record in the input file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    // Path of the input file; none if read from stdin or created in code
+    #[serde(skip)]
+    pub file: Option<Arc<str>>,
+    // Index of the input file if several inputs are processed as a single stream
+    #[serde(skip)]
+    pub input: usize,
+    pub line: u64,
+    // Record number starting at 0 for the header
+    pub record: u64,
+    // Byte offset of the record in the input file
+    pub byte: u64,
+}
+
+impl Position {
+    pub fn new(line: u64) -> Self {
+        Self {
+            line,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_file(mut self, file: Option<Arc<str>>) -> Self {
+        self.file = file;
+        self
+    }
+
+    pub fn with_input(mut self, input: usize) -> Self {
+        self.input = input;
+        self
+    }
+}
+
+impl From<&csv::Position> for Position {
+    fn from(position: &csv::Position) -> Self {
+        Self {
+            file: None,
+            input: 0,
+            line: position.line(),
+            record: position.record(),
+            byte: position.byte(),
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "file '{}', ", file)?;
+        }
+
+        write!(
+            f,
+            "line {}, record {}, byte {}",
+            self.line, self.record, self.byte
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let position = Position {
+            file: None,
+            input: 0,
+            line: 3,
+            record: 2,
+            byte: 83,
+        };
+        assert_eq!(position.to_string(), "line 3, record 2, byte 83");
+
+        let position = position.with_file(Some(Arc::from("transactions.csv")));
+        assert_eq!(
+            position.to_string(),
+            "file 'transactions.csv', line 3, record 2, byte 83"
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{
+    errors::{
+        ChargebackError, DepositError, DisputeError, FormatError, OrderError, ResolveError,
+        WithdrawalError,
+    },
+    models::{Transaction, TransactionEvent},
+};
+use serde::{Deserialize, Serialize};
+
+// Machine-readable reason why a transaction was rejected or a transaction event was ignored
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectionReason {
+    InvalidClientId,
+    InvalidTransactionType,
+    InvalidEventType,
+    MissingAmount,
+    MissingTimestamp,
+    UnexpectedAmount,
+    InvalidAmount,
+    DuplicateTransactionId,
+    InsufficientFunds,
+    FrozenAccount,
+    AmountOverflow,
+    // Transaction event references an unknown transaction id
+    UnknownTransaction,
+    // Client id of the transaction event doesn't coincide with the referenced transaction
+    ClientMismatch,
+    // Resolve or chargeback for a transaction which isn't under dispute
+    NotDisputed,
+    // Transaction event references a rejected transaction
+    RejectedTransaction,
+    // Timestamp is earlier than the timestamp of the last booked record
+    LateArrival,
+    // Transaction event references a transaction which is finalized as its dispute window has passed
+    OutsideDisputeWindow,
+}
+
+// Row of the rejection report
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rejection {
+    // Path of the input file; empty if read from stdin
+    pub file: Option<String>,
+    // Index of the input file; orders the report if several inputs are processed
+    #[serde(skip)]
+    pub input: usize,
+    pub line: u64,
+    pub r#type: String,
+    pub client: u16,
+    pub tx: u32,
+    pub reason: RejectionReason,
+}
+
+impl Rejection {
+    pub fn from_transaction(tx: &Transaction, reason: RejectionReason) -> Self {
+        Self {
+            file: tx.position.file.as_deref().map(String::from),
+            input: tx.position.input,
+            line: tx.position.line,
+            r#type: tx.transaction_type.to_string(),
+            client: tx.client_id,
+            tx: tx.transaction_id,
+            reason,
+        }
+    }
+
+    pub fn from_event(event: &TransactionEvent, reason: RejectionReason) -> Self {
+        Self {
+            file: event.position.file.as_deref().map(String::from),
+            input: event.position.input,
+            line: event.position.line,
+            r#type: event.event_type.to_string(),
+            client: event.client_id,
+            tx: event.transaction_id,
+            reason,
+        }
+    }
+}
+
+impl From<&FormatError> for RejectionReason {
+    fn from(err: &FormatError) -> Self {
+        match err {
+            FormatError::InvalidTransactionType(..) => RejectionReason::InvalidTransactionType,
+            FormatError::MissingAmount(..) => RejectionReason::MissingAmount,
+            FormatError::MissingTimestamp(..) => RejectionReason::MissingTimestamp,
+            FormatError::UnexpectedAmount(..) => RejectionReason::UnexpectedAmount,
+            FormatError::InvalidAmount(..) => RejectionReason::InvalidAmount,
+            FormatError::UniqueTransactionId(..) => RejectionReason::DuplicateTransactionId,
+            // Only raised for opening balances which are never rejected individually
+            FormatError::UniqueClientId(..) => RejectionReason::InvalidClientId,
+            FormatError::UnbalancedAccount(..) => RejectionReason::InvalidAmount,
+        }
+    }
+}
+
+impl From<&DepositError> for RejectionReason {
+    fn from(err: &DepositError) -> Self {
+        match err {
+            DepositError::InvalidClientId => RejectionReason::InvalidClientId,
+            DepositError::FrozenAccount(..) => RejectionReason::FrozenAccount,
+            DepositError::InvalidTransactionType(..) => RejectionReason::InvalidTransactionType,
+            DepositError::AmountOverflow(..) => RejectionReason::AmountOverflow,
+        }
+    }
+}
+
+impl From<&WithdrawalError> for RejectionReason {
+    fn from(err: &WithdrawalError) -> Self {
+        match err {
+            WithdrawalError::InvalidClientId => RejectionReason::InvalidClientId,
+            WithdrawalError::InsufficientFunds(..) => RejectionReason::InsufficientFunds,
+            WithdrawalError::FrozenAccount(..) => RejectionReason::FrozenAccount,
+            WithdrawalError::InvalidTransactionType(..) => RejectionReason::InvalidTransactionType,
+            WithdrawalError::AmountOverflow(..) => RejectionReason::AmountOverflow,
+        }
+    }
+}
+
+impl From<&DisputeError> for RejectionReason {
+    fn from(err: &DisputeError) -> Self {
+        match err {
+            DisputeError::InvalidClientId => RejectionReason::InvalidClientId,
+            DisputeError::InvalidEventType(..) => RejectionReason::InvalidEventType,
+            DisputeError::AmountOverflow(..) => RejectionReason::AmountOverflow,
+        }
+    }
+}
+
+impl From<&ResolveError> for RejectionReason {
+    fn from(err: &ResolveError) -> Self {
+        match err {
+            ResolveError::InvalidClientId => RejectionReason::InvalidClientId,
+            ResolveError::InvalidEventType(..) => RejectionReason::InvalidEventType,
+            ResolveError::AmountOverflow(..) => RejectionReason::AmountOverflow,
+        }
+    }
+}
+
+impl From<&ChargebackError> for RejectionReason {
+    fn from(err: &ChargebackError) -> Self {
+        match err {
+            ChargebackError::InvalidClientId => RejectionReason::InvalidClientId,
+            ChargebackError::InvalidEventType(..) => RejectionReason::InvalidEventType,
+            ChargebackError::AmountOverflow(..) => RejectionReason::AmountOverflow,
+        }
+    }
+}
+
+impl From<&OrderError> for RejectionReason {
+    fn from(err: &OrderError) -> Self {
+        match err {
+            OrderError::LateArrival(..) => RejectionReason::LateArrival,
+        }
+    }
+}
@@ -0,0 +1,4 @@
+use chrono::{DateTime, Utc};
+
+// Point in time of a transaction or transaction event; given in RFC 3339 format in the input, e.g. '2024-05-01T12:00:00Z'
+pub type Timestamp = DateTime<Utc>;
@@ -1,4 +1,5 @@
 use super::{Transaction, TransactionEvent};
+use crate::models::Timestamp;
 
 // Helper type: this type is used to check the format of the input csv file
 #[derive(Debug)]
@@ -6,3 +7,19 @@ pub enum CheckedTransaction {
     Transaction(Transaction),
     TransactionEvent(TransactionEvent),
 }
+
+impl CheckedTransaction {
+    pub fn transaction_id(&self) -> u32 {
+        match self {
+            CheckedTransaction::Transaction(tx) => tx.transaction_id,
+            CheckedTransaction::TransactionEvent(event) => event.transaction_id,
+        }
+    }
+
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        match self {
+            CheckedTransaction::Transaction(tx) => tx.timestamp,
+            CheckedTransaction::TransactionEvent(event) => event.timestamp,
+        }
+    }
+}
@@ -1,5 +1,6 @@
 mod checked_transaction;
 mod raw_transaction;
+#[allow(clippy::module_inception)]
 mod transaction;
 mod transaction_event;
 
@@ -1,94 +1,135 @@
 use super::Transaction;
 use crate::{
     errors::FormatError,
-    models::{round, CheckedTransaction, EventType, TransactionEvent, TransactionType},
+    models::{
+        Amount, CheckedTransaction, EventType, Position, Timestamp, TransactionEvent,
+        TransactionType,
+    },
 };
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RawTransaction {
-    pub r#type: String,
+    // Borrowed if the type token is known, e.g. from the csv fast path; owned if deserialized
+    pub r#type: Cow<'static, str>,
     pub client: u16,
     pub tx: u32,
-    pub amount: Option<f64>,
+    pub amount: Option<Amount>,
+    // Optional column; required for chronological ordering
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<Timestamp>,
+    // Position in the input file; not part of the input format
+    #[serde(skip)]
+    pub position: Position,
 }
 
 // Used in tests
 #[allow(dead_code)]
 impl RawTransaction {
-    pub fn new(r#type: String, client: u16, tx: u32, amount: Option<f64>) -> Self {
+    pub fn new(
+        r#type: impl Into<Cow<'static, str>>,
+        client: u16,
+        tx: u32,
+        amount: Option<Amount>,
+    ) -> Self {
         Self {
-            r#type,
+            r#type: r#type.into(),
             client,
             tx,
             amount,
+            timestamp: None,
+            position: Position::default(),
         }
     }
+
+    pub fn with_timestamp(mut self, timestamp: Timestamp) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
 }
 
 impl TryFrom<RawTransaction> for CheckedTransaction {
     type Error = FormatError;
 
     fn try_from(tx: RawTransaction) -> Result<CheckedTransaction, Self::Error> {
-        match &tx.r#type {
-            x if x == "deposit" => Ok(CheckedTransaction::Transaction(Transaction::new(
-                TransactionType::Deposit,
+        let mut checked_transaction = check_transaction(&tx)?;
+
+        match &mut checked_transaction {
+            CheckedTransaction::Transaction(transaction) => {
+                transaction.timestamp = tx.timestamp;
+                transaction.position = tx.position;
+            }
+            CheckedTransaction::TransactionEvent(event) => {
+                event.timestamp = tx.timestamp;
+                event.position = tx.position;
+            }
+        }
+
+        Ok(checked_transaction)
+    }
+}
+
+#[allow(clippy::single_match)]
+fn check_transaction(tx: &RawTransaction) -> Result<CheckedTransaction, FormatError> {
+    match tx.r#type.as_ref() {
+        x if x == "deposit" => Ok(CheckedTransaction::Transaction(Transaction::new(
+            TransactionType::Deposit,
+            tx.client,
+            tx.tx,
+            validate_amount(tx, x)?,
+        ))),
+        x if x == "withdrawal" => Ok(CheckedTransaction::Transaction(Transaction::new(
+            TransactionType::Withdrawal,
+            tx.client,
+            tx.tx,
+            validate_amount(tx, x)?,
+        ))),
+        x if x == "dispute" => {
+            match tx.amount {
+                Some(_) => return Err(FormatError::UnexpectedAmount(tx.tx, x.to_string())),
+                None => (),
+            }
+
+            Ok(CheckedTransaction::TransactionEvent(TransactionEvent::new(
+                EventType::Dispute,
                 tx.client,
                 tx.tx,
-                validate_amount(&tx, x)?,
-            ))),
-            x if x == "withdrawal" => Ok(CheckedTransaction::Transaction(Transaction::new(
-                TransactionType::Withdrawal,
+            )))
+        }
+        x if x == "resolve" => {
+            match tx.amount {
+                Some(_) => return Err(FormatError::UnexpectedAmount(tx.tx, x.to_string())),
+                None => (),
+            }
+
+            Ok(CheckedTransaction::TransactionEvent(TransactionEvent::new(
+                EventType::Resolve,
                 tx.client,
                 tx.tx,
-                validate_amount(&tx, x)?,
-            ))),
-            x if x == "dispute" => {
-                match tx.amount {
-                    Some(_) => return Err(FormatError::UnexpectedAmount(tx.tx, x.to_string())),
-                    None => (),
-                }
-
-                Ok(CheckedTransaction::TransactionEvent(TransactionEvent::new(
-                    EventType::Dispute,
-                    tx.client,
-                    tx.tx,
-                )))
-            }
-            x if x == "resolve" => {
-                match tx.amount {
-                    Some(_) => return Err(FormatError::UnexpectedAmount(tx.tx, x.to_string())),
-                    None => (),
-                }
-
-                Ok(CheckedTransaction::TransactionEvent(TransactionEvent::new(
-                    EventType::Resolve,
-                    tx.client,
-                    tx.tx,
-                )))
-            }
-            x if x == "chargeback" => {
-                match tx.amount {
-                    Some(_) => return Err(FormatError::UnexpectedAmount(tx.tx, x.to_string())),
-                    None => (),
-                }
-
-                Ok(CheckedTransaction::TransactionEvent(TransactionEvent::new(
-                    EventType::Chargeback,
-                    tx.client,
-                    tx.tx,
-                )))
+            )))
+        }
+        x if x == "chargeback" => {
+            match tx.amount {
+                Some(_) => return Err(FormatError::UnexpectedAmount(tx.tx, x.to_string())),
+                None => (),
             }
-            x => Err(FormatError::InvalidTransactionType(x.to_string(), tx.tx)),
+
+            Ok(CheckedTransaction::TransactionEvent(TransactionEvent::new(
+                EventType::Chargeback,
+                tx.client,
+                tx.tx,
+            )))
         }
+        x => Err(FormatError::InvalidTransactionType(x.to_string(), tx.tx)),
     }
 }
 
-fn validate_amount(tx: &RawTransaction, transaction_type: &str) -> Result<f64, FormatError> {
+fn validate_amount(tx: &RawTransaction, transaction_type: &str) -> Result<Amount, FormatError> {
     match tx.amount {
         Some(amount) => {
-            if amount.is_finite() && amount.is_sign_positive() {
-                Ok(round(amount))
+            if !amount.is_negative() {
+                Ok(amount)
             } else {
                 Err(FormatError::InvalidAmount(
                     tx.tx,
@@ -109,7 +150,8 @@ mod tests {
 
     #[test]
     fn test_valid_transaction() {
-        let raw_transaction = RawTransaction::new("deposit".to_string(), 1, 1, Some(25.0));
+        let raw_transaction =
+            RawTransaction::new("deposit".to_string(), 1, 1, Some("25.0".parse().unwrap()));
 
         let res: Result<CheckedTransaction, FormatError> = raw_transaction.try_into();
         assert!(res.is_ok());
@@ -117,7 +159,8 @@ mod tests {
 
     #[test]
     fn test_invalid_transaction_type() {
-        let raw_transaction = RawTransaction::new("unknown".to_string(), 1, 1, Some(25.0));
+        let raw_transaction =
+            RawTransaction::new("unknown".to_string(), 1, 1, Some("25.0".parse().unwrap()));
 
         let res: Result<CheckedTransaction, FormatError> = raw_transaction.try_into();
         assert!(res.is_err());
@@ -142,7 +185,8 @@ mod tests {
 
     #[test]
     fn test_unexpected_amount() {
-        let raw_transaction = RawTransaction::new("dispute".to_string(), 1, 1, Some(25.0));
+        let raw_transaction =
+            RawTransaction::new("dispute".to_string(), 1, 1, Some("25.0".parse().unwrap()));
 
         let res: Result<CheckedTransaction, FormatError> = raw_transaction.try_into();
         assert!(res.is_err());
@@ -152,42 +196,21 @@ mod tests {
     }
 
     #[test]
-    fn test_infinity_amount() {
-        let raw_transaction = RawTransaction::new("deposit".to_string(), 1, 1, Some(f64::INFINITY));
-
-        let res: Result<CheckedTransaction, FormatError> = raw_transaction.try_into();
-        assert!(res.is_err());
-
-        let err = res.unwrap_err();
-        assert_eq!(err, FormatError::InvalidAmount(1, "deposit".to_string()));
-    }
-
-    #[test]
-    fn test_nan_amount() {
-        let raw_transaction = RawTransaction::new("deposit".to_string(), 1, 1, Some(f64::NAN));
-
-        let res: Result<CheckedTransaction, FormatError> = raw_transaction.try_into();
-        assert!(res.is_err());
-
-        let err = res.unwrap_err();
-        assert_eq!(err, FormatError::InvalidAmount(1, "deposit".to_string()));
-    }
-
-    #[test]
-    fn test_neg_infinity_amount() {
-        let raw_transaction =
-            RawTransaction::new("deposit".to_string(), 1, 1, Some(f64::NEG_INFINITY));
-
-        let res: Result<CheckedTransaction, FormatError> = raw_transaction.try_into();
-        assert!(res.is_err());
-
-        let err = res.unwrap_err();
-        assert_eq!(err, FormatError::InvalidAmount(1, "deposit".to_string()));
+    fn test_invalid_amount_format() {
+        for amount in ["NaN", "inf", "-inf", "abc"] {
+            let data = format!("type,client,tx,amount\ndeposit,1,1,{}\n", amount);
+            let mut csv_reader = csv::Reader::from_reader(data.as_bytes());
+
+            let res: Option<Result<RawTransaction, csv::Error>> = csv_reader.deserialize().next();
+            assert!(res.is_some());
+            assert!(res.unwrap().is_err());
+        }
     }
 
     #[test]
     fn test_negative_amount() {
-        let raw_transaction = RawTransaction::new("deposit".to_string(), 1, 1, Some(-25.0));
+        let raw_transaction =
+            RawTransaction::new("deposit".to_string(), 1, 1, Some("-25.0".parse().unwrap()));
 
         let res: Result<CheckedTransaction, FormatError> = raw_transaction.try_into();
         assert!(res.is_err());
@@ -1,13 +1,34 @@
-use super::EventType;
+use super::TransactionEvent;
+use crate::models::{Amount, Position, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TransactionType {
     Deposit,
     Withdrawal,
 }
 
+impl TransactionType {
+    // Token of the transaction type in the input
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+        }
+    }
+}
+
+impl fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 // The workflow of a Transaction is described by its TransactionStatus
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TransactionStatus {
     // Transaction prepared for processing
     Initiated,
@@ -19,16 +40,25 @@ pub enum TransactionStatus {
     Resolved,
     // Dispute is resolved by reversing the transaction (corresponding to a chargeback)
     Reversed,
+    // Transaction couldn't be booked on the client account (e.g. insufficient funds or frozen account)
+    Rejected,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Transaction {
     pub transaction_type: TransactionType,
     pub client_id: u16,
     pub transaction_id: u32,
-    pub amount: f64,
-    pub events: Vec<EventType>,
+    pub amount: Amount,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<TransactionEvent>,
     pub status: TransactionStatus,
+    // Optional point in time of the transaction in the input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<Timestamp>,
+    // Position in the input file; only relevant for the current run
+    #[serde(skip)]
+    pub position: Position,
 }
 
 impl Transaction {
@@ -36,7 +66,7 @@ impl Transaction {
         transaction_type: TransactionType,
         client_id: u16,
         transaction_id: u32,
-        amount: f64,
+        amount: Amount,
     ) -> Self {
         Self {
             transaction_type,
@@ -45,6 +75,8 @@ impl Transaction {
             amount,
             events: vec![],
             status: TransactionStatus::Initiated,
+            timestamp: None,
+            position: Position::default(),
         }
     }
 
@@ -54,8 +86,8 @@ impl Transaction {
         transaction_type: TransactionType,
         client_id: u16,
         transaction_id: u32,
-        amount: f64,
-        events: Vec<EventType>,
+        amount: Amount,
+        events: Vec<TransactionEvent>,
     ) -> Self {
         Self {
             transaction_type,
@@ -64,6 +96,8 @@ impl Transaction {
             amount,
             events,
             status: TransactionStatus::Initiated,
+            timestamp: None,
+            position: Position::default(),
         }
     }
 }
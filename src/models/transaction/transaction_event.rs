@@ -1,15 +1,43 @@
-#[derive(Debug, PartialEq)]
+use crate::models::{Position, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum EventType {
     Dispute,
     Resolve,
     Chargeback,
 }
 
-#[derive(Debug)]
+impl EventType {
+    // Token of the event type in the input
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventType::Dispute => "dispute",
+            EventType::Resolve => "resolve",
+            EventType::Chargeback => "chargeback",
+        }
+    }
+}
+
+impl fmt::Display for EventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TransactionEvent {
     pub event_type: EventType,
     pub client_id: u16,
     pub transaction_id: u32,
+    // Optional point in time of the transaction event in the input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<Timestamp>,
+    // Position in the input file; only relevant for the current run
+    #[serde(skip)]
+    pub position: Position,
 }
 
 impl TransactionEvent {
@@ -18,6 +46,8 @@ impl TransactionEvent {
             event_type,
             client_id,
             transaction_id,
+            timestamp: None,
+            position: Position::default(),
         }
     }
 }
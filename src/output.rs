@@ -0,0 +1,107 @@
+use crate::{
+    csv::CsvSink,
+    errors::EngineError,
+    json::{JsonLinesSink, JsonSink},
+    models::{RawAccount, Rejection},
+};
+use std::io::Write;
+
+// Sink for the results of processing, e.g. stdout, a file or a message producer
+pub trait AccountSink {
+    fn write_accounts(&mut self, raw_accounts: Vec<RawAccount>) -> Result<(), EngineError>;
+
+    // Rejected transactions and ignored transaction events; ignored by sinks which don't report them
+    fn write_rejections(&mut self, _rejections: &[Rejection]) -> Result<(), EngineError> {
+        Ok(())
+    }
+}
+
+impl<S: AccountSink + ?Sized> AccountSink for Box<S> {
+    fn write_accounts(&mut self, raw_accounts: Vec<RawAccount>) -> Result<(), EngineError> {
+        (**self).write_accounts(raw_accounts)
+    }
+
+    fn write_rejections(&mut self, rejections: &[Rejection]) -> Result<(), EngineError> {
+        (**self).write_rejections(rejections)
+    }
+}
+
+// Account sink keeping the results in memory, e.g. in tests
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    pub accounts: Vec<RawAccount>,
+    pub rejections: Vec<Rejection>,
+}
+
+impl AccountSink for MemorySink {
+    fn write_accounts(&mut self, raw_accounts: Vec<RawAccount>) -> Result<(), EngineError> {
+        self.accounts.extend(raw_accounts);
+        Ok(())
+    }
+
+    fn write_rejections(&mut self, rejections: &[Rejection]) -> Result<(), EngineError> {
+        self.rejections.extend_from_slice(rejections);
+        Ok(())
+    }
+}
+
+// Output format of the client accounts; amounts are always written with four decimal places
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    // JSON array of accounts
+    Json,
+    // One JSON object per account and line
+    JsonLines,
+}
+
+// Account sink for the given format, e.g. writing to stdout or an output file
+pub fn sink<W: Write + 'static>(writer: W, format: OutputFormat) -> Box<dyn AccountSink> {
+    match format {
+        OutputFormat::Csv => Box::new(CsvSink::new(writer)),
+        OutputFormat::Json => Box::new(JsonSink::new(writer)),
+        OutputFormat::JsonLines => Box::new(JsonLinesSink::new(writer)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Amount;
+    use std::fs::{self, File};
+
+    fn write(format: OutputFormat, raw_account: RawAccount) -> String {
+        fs::create_dir_all("tmp").unwrap();
+        let output_path = format!("tmp/output_sink_{:?}.txt", format);
+
+        let mut sink = sink(File::create(&output_path).unwrap(), format);
+        sink.write_accounts(vec![raw_account]).unwrap();
+        drop(sink);
+
+        let output = fs::read_to_string(&output_path).unwrap();
+        fs::remove_file(&output_path).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_sink() {
+        let raw_account = RawAccount::new(
+            1,
+            "0.00005".parse().unwrap(),
+            Amount::ZERO,
+            "0.00005".parse().unwrap(),
+            false,
+        );
+
+        assert_eq!(
+            write(OutputFormat::Csv, raw_account.clone()),
+            "client,available,held,total,locked\n1,0.0001,0.0000,0.0001,false\n"
+        );
+        assert_eq!(
+            write(OutputFormat::JsonLines, raw_account.clone()),
+            "{\"client\":1,\"available\":\"0.0001\",\"held\":\"0.0000\",\"total\":\"0.0001\",\"locked\":false}\n"
+        );
+        assert!(write(OutputFormat::Json, raw_account).contains("\"available\": \"0.0001\""));
+    }
+}
@@ -1,122 +1,356 @@
+mod checkpoint;
+mod chronological;
+mod engine;
+mod id_set;
+mod index;
+mod parallel;
+mod pipeline;
 mod postprocessing;
 mod preprocessing;
+mod snapshot;
+mod store;
+mod wal;
 
+pub use checkpoint::{Checkpoint, Checkpointer, DEFAULT_CHECKPOINT_INTERVAL};
+pub use chronological::{ChronologicalSource, MergedSource};
+pub use engine::{process_stream, Engine};
+pub use index::{DiskIndex, DEFAULT_INDEX_CACHE};
+pub use parallel::process_parallel;
+pub use pipeline::process_pipelined;
 pub use postprocessing::postprocess;
-pub use preprocessing::preprocess;
+pub use preprocessing::{open_accounts, preprocess, HistoryEntry};
+pub use snapshot::{DisputeWindowState, Snapshot, WindowEntry, SNAPSHOT_VERSION};
+pub use store::TransactionStore;
+pub use wal::Wal;
 
 use crate::{
-    errors::FormatError,
-    models::{Account, EventType, Transaction, TransactionStatus, TransactionType},
+    errors::{EngineError, FormatError},
+    input::TransactionSource,
+    models::{
+        Account, EventType, Rejection, RejectionReason, Transaction, TransactionEvent,
+        TransactionStatus, TransactionType,
+    },
+    output::AccountSink,
 };
-use std::collections::{hash_map::Entry, HashMap};
+use chrono::TimeDelta;
+use std::collections::HashMap;
 
+// Order in which the transaction events (dispute, resolve, chargeback) are applied
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EventOrder {
+    // Transaction events are applied immediately after the referenced transaction (legacy batch processing)
+    Grouped,
+    // Transaction events are interleaved with the transactions in the order of the input data
+    #[default]
+    FileOrder,
+}
+
+// Handling of transactions and transaction events which can't be booked on the client account (e.g. insufficient funds or frozen account)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ErrorPolicy {
+    // Abort processing on the first error
+    #[default]
+    Abort,
+    // Mark rejected transactions as TransactionStatus::Rejected, ignore failed transaction events, and continue processing
+    Skip,
+}
+
+// Period in which a processed transaction can be referenced by transaction events; afterwards the transaction is finalized
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DisputeWindow {
+    // Transactions can be disputed forever
+    #[default]
+    Unlimited,
+    // Transactions can be disputed by the given number of subsequent records
+    Records(u64),
+    // Transactions can be disputed for the given time after their timestamp; records without timestamp don't advance the time
+    Duration(TimeDelta),
+}
+
+// Process all raw transactions of the source and write the client accounts and the rejections to the sink
+pub fn run(
+    source: impl TransactionSource,
+    sink: &mut impl AccountSink,
+    event_order: EventOrder,
+    error_policy: ErrorPolicy,
+) -> Result<(), EngineError> {
+    let mut rejections: Vec<Rejection> = vec![];
+
+    let accounts = match event_order {
+        EventOrder::FileOrder => process_stream(source, error_policy, &mut rejections)?,
+        EventOrder::Grouped => {
+            process_batch(source, HashMap::new(), error_policy, &mut rejections)?
+        }
+    };
+
+    write_results(sink, accounts, rejections)
+}
+
+// Process all raw transactions in batch with transaction events grouped under the referenced transaction
+pub fn process_batch(
+    source: impl TransactionSource,
+    accounts: HashMap<u16, Account>,
+    error_policy: ErrorPolicy,
+    rejections: &mut Vec<Rejection>,
+) -> Result<HashMap<u16, Account>, EngineError> {
+    let raw_transactions = source.collect::<Result<Vec<_>, _>>()?;
+
+    // Prepare transactions for processing and convert raw transactions to business objects
+    let (transaction_history, mut transactions) =
+        preprocess(raw_transactions, EventOrder::Grouped, rejections)?;
+
+    process_transactions(
+        &transaction_history,
+        &mut transactions,
+        accounts,
+        error_policy,
+        rejections,
+    )
+}
+
+// Write the client accounts and the rejections to the sink
+pub fn write_results(
+    sink: &mut impl AccountSink,
+    accounts: HashMap<u16, Account>,
+    mut rejections: Vec<Rejection>,
+) -> Result<(), EngineError> {
+    // Convert business objects from Account to RawAccount
+    let raw_accounts = postprocess(accounts)?;
+    sink.write_accounts(raw_accounts)?;
+
+    // Rejections are collected during preprocessing and processing; order by input file and position within it
+    rejections.sort_by_key(|rejection| (rejection.input, rejection.line));
+    sink.write_rejections(&rejections)?;
+
+    Ok(())
+}
+
+// Process the transaction history on top of the opening balances of the client accounts
 pub fn process_transactions(
-    transaction_history: &[u32],
+    transaction_history: &[HistoryEntry],
     transactions: &mut HashMap<u32, Transaction>,
-) -> Result<HashMap<u16, Account>, anyhow::Error> {
     // Use hash map for storing accounts; search, insertion and update is O(1)
-    let mut accounts: HashMap<u16, Account> = HashMap::new();
-
+    mut accounts: HashMap<u16, Account>,
+    error_policy: ErrorPolicy,
+    rejections: &mut Vec<Rejection>,
+) -> Result<HashMap<u16, Account>, EngineError> {
     // Process transactions in chronological order
-    for id in transaction_history {
-        match transactions.get_mut(id) {
-            Some(tx) => match tx.transaction_type {
-                TransactionType::Deposit => match accounts.entry(tx.client_id) {
-                    Entry::Occupied(entry) => {
-                        let account = entry.into_mut();
-                        account.deposit(tx)?;
-                        tx.status = TransactionStatus::Processed;
-                        process_events(tx, account)?;
-                    }
-                    Entry::Vacant(entry) => {
-                        let account = entry.insert(Account::new(tx.client_id));
-                        account.deposit(tx)?;
-                        tx.status = TransactionStatus::Processed;
-                        process_events(tx, account)?;
-                    }
-                },
-                TransactionType::Withdrawal => match accounts.entry(tx.client_id) {
-                    Entry::Occupied(entry) => {
-                        let account = entry.into_mut();
-                        account.withdraw(tx)?;
-                        tx.status = TransactionStatus::Processed;
-                        process_events(tx, account)?;
-                    }
-                    Entry::Vacant(entry) => {
-                        let account = entry.insert(Account::new(tx.client_id));
-                        account.withdraw(tx)?;
-                        tx.status = TransactionStatus::Processed;
-                        process_events(tx, account)?;
+    for entry in transaction_history {
+        match entry {
+            HistoryEntry::Transaction(id) => match transactions.get_mut(id) {
+                Some(tx) => {
+                    let account = accounts
+                        .entry(tx.client_id)
+                        .or_insert_with(|| Account::new(tx.client_id));
+
+                    match process_transaction(tx, account) {
+                        Ok(()) => process_events(tx, account, error_policy, rejections)?,
+                        Err(err) => reject_transaction(tx, err, error_policy, rejections)?,
                     }
-                },
+                }
+                None => return Err(FormatError::UniqueTransactionId(*id).into()),
             },
-            None => return Err(FormatError::UniqueTransactionId(*id).into()),
+            HistoryEntry::Event(event) => {
+                // The referenced transaction precedes the transaction event in the transaction history
+                if let Some(tx) = transactions.get_mut(&event.transaction_id) {
+                    if let Some(account) = accounts.get_mut(&tx.client_id) {
+                        process_event(tx, account, event, error_policy, rejections)?;
+                    }
+                }
+            }
         };
     }
 
     Ok(accounts)
 }
 
-fn process_events(tx: &mut Transaction, account: &mut Account) -> Result<(), anyhow::Error> {
-    if !tx.events.is_empty() {
-        for event in tx.events.iter() {
-            match event {
-                EventType::Dispute => {
-                    account.dispute(tx, event)?;
-                    tx.status = TransactionStatus::Disputed;
-                }
-                EventType::Resolve => {
-                    // Ignore resolve if transaction isn't under dispute
-                    if tx.status == TransactionStatus::Disputed {
-                        account.resolve(tx, event)?;
-                        tx.status = TransactionStatus::Resolved;
-                    }
-                }
-                EventType::Chargeback => {
-                    // Ignore chargeback if transaction isn't under dispute
-                    if tx.status == TransactionStatus::Disputed {
-                        account.chargeback(tx, event)?;
-                        tx.status = TransactionStatus::Reversed;
-                    }
-                }
-            }
+// Error of a single transaction or transaction event which can be skipped depending on the error policy
+struct BookingError {
+    reason: RejectionReason,
+    source: EngineError,
+}
+
+impl<E> From<E> for BookingError
+where
+    E: Into<EngineError>,
+    for<'a> RejectionReason: From<&'a E>,
+{
+    fn from(err: E) -> Self {
+        Self {
+            reason: RejectionReason::from(&err),
+            source: err.into(),
         }
     }
+}
+
+fn process_transaction(tx: &mut Transaction, account: &mut Account) -> Result<(), BookingError> {
+    match tx.transaction_type {
+        TransactionType::Deposit => account.deposit(tx)?,
+        TransactionType::Withdrawal => account.withdraw(tx)?,
+    }
+
+    tx.status = TransactionStatus::Processed;
 
     Ok(())
 }
 
+fn process_events(
+    tx: &mut Transaction,
+    account: &mut Account,
+    error_policy: ErrorPolicy,
+    rejections: &mut Vec<Rejection>,
+) -> Result<(), EngineError> {
+    if tx.events.is_empty() {
+        return Ok(());
+    }
+
+    let events = std::mem::take(&mut tx.events);
+
+    let res = events
+        .iter()
+        .try_for_each(|event| process_event(tx, account, event, error_policy, rejections));
+
+    tx.events = events;
+
+    res
+}
+
+fn process_event(
+    tx: &mut Transaction,
+    account: &mut Account,
+    event: &TransactionEvent,
+    error_policy: ErrorPolicy,
+    rejections: &mut Vec<Rejection>,
+) -> Result<(), EngineError> {
+    match apply_event(tx, account, &event.event_type) {
+        Ok(None) => Ok(()),
+        Ok(Some(reason)) => {
+            rejections.push(Rejection::from_event(event, reason));
+            Ok(())
+        }
+        Err(err) => reject_event(event, err, error_policy, rejections),
+    }
+}
+
+// Ignore the transaction event or abort processing depending on the error policy
+fn reject_event(
+    event: &TransactionEvent,
+    err: BookingError,
+    error_policy: ErrorPolicy,
+    rejections: &mut Vec<Rejection>,
+) -> Result<(), EngineError> {
+    match error_policy {
+        ErrorPolicy::Abort => Err(err.source.at(&event.position)),
+        ErrorPolicy::Skip => {
+            rejections.push(Rejection::from_event(event, err.reason));
+            Ok(())
+        }
+    }
+}
+
+// Apply the transaction event on the account; returns the reason if the transaction event is ignored
+fn apply_event(
+    tx: &mut Transaction,
+    account: &mut Account,
+    event: &EventType,
+) -> Result<Option<RejectionReason>, BookingError> {
+    // Ignore transaction events referencing a rejected transaction
+    if tx.status == TransactionStatus::Rejected {
+        return Ok(Some(RejectionReason::RejectedTransaction));
+    }
+
+    match event {
+        EventType::Dispute => {
+            account.dispute(tx, event)?;
+            tx.status = TransactionStatus::Disputed;
+        }
+        EventType::Resolve => {
+            // Ignore resolve if transaction isn't under dispute
+            if tx.status != TransactionStatus::Disputed {
+                return Ok(Some(RejectionReason::NotDisputed));
+            }
+
+            account.resolve(tx, event)?;
+            tx.status = TransactionStatus::Resolved;
+        }
+        EventType::Chargeback => {
+            // Ignore chargeback if transaction isn't under dispute
+            if tx.status != TransactionStatus::Disputed {
+                return Ok(Some(RejectionReason::NotDisputed));
+            }
+
+            account.chargeback(tx, event)?;
+            tx.status = TransactionStatus::Reversed;
+        }
+    }
+
+    Ok(None)
+}
+
+// Mark the transaction as rejected or abort processing depending on the error policy
+fn reject_transaction(
+    tx: &mut Transaction,
+    err: BookingError,
+    error_policy: ErrorPolicy,
+    rejections: &mut Vec<Rejection>,
+) -> Result<(), EngineError> {
+    match error_policy {
+        ErrorPolicy::Abort => Err(err.source.at(&tx.position)),
+        ErrorPolicy::Skip => {
+            tx.status = TransactionStatus::Rejected;
+            rejections.push(Rejection::from_transaction(tx, err.reason));
+            Ok(())
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{models::RawTransaction, payment_engine};
+    use crate::{
+        errors::WithdrawalError,
+        input::MemorySource,
+        models::{Amount, Position, RawAccount, RawTransaction},
+        output::MemorySink,
+        payment_engine,
+    };
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
 
     #[test]
     fn test_process_transactions() {
         let raw_transactions: Vec<RawTransaction> = vec![
-            RawTransaction::new(String::from("deposit"), 1, 1, Some(1.0)),
-            RawTransaction::new(String::from("deposit"), 2, 2, Some(2.0)),
-            RawTransaction::new(String::from("deposit"), 1, 3, Some(2.0)),
-            RawTransaction::new(String::from("withdrawal"), 1, 4, Some(1.5)),
-            RawTransaction::new(String::from("withdrawal"), 2, 5, Some(2.0)),
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("1.0"))),
+            RawTransaction::new(String::from("deposit"), 2, 2, Some(amount("2.0"))),
+            RawTransaction::new(String::from("deposit"), 1, 3, Some(amount("2.0"))),
+            RawTransaction::new(String::from("withdrawal"), 1, 4, Some(amount("1.5"))),
+            RawTransaction::new(String::from("withdrawal"), 2, 5, Some(amount("2.0"))),
         ];
         let (transaction_history, mut transactions) =
-            payment_engine::preprocess(raw_transactions).unwrap();
+            payment_engine::preprocess(raw_transactions, EventOrder::Grouped, &mut vec![]).unwrap();
 
-        let res = process_transactions(&transaction_history, &mut transactions);
+        let res = process_transactions(
+            &transaction_history,
+            &mut transactions,
+            HashMap::new(),
+            ErrorPolicy::Abort,
+            &mut vec![],
+        );
         assert!(res.is_ok());
 
         let accounts = res.unwrap();
-        assert!(accounts.get(&1).is_some());
-        assert!(accounts.get(&2).is_some());
+        assert!(accounts.contains_key(&1));
+        assert!(accounts.contains_key(&2));
 
         assert_eq!(
             accounts.get(&1).unwrap(),
             &Account {
                 client_id: 1,
-                available_amount: 1.5,
-                held_amount: 0.0,
-                total_amount: 1.5,
+                available_amount: amount("1.5"),
+                held_amount: amount("0.0"),
+                total_amount: amount("1.5"),
                 is_locked: false
             }
         );
@@ -124,9 +358,9 @@ mod tests {
             accounts.get(&2).unwrap(),
             &Account {
                 client_id: 2,
-                available_amount: 0.0,
-                held_amount: 0.0,
-                total_amount: 0.0,
+                available_amount: amount("0.0"),
+                held_amount: amount("0.0"),
+                total_amount: amount("0.0"),
                 is_locked: false
             }
         );
@@ -135,31 +369,37 @@ mod tests {
     #[test]
     fn test_process_transactions_and_events() {
         let raw_transactions: Vec<RawTransaction> = vec![
-            RawTransaction::new(String::from("deposit"), 1, 1, Some(1.0)),
-            RawTransaction::new(String::from("deposit"), 2, 2, Some(2.0)),
-            RawTransaction::new(String::from("deposit"), 1, 3, Some(2.0)),
-            RawTransaction::new(String::from("withdrawal"), 1, 4, Some(1.5)),
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("1.0"))),
+            RawTransaction::new(String::from("deposit"), 2, 2, Some(amount("2.0"))),
+            RawTransaction::new(String::from("deposit"), 1, 3, Some(amount("2.0"))),
+            RawTransaction::new(String::from("withdrawal"), 1, 4, Some(amount("1.5"))),
             RawTransaction::new(String::from("dispute"), 1, 4, None),
             RawTransaction::new(String::from("chargeback"), 1, 4, None),
-            RawTransaction::new(String::from("withdrawal"), 2, 5, Some(2.0)),
+            RawTransaction::new(String::from("withdrawal"), 2, 5, Some(amount("2.0"))),
         ];
         let (transaction_history, mut transactions) =
-            payment_engine::preprocess(raw_transactions).unwrap();
+            payment_engine::preprocess(raw_transactions, EventOrder::Grouped, &mut vec![]).unwrap();
 
-        let res = process_transactions(&transaction_history, &mut transactions);
+        let res = process_transactions(
+            &transaction_history,
+            &mut transactions,
+            HashMap::new(),
+            ErrorPolicy::Abort,
+            &mut vec![],
+        );
         assert!(res.is_ok());
 
         let accounts = res.unwrap();
-        assert!(accounts.get(&1).is_some());
-        assert!(accounts.get(&2).is_some());
+        assert!(accounts.contains_key(&1));
+        assert!(accounts.contains_key(&2));
 
         assert_eq!(
             accounts.get(&1).unwrap(),
             &Account {
                 client_id: 1,
-                available_amount: 3.0,
-                held_amount: 0.0,
-                total_amount: 3.0,
+                available_amount: amount("3.0"),
+                held_amount: amount("0.0"),
+                total_amount: amount("3.0"),
                 is_locked: true
             }
         );
@@ -167,22 +407,269 @@ mod tests {
             accounts.get(&2).unwrap(),
             &Account {
                 client_id: 2,
-                available_amount: 0.0,
-                held_amount: 0.0,
-                total_amount: 0.0,
+                available_amount: amount("0.0"),
+                held_amount: amount("0.0"),
+                total_amount: amount("0.0"),
+                is_locked: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_process_withdrawal_before_dispute_file_order() {
+        let raw_transactions: Vec<RawTransaction> = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("10.0"))),
+            RawTransaction::new(String::from("withdrawal"), 1, 2, Some(amount("10.0"))),
+            RawTransaction::new(String::from("dispute"), 1, 1, None),
+        ];
+        let (transaction_history, mut transactions) =
+            payment_engine::preprocess(raw_transactions, EventOrder::FileOrder, &mut vec![])
+                .unwrap();
+
+        let res = process_transactions(
+            &transaction_history,
+            &mut transactions,
+            HashMap::new(),
+            ErrorPolicy::Abort,
+            &mut vec![],
+        );
+        assert!(res.is_ok());
+
+        let accounts = res.unwrap();
+        assert_eq!(
+            accounts.get(&1).unwrap(),
+            &Account {
+                client_id: 1,
+                available_amount: amount("-10.0"),
+                held_amount: amount("10.0"),
+                total_amount: amount("0.0"),
+                is_locked: false
+            }
+        );
+        assert_eq!(
+            transactions.get(&1).unwrap().status,
+            TransactionStatus::Disputed
+        );
+        assert_eq!(
+            transactions.get(&2).unwrap().status,
+            TransactionStatus::Processed
+        );
+    }
+
+    #[test]
+    fn test_process_withdrawal_before_dispute_grouped() {
+        let raw_transactions: Vec<RawTransaction> = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("10.0"))),
+            RawTransaction::new(String::from("withdrawal"), 1, 2, Some(amount("10.0"))),
+            RawTransaction::new(String::from("dispute"), 1, 1, None),
+        ];
+        let (transaction_history, mut transactions) =
+            payment_engine::preprocess(raw_transactions, EventOrder::Grouped, &mut vec![]).unwrap();
+
+        // The dispute is applied before the withdrawal, so the funds are held already
+        let res = process_transactions(
+            &transaction_history,
+            &mut transactions,
+            HashMap::new(),
+            ErrorPolicy::Abort,
+            &mut vec![],
+        );
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert!(matches!(
+            err.root(),
+            EngineError::Withdrawal(err) if *err == WithdrawalError::InsufficientFunds(1)
+        ));
+    }
+
+    #[test]
+    fn test_process_withdrawal_after_dispute_file_order() {
+        let raw_transactions: Vec<RawTransaction> = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("10.0"))),
+            RawTransaction::new(String::from("dispute"), 1, 1, None),
+            RawTransaction::new(String::from("withdrawal"), 1, 2, Some(amount("5.0"))),
+        ];
+        let (transaction_history, mut transactions) =
+            payment_engine::preprocess(raw_transactions, EventOrder::FileOrder, &mut vec![])
+                .unwrap();
+
+        let res = process_transactions(
+            &transaction_history,
+            &mut transactions,
+            HashMap::new(),
+            ErrorPolicy::Abort,
+            &mut vec![],
+        );
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert!(matches!(
+            err.root(),
+            EngineError::Withdrawal(err) if *err == WithdrawalError::InsufficientFunds(1)
+        ));
+    }
+
+    #[test]
+    fn test_process_withdrawal_after_resolve_file_order() {
+        let raw_transactions: Vec<RawTransaction> = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("10.0"))),
+            RawTransaction::new(String::from("dispute"), 1, 1, None),
+            RawTransaction::new(String::from("deposit"), 1, 2, Some(amount("2.0"))),
+            RawTransaction::new(String::from("resolve"), 1, 1, None),
+            RawTransaction::new(String::from("withdrawal"), 1, 3, Some(amount("12.0"))),
+        ];
+        let (transaction_history, mut transactions) =
+            payment_engine::preprocess(raw_transactions, EventOrder::FileOrder, &mut vec![])
+                .unwrap();
+
+        let res = process_transactions(
+            &transaction_history,
+            &mut transactions,
+            HashMap::new(),
+            ErrorPolicy::Abort,
+            &mut vec![],
+        );
+        assert!(res.is_ok());
+
+        let accounts = res.unwrap();
+        assert_eq!(
+            accounts.get(&1).unwrap(),
+            &Account {
+                client_id: 1,
+                available_amount: amount("0.0"),
+                held_amount: amount("0.0"),
+                total_amount: amount("0.0"),
                 is_locked: false
             }
         );
+        assert_eq!(
+            transactions.get(&1).unwrap().status,
+            TransactionStatus::Resolved
+        );
+    }
+
+    #[test]
+    fn test_process_transactions_skip_errors() {
+        let raw_transactions: Vec<RawTransaction> = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("1.0"))),
+            RawTransaction::new(String::from("withdrawal"), 1, 2, Some(amount("1.5"))),
+            RawTransaction::new(String::from("dispute"), 1, 2, None),
+            RawTransaction::new(String::from("deposit"), 2, 3, Some(amount("2.0"))),
+            RawTransaction::new(String::from("dispute"), 2, 3, None),
+            RawTransaction::new(String::from("chargeback"), 2, 3, None),
+            RawTransaction::new(String::from("deposit"), 2, 4, Some(amount("1.0"))),
+            RawTransaction::new(String::from("withdrawal"), 1, 5, Some(amount("0.5"))),
+        ];
+        let mut rejections = vec![];
+        let (transaction_history, mut transactions) =
+            payment_engine::preprocess(raw_transactions, EventOrder::FileOrder, &mut rejections)
+                .unwrap();
+
+        let res = process_transactions(
+            &transaction_history,
+            &mut transactions,
+            HashMap::new(),
+            ErrorPolicy::Skip,
+            &mut rejections,
+        );
+        assert!(res.is_ok());
+
+        let accounts = res.unwrap();
+        assert_eq!(
+            accounts.get(&1).unwrap(),
+            &Account {
+                client_id: 1,
+                available_amount: amount("0.5"),
+                held_amount: amount("0.0"),
+                total_amount: amount("0.5"),
+                is_locked: false
+            }
+        );
+        assert_eq!(
+            accounts.get(&2).unwrap(),
+            &Account {
+                client_id: 2,
+                available_amount: amount("0.0"),
+                held_amount: amount("0.0"),
+                total_amount: amount("0.0"),
+                is_locked: true
+            }
+        );
+
+        // Insufficient funds
+        assert_eq!(
+            transactions.get(&2).unwrap().status,
+            TransactionStatus::Rejected
+        );
+        // Frozen account
+        assert_eq!(
+            transactions.get(&4).unwrap().status,
+            TransactionStatus::Rejected
+        );
+        assert_eq!(
+            transactions.get(&5).unwrap().status,
+            TransactionStatus::Processed
+        );
+
+        let reasons: Vec<(u32, RejectionReason)> = rejections
+            .iter()
+            .map(|rejection| (rejection.tx, rejection.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (2, RejectionReason::InsufficientFunds),
+                (2, RejectionReason::RejectedTransaction),
+                (4, RejectionReason::FrozenAccount),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_process_transactions_abort_on_error() {
+        let raw_transactions: Vec<RawTransaction> = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("1.0"))),
+            RawTransaction::new(String::from("withdrawal"), 1, 2, Some(amount("1.5"))),
+            RawTransaction::new(String::from("withdrawal"), 1, 3, Some(amount("0.5"))),
+        ];
+        let (transaction_history, mut transactions) =
+            payment_engine::preprocess(raw_transactions, EventOrder::FileOrder, &mut vec![])
+                .unwrap();
+
+        let res = process_transactions(
+            &transaction_history,
+            &mut transactions,
+            HashMap::new(),
+            ErrorPolicy::Abort,
+            &mut vec![],
+        );
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert!(matches!(
+            err.root(),
+            EngineError::Withdrawal(err) if *err == WithdrawalError::InsufficientFunds(1)
+        ));
+        assert_eq!(
+            transactions.get(&3).unwrap().status,
+            TransactionStatus::Initiated
+        );
     }
 
     #[test]
     fn test_process_dispute_event() {
         let mut account = Account::new(1);
 
-        let mut transaction = Transaction::new(TransactionType::Deposit, 1, 1, 1.0);
-        transaction.events = vec![EventType::Dispute];
+        let mut transaction = Transaction::new(TransactionType::Deposit, 1, 1, amount("1.0"));
+        transaction.events = vec![TransactionEvent::new(EventType::Dispute, 1, 1)];
 
-        let res = process_events(&mut transaction, &mut account);
+        let res = process_events(
+            &mut transaction,
+            &mut account,
+            ErrorPolicy::Abort,
+            &mut vec![],
+        );
         assert!(res.is_ok());
 
         assert_eq!(transaction.status, TransactionStatus::Disputed);
@@ -192,10 +679,18 @@ mod tests {
     fn test_process_resolve_event() {
         let mut account = Account::new(1);
 
-        let mut transaction = Transaction::new(TransactionType::Deposit, 1, 1, 1.0);
-        transaction.events = vec![EventType::Dispute, EventType::Resolve];
+        let mut transaction = Transaction::new(TransactionType::Deposit, 1, 1, amount("1.0"));
+        transaction.events = vec![
+            TransactionEvent::new(EventType::Dispute, 1, 1),
+            TransactionEvent::new(EventType::Resolve, 1, 1),
+        ];
 
-        let res = process_events(&mut transaction, &mut account);
+        let res = process_events(
+            &mut transaction,
+            &mut account,
+            ErrorPolicy::Abort,
+            &mut vec![],
+        );
         assert!(res.is_ok());
 
         assert_eq!(transaction.status, TransactionStatus::Resolved);
@@ -205,12 +700,81 @@ mod tests {
     fn test_process_chargeback_event() {
         let mut account = Account::new(1);
 
-        let mut transaction = Transaction::new(TransactionType::Deposit, 1, 1, 1.0);
-        transaction.events = vec![EventType::Dispute, EventType::Chargeback];
+        let mut transaction = Transaction::new(TransactionType::Deposit, 1, 1, amount("1.0"));
+        transaction.events = vec![
+            TransactionEvent::new(EventType::Dispute, 1, 1),
+            TransactionEvent::new(EventType::Chargeback, 1, 1),
+        ];
 
-        let res = process_events(&mut transaction, &mut account);
+        let res = process_events(
+            &mut transaction,
+            &mut account,
+            ErrorPolicy::Abort,
+            &mut vec![],
+        );
         assert!(res.is_ok());
 
         assert_eq!(transaction.status, TransactionStatus::Reversed);
     }
+
+    #[test]
+    fn test_run() {
+        for event_order in [EventOrder::FileOrder, EventOrder::Grouped] {
+            let mut raw_transactions = vec![
+                RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("1.0"))),
+                RawTransaction::new(String::from("withdrawal"), 1, 2, Some(amount("1.5"))),
+                RawTransaction::new(String::from("dispute"), 1, 3, None),
+            ];
+
+            // Rejections are ordered by position in the input file
+            for (line, raw_transaction) in raw_transactions.iter_mut().enumerate() {
+                raw_transaction.position = Position::new(line as u64 + 2);
+            }
+
+            let source = MemorySource::new(raw_transactions);
+            let mut sink = MemorySink::default();
+
+            let res = run(source, &mut sink, event_order, ErrorPolicy::Skip);
+            assert!(res.is_ok());
+
+            assert_eq!(
+                sink.accounts,
+                vec![RawAccount::new(
+                    1,
+                    amount("1.0"),
+                    amount("0.0"),
+                    amount("1.0"),
+                    false
+                )]
+            );
+
+            let reasons: Vec<RejectionReason> = sink
+                .rejections
+                .iter()
+                .map(|rejection| rejection.reason)
+                .collect();
+            assert_eq!(
+                reasons,
+                vec![
+                    RejectionReason::InsufficientFunds,
+                    RejectionReason::UnknownTransaction
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_abort_on_error() {
+        let source = MemorySource::new(vec![RawTransaction::new(
+            String::from("withdrawal"),
+            1,
+            1,
+            Some(amount("1.0")),
+        )]);
+        let mut sink = MemorySink::default();
+
+        let res = run(source, &mut sink, EventOrder::FileOrder, ErrorPolicy::Abort);
+        assert!(res.is_err());
+        assert!(sink.accounts.is_empty());
+    }
 }
@@ -0,0 +1,286 @@
+use super::{Engine, Snapshot};
+use crate::{
+    errors::{EngineError, SnapshotError},
+    input::TransactionSource,
+    models::{Position, Rejection},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+/*
+    A checkpoint is the state of the engine after a number of input records, so that an interrupted run doesn't start over
+    In contrast to a snapshot, a checkpoint belongs to a single run and includes the rejections so far
+    The checkpointer records the position of the next input record; a resumed run seeks the input to this position instead of reading all preceding records
+*/
+
+pub const DEFAULT_CHECKPOINT_INTERVAL: u64 = 100_000;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    // Number of input records applied to the engine
+    pub records: u64,
+    // Position of the next input record; none if the input is recovered via the write-ahead log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+    pub snapshot: Snapshot,
+    pub rejections: Vec<Rejection>,
+}
+
+impl Checkpoint {
+    pub fn new(
+        engine: &mut Engine,
+        records: u64,
+        position: Option<Position>,
+    ) -> Result<Self, EngineError> {
+        Ok(Self {
+            records,
+            position,
+            snapshot: engine.snapshot()?,
+            rejections: engine.rejections().to_vec(),
+        })
+    }
+
+    // Restore the client accounts, transactions and rejections of the checkpoint into the engine; the configuration of the engine is kept
+    pub fn restore(self, engine: &mut Engine) -> Result<(), EngineError> {
+        self.snapshot.check_version()?;
+
+        engine.restore_state(self.snapshot)?;
+        engine.restore_rejections(self.rejections);
+
+        Ok(())
+    }
+
+    // Load the checkpoint if the file exists
+    pub fn load(checkpoint_file: &Path) -> Result<Option<Self>, EngineError> {
+        if !checkpoint_file.exists() {
+            return Ok(None);
+        }
+
+        let reader = BufReader::new(File::open(checkpoint_file)?);
+        let checkpoint = serde_json::from_reader(reader)?;
+
+        Ok(Some(checkpoint))
+    }
+
+    // Replace the checkpoint atomically so that a crash leaves either the old or the new checkpoint
+    pub fn save(&self, checkpoint_file: &Path) -> Result<(), EngineError> {
+        let mut tmp_file = checkpoint_file.as_os_str().to_owned();
+        tmp_file.push(".tmp");
+
+        let mut writer = BufWriter::new(File::create(&tmp_file)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+
+        fs::rename(&tmp_file, checkpoint_file)?;
+
+        Ok(())
+    }
+}
+
+// Writes a checkpoint with the position of the next input record every N records
+pub struct Checkpointer {
+    checkpoint_file: String,
+    checkpoint_interval: u64,
+    // Number of input records applied to the engine
+    records: u64,
+    // Number of input records applied since the last checkpoint
+    pending: u64,
+}
+
+impl Checkpointer {
+    pub fn new(checkpoint_file: &str, checkpoint_interval: u64) -> Self {
+        Self {
+            checkpoint_file: checkpoint_file.to_string(),
+            checkpoint_interval: checkpoint_interval.max(1),
+            records: 0,
+            pending: 0,
+        }
+    }
+
+    // Restore the state of an interrupted run into the engine and return the position to continue reading the input
+    // The state of the engine is only replaced if there is a checkpoint; its configuration, e.g. the transaction store, is kept either way
+    pub fn resume(&mut self, engine: &mut Engine) -> Result<Option<Position>, EngineError> {
+        let checkpoint = match Checkpoint::load(Path::new(&self.checkpoint_file))? {
+            Some(checkpoint) => checkpoint,
+            None => return Ok(None),
+        };
+
+        // Without position the input would be read from the beginning and the records would be booked twice
+        let position = match &checkpoint.position {
+            Some(position) => position.clone(),
+            None => return Err(SnapshotError::MissingPosition.into()),
+        };
+
+        self.records = checkpoint.records;
+        checkpoint.restore(engine)?;
+
+        Ok(Some(position))
+    }
+
+    // Number of input records applied to the engine, including the records of an interrupted run
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    pub fn apply_all(
+        &mut self,
+        engine: &mut Engine,
+        source: impl TransactionSource,
+    ) -> Result<(), EngineError> {
+        for raw_transaction in source {
+            let raw_transaction = raw_transaction?;
+
+            // Checkpoint before applying, so that the position of the next input record is known
+            if self.pending >= self.checkpoint_interval {
+                Checkpoint::new(engine, self.records, Some(raw_transaction.position.clone()))?
+                    .save(Path::new(&self.checkpoint_file))?;
+                self.pending = 0;
+            }
+
+            engine.apply(raw_transaction)?;
+            self.records += 1;
+            self.pending += 1;
+        }
+
+        Ok(())
+    }
+
+    // Remove the checkpoint after the run completed
+    pub fn close(self) -> Result<(), EngineError> {
+        let path = Path::new(&self.checkpoint_file);
+
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        csv::CsvSource,
+        models::RejectionReason,
+        payment_engine::{DiskIndex, DisputeWindow, ErrorPolicy, TransactionStore},
+    };
+
+    const INPUT: &str = "test_data/transactions_resume.csv";
+
+    // Positions of restored transactions are not part of the checkpoint; compare the serialized state
+    fn state(engine: &mut Engine) -> String {
+        let mut output = vec![];
+        engine.snapshot().unwrap().write(&mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn uninterrupted() -> Engine {
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip);
+        engine.apply_all(CsvSource::open(INPUT).unwrap()).unwrap();
+        engine
+    }
+
+    #[test]
+    fn test_resume() {
+        fs::create_dir_all("tmp").unwrap();
+        let checkpoint_file = "tmp/checkpoint_resume.json";
+        let _ = fs::remove_file(checkpoint_file);
+
+        // Crash after 6 records with checkpoints every 2 records
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip);
+        let mut checkpointer = Checkpointer::new(checkpoint_file, 2);
+        let res = checkpointer.apply_all(&mut engine, CsvSource::open(INPUT).unwrap().take(6));
+        assert!(res.is_ok());
+
+        // The last checkpoint was written before the 5th record
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip);
+        let mut checkpointer = Checkpointer::new(checkpoint_file, 2);
+        let res = checkpointer.resume(&mut engine);
+        assert!(res.is_ok());
+        assert_eq!(checkpointer.records(), 4);
+
+        let position = res.unwrap().unwrap();
+        assert_eq!(position.line, 6);
+        assert_eq!(position.record, 5);
+
+        let source = CsvSource::resume(INPUT, &position).unwrap();
+        let res = checkpointer.apply_all(&mut engine, source);
+        assert!(res.is_ok());
+        assert_eq!(checkpointer.records(), 9);
+
+        let mut expected = uninterrupted();
+        assert_eq!(state(&mut engine), state(&mut expected));
+        assert_eq!(engine.rejections(), expected.rejections());
+        assert_eq!(
+            engine.rejections()[0].reason,
+            RejectionReason::InsufficientFunds
+        );
+
+        assert!(checkpointer.close().is_ok());
+        assert!(!Path::new(checkpoint_file).exists());
+    }
+
+    // Engine with a disk index and a dispute window
+    fn configured(index_file: &str) -> Engine {
+        Engine::with_error_policy(ErrorPolicy::Skip)
+            .with_store(TransactionStore::on_disk(
+                DiskIndex::create(index_file, 1).unwrap(),
+            ))
+            .unwrap()
+            .with_dispute_window(DisputeWindow::Records(3))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_resume_keeps_configuration() {
+        fs::create_dir_all("tmp").unwrap();
+        let checkpoint_file = "tmp/checkpoint_configuration.json";
+        let _ = fs::remove_file(checkpoint_file);
+
+        let mut engine = configured("tmp/checkpoint_configuration_crash.bin");
+        let mut checkpointer = Checkpointer::new(checkpoint_file, 2);
+        let res = checkpointer.apply_all(&mut engine, CsvSource::open(INPUT).unwrap().take(6));
+        assert!(res.is_ok());
+
+        let index_file = "tmp/checkpoint_configuration.bin";
+        let mut engine = configured(index_file);
+        let mut checkpointer = Checkpointer::new(checkpoint_file, 2);
+        let position = checkpointer.resume(&mut engine).unwrap().unwrap();
+
+        // The restored transactions are moved to the disk index of the engine
+        assert_eq!(engine.dispute_window(), DisputeWindow::Records(3));
+        assert!(fs::metadata(index_file).unwrap().len() > 0);
+
+        let source = CsvSource::resume(INPUT, &position).unwrap();
+        let res = checkpointer.apply_all(&mut engine, source);
+        assert!(res.is_ok());
+
+        let mut expected = configured("tmp/checkpoint_configuration_expected.bin");
+        expected.apply_all(CsvSource::open(INPUT).unwrap()).unwrap();
+        assert_eq!(state(&mut engine), state(&mut expected));
+        assert_eq!(engine.rejections(), expected.rejections());
+        assert!(engine
+            .rejections()
+            .iter()
+            .any(|rejection| rejection.reason == RejectionReason::OutsideDisputeWindow));
+
+        assert!(checkpointer.close().is_ok());
+    }
+
+    #[test]
+    fn test_resume_without_checkpoint() {
+        let mut engine = Engine::new();
+        let mut checkpointer = Checkpointer::new("tmp/checkpoint_missing.json", 2);
+
+        let res = checkpointer.resume(&mut engine);
+        assert!(res.is_ok());
+        assert!(res.unwrap().is_none());
+        assert_eq!(checkpointer.records(), 0);
+    }
+}
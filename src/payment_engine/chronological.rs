@@ -0,0 +1,370 @@
+use crate::{
+    errors::{EngineError, FormatError},
+    input::TransactionSource,
+    models::{RawTransaction, Timestamp},
+};
+use chrono::TimeDelta;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+};
+
+/*
+    Chronological ordering of the records by their timestamp, e.g. for merged exports of several gateways which are only sorted per gateway
+    Records are held back until no record within the tolerance can precede them anymore, i.e. until a record with a timestamp of at least their timestamp plus the tolerance is read
+    Held back records are released in the order of their timestamps; records with the same timestamp keep the order of the input data
+    Records which arrive later than the tolerance are released as they are; the engine rejects them if they are earlier than the last booked record
+    Records without timestamp can't be ordered and abort processing with a format error
+    Several inputs which are each ordered by timestamp are merged into a single ordered source by MergedSource
+*/
+
+// Record which is held back; ordered by timestamp and by the order of the input data on ties
+#[derive(Debug)]
+struct Pending {
+    timestamp: Timestamp,
+    // Position in the input data or index of the input
+    sequence: u64,
+    raw_transaction: RawTransaction,
+}
+
+impl Pending {
+    fn key(&self) -> (Timestamp, u64) {
+        (self.timestamp, self.sequence)
+    }
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+pub struct ChronologicalSource<S> {
+    source: S,
+    tolerance: TimeDelta,
+    // Min-heap of the held back records; the earliest record comes first
+    pending: BinaryHeap<Reverse<Pending>>,
+    // Latest timestamp read so far
+    latest: Option<Timestamp>,
+    sequence: u64,
+    // First error of the source; passed on once the records read before it are released
+    error: Option<EngineError>,
+    is_exhausted: bool,
+}
+
+impl<S: TransactionSource> ChronologicalSource<S> {
+    pub fn new(source: S, tolerance: TimeDelta) -> Self {
+        Self {
+            source,
+            tolerance,
+            pending: BinaryHeap::new(),
+            latest: None,
+            sequence: 0,
+            error: None,
+            is_exhausted: false,
+        }
+    }
+
+    fn hold_back(&mut self, raw_transaction: RawTransaction) -> Result<(), EngineError> {
+        let timestamp = timestamp(&raw_transaction)?;
+
+        self.latest = self.latest.max(Some(timestamp));
+        self.sequence += 1;
+        self.pending.push(Reverse(Pending {
+            timestamp,
+            sequence: self.sequence,
+            raw_transaction,
+        }));
+
+        Ok(())
+    }
+
+    // Earliest held back record once the latest timestamp reaches its timestamp plus the tolerance; all records once the source is exhausted
+    fn release(&mut self) -> Option<RawTransaction> {
+        let Reverse(earliest) = self.pending.peek()?;
+
+        if !self.is_exhausted {
+            let released_until = earliest.timestamp.checked_add_signed(self.tolerance)?;
+
+            if self.latest? < released_until {
+                return None;
+            }
+        }
+
+        self.pending
+            .pop()
+            .map(|Reverse(pending)| pending.raw_transaction)
+    }
+}
+
+impl<S: TransactionSource> Iterator for ChronologicalSource<S> {
+    type Item = Result<RawTransaction, EngineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(raw_transaction) = self.release() {
+                return Some(Ok(raw_transaction));
+            }
+
+            if self.is_exhausted {
+                return self.error.take().map(Err);
+            }
+
+            // Nothing is read after the first error
+            match self.source.next() {
+                Some(Ok(raw_transaction)) => {
+                    if let Err(err) = self.hold_back(raw_transaction) {
+                        self.error = Some(err);
+                        self.is_exhausted = true;
+                    }
+                }
+                Some(Err(err)) => {
+                    self.error = Some(err);
+                    self.is_exhausted = true;
+                }
+                None => self.is_exhausted = true,
+            }
+        }
+    }
+}
+
+// Merge of several sources which are each ordered by timestamp, e.g. one export per payment gateway
+pub struct MergedSource<S> {
+    sources: Vec<S>,
+    // Min-heap of the next record of every source which isn't exhausted; records with the same timestamp are taken from the first source first
+    heads: BinaryHeap<Reverse<Pending>>,
+    // First error of a source; passed on once the records read before it are released
+    error: Option<EngineError>,
+    is_started: bool,
+}
+
+impl<S: TransactionSource> MergedSource<S> {
+    pub fn new(sources: Vec<S>) -> Self {
+        Self {
+            sources,
+            heads: BinaryHeap::new(),
+            error: None,
+            is_started: false,
+        }
+    }
+
+    // Read the next record of the source; nothing is read after the first error
+    fn advance(&mut self, index: usize) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let res = match self.sources[index].next() {
+            Some(Ok(raw_transaction)) => timestamp(&raw_transaction).map(|timestamp| {
+                self.heads.push(Reverse(Pending {
+                    timestamp,
+                    sequence: index as u64,
+                    raw_transaction,
+                }));
+            }),
+            Some(Err(err)) => Err(err),
+            None => Ok(()),
+        };
+
+        if let Err(err) = res {
+            self.error = Some(err);
+        }
+    }
+}
+
+impl<S: TransactionSource> Iterator for MergedSource<S> {
+    type Item = Result<RawTransaction, EngineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.is_started {
+            self.is_started = true;
+
+            for index in 0..self.sources.len() {
+                self.advance(index);
+            }
+        }
+
+        match self.heads.pop() {
+            Some(Reverse(earliest)) => {
+                self.advance(earliest.sequence as usize);
+                Some(Ok(earliest.raw_transaction))
+            }
+            None => self.error.take().map(Err),
+        }
+    }
+}
+
+// Records without timestamp can't be ordered
+fn timestamp(raw_transaction: &RawTransaction) -> Result<Timestamp, EngineError> {
+    raw_transaction.timestamp.ok_or_else(|| {
+        EngineError::from(FormatError::MissingTimestamp(
+            raw_transaction.tx,
+            raw_transaction.r#type.to_string(),
+        ))
+        .at(&raw_transaction.position)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{input::MemorySource, models::Position};
+
+    fn timestamp(timestamp: &str) -> Timestamp {
+        timestamp.parse().unwrap()
+    }
+
+    fn record(tx: u32, time: &str) -> RawTransaction {
+        RawTransaction::new("deposit", 1, tx, Some("1.0".parse().unwrap()))
+            .with_timestamp(timestamp(time))
+    }
+
+    fn ids(source: impl TransactionSource) -> Vec<u32> {
+        source
+            .map(|raw_transaction| raw_transaction.unwrap().tx)
+            .collect()
+    }
+
+    #[test]
+    fn test_chronological_source() {
+        let raw_transactions = vec![
+            record(1, "2024-05-01T12:00:10Z"),
+            record(2, "2024-05-01T12:00:00Z"),
+            record(3, "2024-05-01T12:00:20Z"),
+            // Same timestamp as transaction 1; keeps the order of the input data
+            record(4, "2024-05-01T12:00:10Z"),
+            record(5, "2024-05-01T12:00:05Z"),
+        ];
+
+        let source =
+            ChronologicalSource::new(MemorySource::new(raw_transactions), TimeDelta::seconds(15));
+        assert_eq!(ids(source), vec![2, 5, 1, 4, 3]);
+    }
+
+    #[test]
+    fn test_chronological_source_late_arrival() {
+        let raw_transactions = vec![
+            record(1, "2024-05-01T12:00:00Z"),
+            record(2, "2024-05-01T12:01:00Z"),
+            record(3, "2024-05-01T12:02:00Z"),
+            // Beyond the tolerance: transaction 1 is released already
+            record(4, "2024-05-01T11:59:00Z"),
+        ];
+
+        let source = ChronologicalSource::new(
+            MemorySource::new(raw_transactions.clone()),
+            TimeDelta::seconds(90),
+        );
+        assert_eq!(ids(source), vec![1, 4, 2, 3]);
+
+        // Without tolerance, every record is released as soon as it is read
+        let source =
+            ChronologicalSource::new(MemorySource::new(raw_transactions), TimeDelta::zero());
+        assert_eq!(ids(source), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_chronological_source_tolerance_boundary() {
+        let raw_transactions = vec![
+            record(1, "2024-05-01T12:00:00Z"),
+            // Exactly the tolerance later: transaction 1 is released
+            record(2, "2024-05-01T12:00:10Z"),
+            // Earlier than transaction 1, but more than the tolerance late
+            record(3, "2024-05-01T11:59:59Z"),
+            // Exactly the tolerance late; still ordered before transaction 2
+            record(4, "2024-05-01T12:00:00Z"),
+        ];
+
+        let source =
+            ChronologicalSource::new(MemorySource::new(raw_transactions), TimeDelta::seconds(10));
+        assert_eq!(ids(source), vec![1, 3, 4, 2]);
+    }
+
+    #[test]
+    fn test_chronological_source_missing_timestamp() {
+        let mut raw_transactions = vec![
+            record(1, "2024-05-01T12:00:10Z"),
+            record(2, "2024-05-01T12:00:00Z"),
+            RawTransaction::new("deposit", 1, 3, Some("1.0".parse().unwrap())),
+            record(4, "2024-05-01T12:00:00Z"),
+        ];
+        raw_transactions[2].position = Position::new(4);
+
+        let mut source =
+            ChronologicalSource::new(MemorySource::new(raw_transactions), TimeDelta::minutes(1));
+
+        // Records read before the error are released first
+        assert_eq!(source.next().unwrap().unwrap().tx, 2);
+        assert_eq!(source.next().unwrap().unwrap().tx, 1);
+
+        let err = source.next().unwrap().unwrap_err();
+        assert!(matches!(
+            err.root(),
+            EngineError::Format(FormatError::MissingTimestamp(3, _))
+        ));
+        assert_eq!(err.position(), Some(&Position::new(4)));
+        assert!(source.next().is_none());
+    }
+
+    #[test]
+    fn test_merged_source() {
+        let first = vec![
+            record(1, "2024-05-01T12:00:00Z"),
+            record(2, "2024-05-01T12:00:10Z"),
+            record(3, "2024-05-01T12:00:30Z"),
+        ];
+        let second = vec![
+            record(4, "2024-05-01T11:59:00Z"),
+            // Same timestamp as transaction 2; the first source comes first
+            record(5, "2024-05-01T12:00:10Z"),
+        ];
+
+        let source = MergedSource::new(vec![
+            MemorySource::new(first),
+            MemorySource::new(vec![]),
+            MemorySource::new(second),
+        ]);
+        assert_eq!(ids(source), vec![4, 1, 2, 5, 3]);
+    }
+
+    #[test]
+    fn test_merged_source_missing_timestamp() {
+        let first = vec![
+            record(1, "2024-05-01T12:00:00Z"),
+            record(2, "2024-05-01T12:00:30Z"),
+        ];
+        let second = vec![
+            record(3, "2024-05-01T12:00:10Z"),
+            RawTransaction::new("deposit", 1, 4, Some("1.0".parse().unwrap())),
+        ];
+
+        let mut source =
+            MergedSource::new(vec![MemorySource::new(first), MemorySource::new(second)]);
+
+        // Records read before the error are released first
+        assert_eq!(source.next().unwrap().unwrap().tx, 1);
+        assert_eq!(source.next().unwrap().unwrap().tx, 3);
+        assert_eq!(source.next().unwrap().unwrap().tx, 2);
+
+        let err = source.next().unwrap().unwrap_err();
+        assert!(matches!(
+            err.root(),
+            EngineError::Format(FormatError::MissingTimestamp(4, _))
+        ));
+        assert!(source.next().is_none());
+    }
+}
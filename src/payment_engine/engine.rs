@@ -0,0 +1,1404 @@
+use super::{
+    id_set::IdSet, process_event, process_transaction, reject_event, reject_transaction,
+    BookingError, DisputeWindow, DisputeWindowState, ErrorPolicy, Snapshot, TransactionStore,
+    WindowEntry,
+};
+use crate::{
+    errors::{EngineError, FormatError, OrderError, SnapshotError},
+    input::TransactionSource,
+    models::{
+        Account, CheckedTransaction, RawTransaction, Rejection, RejectionReason, Timestamp,
+        TransactionStatus,
+    },
+};
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    mem,
+};
+
+/*
+    Incremental processing: each raw transaction is validated and booked as soon as it is applied
+    Transaction events (dispute, resolve, chargeback) are applied in the order of the input data
+    Peak memory is bounded by the number of disputable transactions (deposit, withdrawal) rather than the size of the input
+    The state of the engine can be saved as a snapshot and restored in the next run
+    With a dispute window, transactions are finalized and evicted from the store once the given number of records has been applied after them, or once the given time has passed
+    The time of the engine is the latest timestamp applied so far; transactions entered before the first timestamp count from it
+    Only the ids of finalized transactions are kept, so that transaction events referencing them are reported as outside the dispute window
+    In chronological order, records with a timestamp earlier than the last booked record are rejected as late arrivals (see ChronologicalSource)
+*/
+
+#[derive(Debug, Default)]
+pub struct Engine {
+    // Use hash map for storing accounts; search, insertion and update is O(1)
+    accounts: HashMap<u16, Account>,
+    // Keep processed transactions as they can be referenced by subsequent transaction events; only the fields needed by disputes are stored
+    transactions: TransactionStore,
+    rejections: Vec<Rejection>,
+    error_policy: ErrorPolicy,
+    dispute_window: DisputeWindow,
+    // Number of records applied so far
+    records: u64,
+    // Latest timestamp applied so far
+    time: Option<Timestamp>,
+    // Transaction ids in the order of booking with the number of the record and the time that booked them; the oldest transaction comes first
+    // Only used with a limited dispute window
+    window: VecDeque<WindowEntry>,
+    // Ids of the transactions evicted from the store; taken ids can't be reused
+    // Kept as a compact bitmap of about one bit per id instead of the full transactions
+    finalized: IdSet,
+    // Reject records which are earlier than the last booked record
+    is_chronological: bool,
+    last_timestamp: Option<Timestamp>,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_error_policy(error_policy: ErrorPolicy) -> Self {
+        Self {
+            error_policy,
+            ..Default::default()
+        }
+    }
+
+    // Start from the opening balances of the client accounts
+    pub fn with_accounts(accounts: HashMap<u16, Account>, error_policy: ErrorPolicy) -> Self {
+        Self {
+            accounts,
+            error_policy,
+            ..Default::default()
+        }
+    }
+
+    // Continue with the client accounts and transactions of a previous run
+    pub fn restore(snapshot: Snapshot, error_policy: ErrorPolicy) -> Result<Self, EngineError> {
+        let mut engine = Self::with_error_policy(error_policy);
+
+        for account in snapshot.accounts {
+            match engine.accounts.entry(account.client_id) {
+                Entry::Occupied(_) => {
+                    return Err(SnapshotError::DuplicateClientId(account.client_id).into())
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(account);
+                }
+            }
+        }
+
+        for tx in snapshot.transactions {
+            if !engine.accounts.contains_key(&tx.client_id) {
+                return Err(SnapshotError::UnknownClientId(tx.transaction_id, tx.client_id).into());
+            }
+
+            if !engine.transactions.insert(&tx)? {
+                return Err(SnapshotError::DuplicateTransactionId(tx.transaction_id).into());
+            }
+        }
+
+        engine.last_timestamp = snapshot.last_timestamp;
+
+        let state = snapshot.dispute_window;
+        engine.records = state.records;
+        engine.time = state.time;
+        engine.window = state.window.into();
+        engine.finalized = IdSet::from_ranges(&state.finalized);
+
+        for transaction_id in engine.finalized.iter() {
+            if engine.transactions.contains(transaction_id)? {
+                return Err(SnapshotError::DuplicateTransactionId(transaction_id).into());
+            }
+        }
+
+        Ok(engine)
+    }
+
+    // Keep the transactions in the given store, e.g. a disk index; transactions restored so far are moved to the store
+    pub fn with_store(mut self, mut transactions: TransactionStore) -> Result<Self, EngineError> {
+        transactions.extend(mem::take(&mut self.transactions))?;
+        self.transactions = transactions;
+
+        Ok(self)
+    }
+
+    // Finalize transactions once their dispute window has passed
+    // Restored transactions keep their place in the window of the previous run; transactions restored without it enter the window at the start of the run
+    pub fn with_dispute_window(
+        mut self,
+        dispute_window: DisputeWindow,
+    ) -> Result<Self, EngineError> {
+        self.dispute_window = dispute_window;
+
+        if dispute_window != DisputeWindow::Unlimited {
+            let in_window: IdSet = self
+                .window
+                .iter()
+                .map(|entry| entry.transaction_id)
+                .collect();
+
+            let mut transaction_ids: Vec<u32> = self
+                .transactions
+                .clients()?
+                .into_iter()
+                .map(|(transaction_id, _)| transaction_id)
+                .filter(|transaction_id| !in_window.contains(*transaction_id))
+                .collect();
+            transaction_ids.sort_unstable();
+
+            self.window.extend(
+                transaction_ids
+                    .into_iter()
+                    .map(|transaction_id| WindowEntry {
+                        record: self.records,
+                        transaction_id,
+                        timestamp: self.time,
+                    }),
+            );
+        }
+
+        Ok(self)
+    }
+
+    // Book the records in the order of their timestamps; records are expected to be ordered by the source, e.g. ChronologicalSource
+    pub fn with_chronological_order(mut self) -> Self {
+        self.is_chronological = true;
+        self
+    }
+
+    // Current state of the client accounts, transactions and dispute window; the transactions of a disk index are loaded into memory
+    pub fn snapshot(&mut self) -> Result<Snapshot, EngineError> {
+        let mut snapshot = Snapshot::new(
+            self.accounts.values().cloned().collect(),
+            self.transactions.transactions()?,
+        );
+        snapshot.last_timestamp = self.last_timestamp;
+
+        if self.dispute_window != DisputeWindow::Unlimited
+            || !self.window.is_empty()
+            || !self.finalized.is_empty()
+        {
+            snapshot.dispute_window = DisputeWindowState {
+                records: self.records,
+                time: self.time,
+                window: self.window.iter().copied().collect(),
+                finalized: self.finalized.ranges(),
+            };
+        }
+
+        Ok(snapshot)
+    }
+
+    // Apply all raw transactions of the source in the order of the input data
+    pub fn apply_all(&mut self, source: impl TransactionSource) -> Result<(), EngineError> {
+        for raw_transaction in source {
+            self.apply(raw_transaction?)?;
+        }
+
+        Ok(())
+    }
+
+    // Validate the raw transaction and book it on the client account
+    pub fn apply(&mut self, raw_transaction: RawTransaction) -> Result<(), EngineError> {
+        self.apply_checked(check(raw_transaction)?)
+    }
+
+    // Book the validated transaction or transaction event on the client account
+    pub fn apply_checked(
+        &mut self,
+        checked_transaction: CheckedTransaction,
+    ) -> Result<(), EngineError> {
+        self.records += 1;
+        self.advance_time(checked_transaction.timestamp());
+        self.finalize()?;
+
+        let order = self.check_order(&checked_transaction);
+
+        match checked_transaction {
+            CheckedTransaction::Transaction(mut tx) => {
+                if self.transactions.contains(tx.transaction_id)?
+                    || self.finalized.contains(tx.transaction_id)
+                {
+                    return Err(EngineError::from(FormatError::UniqueTransactionId(
+                        tx.transaction_id,
+                    ))
+                    .at(&tx.position));
+                }
+
+                let account = self
+                    .accounts
+                    .entry(tx.client_id)
+                    .or_insert_with(|| Account::new(tx.client_id));
+
+                let res = order
+                    .map_err(BookingError::from)
+                    .and_then(|()| process_transaction(&mut tx, account));
+
+                if let Err(err) = res {
+                    reject_transaction(&mut tx, err, self.error_policy, &mut self.rejections)?;
+                }
+
+                self.transactions.insert(&tx)?;
+
+                if self.dispute_window != DisputeWindow::Unlimited {
+                    self.window.push_back(WindowEntry {
+                        record: self.records,
+                        transaction_id: tx.transaction_id,
+                        timestamp: self.time,
+                    });
+                }
+            }
+            CheckedTransaction::TransactionEvent(event) => {
+                if let Err(err) = order {
+                    return reject_event(
+                        &event,
+                        err.into(),
+                        self.error_policy,
+                        &mut self.rejections,
+                    );
+                }
+
+                match self.transactions.get(event.transaction_id)? {
+                    Some(mut tx) => {
+                        // Assumption: client_id and transaction_id of the transaction event have to coincide with the actual transaction; ignore if this is not the case
+                        if tx.client_id != event.client_id {
+                            self.rejections.push(Rejection::from_event(
+                                &event,
+                                RejectionReason::ClientMismatch,
+                            ));
+                            return Ok(());
+                        }
+
+                        if let Some(account) = self.accounts.get_mut(&tx.client_id) {
+                            process_event(
+                                &mut tx,
+                                account,
+                                &event,
+                                self.error_policy,
+                                &mut self.rejections,
+                            )?;
+                            self.transactions.set_status(tx.transaction_id, tx.status)?;
+                        }
+                    }
+                    // Assumption: transaction events which do not reference a valid transaction_id can be ignored
+                    None => {
+                        let reason = if self.finalized.contains(event.transaction_id) {
+                            RejectionReason::OutsideDisputeWindow
+                        } else {
+                            RejectionReason::UnknownTransaction
+                        };
+
+                        self.rejections.push(Rejection::from_event(&event, reason));
+                    }
+                }
+            }
+        };
+
+        Ok(())
+    }
+
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.error_policy
+    }
+
+    pub fn dispute_window(&self) -> DisputeWindow {
+        self.dispute_window
+    }
+
+    pub fn account(&self, client_id: u16) -> Option<&Account> {
+        self.accounts.get(&client_id)
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = &Account> {
+        self.accounts.values()
+    }
+
+    // Rejected transactions and ignored transaction events so far
+    pub fn rejections(&self) -> &[Rejection] {
+        &self.rejections
+    }
+
+    // Split client accounts and transactions into shards by client id; rejections, the dispute window and the timestamps are kept by the first shard
+    // Every shard knows the finalized transactions, so that transaction events referencing them are reported as outside the dispute window
+    // The dispute window is not applied by the shards as the number of records differs per shard
+    // The transactions of a disk index are loaded into memory
+    pub(crate) fn split(self, shards: usize) -> Result<Vec<Engine>, EngineError> {
+        let mut engines: Vec<Engine> = (0..shards)
+            .map(|_| Engine::with_error_policy(self.error_policy))
+            .collect();
+
+        for (client_id, account) in self.accounts {
+            engines[shard(client_id, shards)]
+                .accounts
+                .insert(client_id, account);
+        }
+
+        let stores = self
+            .transactions
+            .split(shards, |client_id| shard(client_id, shards))?;
+
+        for (engine, transactions) in engines.iter_mut().zip(stores) {
+            engine.transactions = transactions;
+        }
+
+        for engine in engines.iter_mut().skip(1) {
+            engine.finalized = self.finalized.clone();
+        }
+
+        engines[0].rejections = self.rejections;
+        engines[0].records = self.records;
+        engines[0].window = self.window;
+        engines[0].finalized = self.finalized;
+        engines[0].time = self.time;
+        engines[0].last_timestamp = self.last_timestamp;
+
+        Ok(engines)
+    }
+
+    // Merge the shard of another engine; shards don't share client accounts or transactions
+    pub(crate) fn merge(&mut self, other: Engine) -> Result<(), EngineError> {
+        self.accounts.extend(other.accounts);
+        self.transactions.extend(other.transactions)?;
+        self.rejections.extend(other.rejections);
+        self.records += other.records;
+        self.window.extend(other.window);
+        self.finalized.extend(other.finalized.iter());
+        self.time = self.time.max(other.time);
+        self.last_timestamp = self.last_timestamp.max(other.last_timestamp);
+
+        Ok(())
+    }
+
+    // Ids of the transactions finalized so far; they can't be reused
+    pub(crate) fn finalized(&self) -> &IdSet {
+        &self.finalized
+    }
+
+    // Client id of every transaction
+    pub(crate) fn transaction_clients(&mut self) -> Result<Vec<(u32, u16)>, EngineError> {
+        self.transactions.clients()
+    }
+
+    // Replace the state of the engine with the snapshot of an interrupted run
+    // The configuration of the engine is kept, i.e. its transaction store, dispute window and chronological order
+    pub(crate) fn restore_state(&mut self, snapshot: Snapshot) -> Result<(), EngineError> {
+        let restored = Engine::restore(snapshot, self.error_policy)?;
+        let transactions = mem::take(&mut self.transactions);
+
+        *self = Engine {
+            is_chronological: self.is_chronological,
+            ..restored
+                .with_store(transactions)?
+                .with_dispute_window(self.dispute_window)?
+        };
+
+        Ok(())
+    }
+
+    // Rejections of an interrupted run which are restored from a checkpoint
+    pub(crate) fn restore_rejections(&mut self, rejections: Vec<Rejection>) {
+        self.rejections = rejections;
+    }
+
+    // Records must not be earlier than the last booked record in chronological order; records without timestamp are not checked
+    fn check_order(&mut self, checked_transaction: &CheckedTransaction) -> Result<(), OrderError> {
+        let Some(timestamp) = checked_transaction.timestamp() else {
+            return Ok(());
+        };
+
+        if !self.is_chronological {
+            return Ok(());
+        }
+
+        match self.last_timestamp {
+            Some(last_timestamp) if timestamp < last_timestamp => {
+                let r#type = match checked_transaction {
+                    CheckedTransaction::Transaction(tx) => tx.transaction_type.as_str(),
+                    CheckedTransaction::TransactionEvent(event) => event.event_type.as_str(),
+                };
+
+                Err(OrderError::LateArrival(
+                    r#type.to_string(),
+                    checked_transaction.transaction_id(),
+                    timestamp,
+                    last_timestamp,
+                ))
+            }
+            _ => {
+                self.last_timestamp = Some(timestamp);
+                Ok(())
+            }
+        }
+    }
+
+    // The time only moves forward; transactions which entered the window before the first timestamp count from it
+    fn advance_time(&mut self, timestamp: Option<Timestamp>) {
+        let Some(timestamp) = timestamp else {
+            return;
+        };
+
+        match self.time {
+            Some(time) if timestamp <= time => {}
+            Some(_) => self.time = Some(timestamp),
+            None => {
+                self.time = Some(timestamp);
+
+                for entry in self.window.iter_mut() {
+                    entry.timestamp.get_or_insert(timestamp);
+                }
+            }
+        }
+    }
+
+    // Evict the transactions whose dispute window has passed before the current record is applied
+    fn finalize(&mut self) -> Result<(), EngineError> {
+        while let Some(&WindowEntry {
+            record,
+            transaction_id,
+            timestamp,
+        }) = self.window.front()
+        {
+            let is_open = match self.dispute_window {
+                DisputeWindow::Unlimited => return Ok(()),
+                DisputeWindow::Records(window) => self.records - record <= window,
+                DisputeWindow::Duration(window) => match (self.time, timestamp) {
+                    (Some(time), Some(timestamp)) => time - timestamp <= window,
+                    _ => true,
+                },
+            };
+
+            if is_open {
+                break;
+            }
+
+            self.window.pop_front();
+
+            match self.transactions.get(transaction_id)? {
+                // Disputed transactions are kept until the dispute is resolved or charged back; checked again after another window
+                Some(tx) if tx.status == TransactionStatus::Disputed => {
+                    self.window.push_back(WindowEntry {
+                        record: self.records,
+                        transaction_id,
+                        timestamp: self.time,
+                    });
+                }
+                Some(_) => {
+                    self.transactions.remove(transaction_id)?;
+                    self.finalized.insert(transaction_id);
+                }
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    // Finish processing and hand over the client accounts and the rejections
+    pub fn finish(self) -> (HashMap<u16, Account>, Vec<Rejection>) {
+        (self.accounts, self.rejections)
+    }
+}
+
+// Check and verify input format via CheckedTransaction type
+pub(crate) fn check(raw_transaction: RawTransaction) -> Result<CheckedTransaction, EngineError> {
+    let position = raw_transaction.position.clone();
+
+    raw_transaction
+        .try_into()
+        .map_err(|err| EngineError::from(err).at(&position))
+}
+
+// Shard of the client for parallel processing
+pub(crate) fn shard(client_id: u16, shards: usize) -> usize {
+    client_id as usize % shards
+}
+
+// Process all raw transactions in a single pass
+pub fn process_stream<I, E>(
+    raw_transactions: I,
+    error_policy: ErrorPolicy,
+    rejections: &mut Vec<Rejection>,
+) -> Result<HashMap<u16, Account>, EngineError>
+where
+    I: IntoIterator<Item = Result<RawTransaction, E>>,
+    E: Into<EngineError>,
+{
+    let mut engine = Engine::with_error_policy(error_policy);
+    engine.apply_all(
+        raw_transactions
+            .into_iter()
+            .map(|res| res.map_err(Into::into)),
+    )?;
+
+    let (accounts, engine_rejections) = engine.finish();
+    rejections.extend(engine_rejections);
+
+    Ok(accounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        csv::CsvSource,
+        errors::{ErrorCategory, WithdrawalError},
+        input::MemorySource,
+        models::{Amount, Position, RawAccount, Transaction, TransactionType},
+        payment_engine::{self, ChronologicalSource, DiskIndex, EventOrder},
+    };
+    use chrono::TimeDelta;
+    use std::fs;
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    #[test]
+    fn test_engine_apply() {
+        let mut engine = Engine::new();
+
+        let res = engine.apply(RawTransaction::new(
+            String::from("deposit"),
+            1,
+            1,
+            Some(amount("2.0")),
+        ));
+        assert!(res.is_ok());
+        assert_eq!(engine.account(1).unwrap().available_amount, amount("2.0"));
+
+        let res = engine.apply(RawTransaction::new(String::from("dispute"), 1, 1, None));
+        assert!(res.is_ok());
+        assert_eq!(
+            engine.account(1).unwrap(),
+            &Account {
+                client_id: 1,
+                available_amount: amount("0.0"),
+                held_amount: amount("2.0"),
+                total_amount: amount("2.0"),
+                is_locked: false
+            }
+        );
+
+        let res = engine.apply(RawTransaction::new(
+            String::from("deposit"),
+            2,
+            2,
+            Some(amount("1.0")),
+        ));
+        assert!(res.is_ok());
+        assert_eq!(engine.accounts().count(), 2);
+        assert!(engine.account(3).is_none());
+
+        let (accounts, rejections) = engine.finish();
+        assert_eq!(accounts.len(), 2);
+        assert!(rejections.is_empty());
+    }
+
+    #[test]
+    fn test_engine_apply_invalid_format() {
+        let mut engine = Engine::new();
+
+        let res = engine.apply(RawTransaction::new(String::from("deposit"), 1, 1, None));
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert!(matches!(
+            err.root(),
+            EngineError::Format(err) if *err == FormatError::MissingAmount(1, String::from("deposit"))
+        ));
+        assert!(engine.account(1).is_none());
+    }
+
+    #[test]
+    fn test_engine_apply_error_position() {
+        let mut engine = Engine::new();
+
+        let mut raw_transaction =
+            RawTransaction::new(String::from("withdrawal"), 1, 1, Some(amount("1.0")));
+        raw_transaction.position = Position::new(7);
+
+        let res = engine.apply(raw_transaction);
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert!(matches!(
+            err.root(),
+            EngineError::Withdrawal(err) if *err == WithdrawalError::InsufficientFunds(1)
+        ));
+        assert_eq!(err.position(), Some(&Position::new(7)));
+        assert_eq!(err.category(), ErrorCategory::Booking);
+        assert_eq!(
+            err.to_string(),
+            "Can't withdraw transaction: insufficient funds for client id 1 (line 7, record 0, byte 0)"
+        );
+    }
+
+    #[test]
+    fn test_engine_rejections() {
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip);
+
+        engine
+            .apply(RawTransaction::new(
+                String::from("withdrawal"),
+                1,
+                1,
+                Some(amount("1.0")),
+            ))
+            .unwrap();
+        engine
+            .apply(RawTransaction::new(String::from("resolve"), 1, 2, None))
+            .unwrap();
+
+        let reasons: Vec<RejectionReason> = engine
+            .rejections()
+            .iter()
+            .map(|rejection| rejection.reason)
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                RejectionReason::InsufficientFunds,
+                RejectionReason::UnknownTransaction
+            ]
+        );
+    }
+
+    #[test]
+    fn test_engine_disk_index() {
+        fs::create_dir_all("tmp").unwrap();
+        let input = "test_data/transactions_resume.csv";
+
+        let mut expected = Engine::with_error_policy(ErrorPolicy::Skip);
+        expected.apply_all(CsvSource::open(input).unwrap()).unwrap();
+
+        // A cache of 2 entries evicts most transactions to the index file
+        let index = DiskIndex::create("tmp/engine_index.bin", 2).unwrap();
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip)
+            .with_store(TransactionStore::on_disk(index))
+            .unwrap();
+        engine.apply_all(CsvSource::open(input).unwrap()).unwrap();
+
+        assert_eq!(engine.rejections(), expected.rejections());
+        assert_eq!(engine.snapshot().unwrap(), expected.snapshot().unwrap());
+
+        drop(engine);
+        assert!(!std::path::Path::new("tmp/engine_index.bin").exists());
+    }
+
+    #[test]
+    fn test_engine_with_store_restored() {
+        fs::create_dir_all("tmp").unwrap();
+
+        let mut engine = Engine::new();
+        engine
+            .apply(RawTransaction::new("deposit", 1, 1, Some(amount("2.0"))))
+            .unwrap();
+
+        let restored = Engine::restore(engine.snapshot().unwrap(), ErrorPolicy::Abort).unwrap();
+        let index = DiskIndex::create("tmp/engine_index_restored.bin", 1).unwrap();
+        let mut engine = restored
+            .with_store(TransactionStore::on_disk(index))
+            .unwrap();
+
+        // Transactions of the snapshot are moved to the disk index
+        let res = engine.apply(RawTransaction::new("dispute", 1, 1, None));
+        assert!(res.is_ok());
+        assert_eq!(engine.account(1).unwrap().held_amount, amount("2.0"));
+    }
+
+    #[test]
+    fn test_engine_dispute_window() {
+        let mut engine = Engine::new()
+            .with_dispute_window(DisputeWindow::Records(1))
+            .unwrap();
+
+        for (r#type, transaction_id, amount_value) in [
+            ("deposit", 1, Some(amount("1.0"))),
+            ("deposit", 2, Some(amount("2.0"))),
+            // Within the window of transaction 2: 1 record after it
+            ("dispute", 2, None),
+            // Outside the window of transaction 1: 3 records after it
+            ("dispute", 1, None),
+        ] {
+            let res = engine.apply(RawTransaction::new(r#type, 1, transaction_id, amount_value));
+            assert!(res.is_ok());
+        }
+
+        assert_eq!(engine.account(1).unwrap().held_amount, amount("2.0"));
+        assert_eq!(
+            engine.rejections(),
+            &[Rejection {
+                file: None,
+                input: 0,
+                line: 0,
+                r#type: String::from("dispute"),
+                client: 1,
+                tx: 1,
+                reason: RejectionReason::OutsideDisputeWindow,
+            }]
+        );
+
+        // The disputed transaction is kept until the dispute is settled
+        for transaction_id in 3..=6 {
+            engine
+                .apply(RawTransaction::new(
+                    "deposit",
+                    2,
+                    transaction_id,
+                    Some(amount("1.0")),
+                ))
+                .unwrap();
+        }
+
+        engine
+            .apply(RawTransaction::new("resolve", 1, 2, None))
+            .unwrap();
+        assert_eq!(engine.account(1).unwrap().held_amount, amount("0.0"));
+
+        // Unknown transactions are still reported as such
+        engine
+            .apply(RawTransaction::new("dispute", 1, 99, None))
+            .unwrap();
+        assert_eq!(
+            engine.rejections()[1].reason,
+            RejectionReason::UnknownTransaction
+        );
+
+        // All transactions are finalized once the dispute is resolved
+        assert!(engine.snapshot().unwrap().transactions.is_empty());
+
+        // Ids of finalized transactions can't be reused
+        let res = engine.apply(RawTransaction::new("deposit", 1, 3, Some(amount("1.0"))));
+        assert!(res.is_err());
+        assert!(matches!(
+            res.unwrap_err().root(),
+            EngineError::Format(FormatError::UniqueTransactionId(3))
+        ));
+    }
+
+    #[test]
+    fn test_engine_dispute_window_disk_index() {
+        fs::create_dir_all("tmp").unwrap();
+        let input = "test_data/transactions_resume.csv";
+
+        let mut expected = Engine::with_error_policy(ErrorPolicy::Skip)
+            .with_dispute_window(DisputeWindow::Records(3))
+            .unwrap();
+        expected.apply_all(CsvSource::open(input).unwrap()).unwrap();
+
+        let index = DiskIndex::create("tmp/engine_index_window.bin", 1).unwrap();
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip)
+            .with_store(TransactionStore::on_disk(index))
+            .unwrap()
+            .with_dispute_window(DisputeWindow::Records(3))
+            .unwrap();
+        engine.apply_all(CsvSource::open(input).unwrap()).unwrap();
+
+        assert_eq!(engine.rejections(), expected.rejections());
+        assert_eq!(engine.snapshot().unwrap(), expected.snapshot().unwrap());
+    }
+
+    #[test]
+    fn test_engine_dispute_window_restored() {
+        let mut engine = Engine::new();
+        engine
+            .apply(RawTransaction::new("deposit", 1, 1, Some(amount("2.0"))))
+            .unwrap();
+
+        // Restored transactions enter the window at the start of the run
+        let mut engine = Engine::restore(engine.snapshot().unwrap(), ErrorPolicy::Abort)
+            .unwrap()
+            .with_dispute_window(DisputeWindow::Records(1))
+            .unwrap();
+
+        engine
+            .apply(RawTransaction::new("deposit", 1, 2, Some(amount("1.0"))))
+            .unwrap();
+        engine
+            .apply(RawTransaction::new("dispute", 1, 1, None))
+            .unwrap();
+
+        assert_eq!(engine.account(1).unwrap().held_amount, amount("0.0"));
+        assert_eq!(
+            engine.rejections()[0].reason,
+            RejectionReason::OutsideDisputeWindow
+        );
+    }
+
+    #[test]
+    fn test_engine_dispute_window_snapshot() {
+        let mut engine = Engine::new()
+            .with_dispute_window(DisputeWindow::Records(3))
+            .unwrap();
+
+        // Transaction 1 is finalized by the fifth record
+        for transaction_id in 1..=5 {
+            engine
+                .apply(RawTransaction::new(
+                    "deposit",
+                    1,
+                    transaction_id,
+                    Some(amount("1.0")),
+                ))
+                .unwrap();
+        }
+
+        let snapshot = engine.snapshot().unwrap();
+        assert_eq!(
+            snapshot.dispute_window,
+            DisputeWindowState {
+                records: 5,
+                time: None,
+                window: (2..=5)
+                    .map(|transaction_id| WindowEntry {
+                        record: u64::from(transaction_id),
+                        transaction_id,
+                        timestamp: None,
+                    })
+                    .collect(),
+                finalized: vec![(1, 1)],
+            }
+        );
+
+        let mut output = vec![];
+        snapshot.write(&mut output).unwrap();
+
+        // The window continues where the previous run stopped
+        let mut engine = Engine::restore(
+            Snapshot::read(output.as_slice()).unwrap(),
+            ErrorPolicy::Abort,
+        )
+        .unwrap()
+        .with_dispute_window(DisputeWindow::Records(3))
+        .unwrap();
+
+        engine
+            .apply(RawTransaction::new("dispute", 1, 1, None))
+            .unwrap();
+        engine
+            .apply(RawTransaction::new("dispute", 1, 4, None))
+            .unwrap();
+
+        assert_eq!(engine.account(1).unwrap().held_amount, amount("1.0"));
+        assert_eq!(
+            engine.rejections()[0].reason,
+            RejectionReason::OutsideDisputeWindow
+        );
+
+        // Ids finalized in the previous run can't be reused
+        let res = engine.apply(RawTransaction::new("deposit", 1, 1, Some(amount("1.0"))));
+        assert!(matches!(
+            res.unwrap_err().root(),
+            EngineError::Format(FormatError::UniqueTransactionId(1))
+        ));
+    }
+
+    #[test]
+    fn test_engine_restore_finalized_duplicate() {
+        let mut snapshot = Snapshot::new(
+            vec![Account::new(1)],
+            vec![Transaction::new(
+                TransactionType::Deposit,
+                1,
+                2,
+                amount("1.0"),
+            )],
+        );
+        snapshot.dispute_window.finalized = vec![(1, 3)];
+
+        let res = Engine::restore(snapshot, ErrorPolicy::Abort);
+        assert!(matches!(
+            res.unwrap_err(),
+            EngineError::Snapshot(SnapshotError::DuplicateTransactionId(2))
+        ));
+    }
+
+    #[test]
+    fn test_engine_dispute_window_duration() {
+        let mut engine = Engine::new()
+            .with_dispute_window(DisputeWindow::Duration(TimeDelta::seconds(60)))
+            .unwrap();
+
+        for (r#type, transaction_id, amount_value, timestamp) in [
+            ("deposit", 1, Some(amount("1.0")), "2024-05-01T12:00:00Z"),
+            ("deposit", 2, Some(amount("2.0")), "2024-05-01T12:00:30Z"),
+            // Within the window of transaction 2: 30 seconds after it
+            ("dispute", 2, None, "2024-05-01T12:01:00Z"),
+            // Outside the window of transaction 1: 61 seconds after it
+            ("dispute", 1, None, "2024-05-01T12:01:01Z"),
+            // The disputed transaction is kept until the dispute is settled
+            ("deposit", 3, Some(amount("1.0")), "2024-05-01T12:05:00Z"),
+            ("resolve", 2, None, "2024-05-01T12:05:00Z"),
+        ] {
+            engine
+                .apply(
+                    RawTransaction::new(r#type, 1, transaction_id, amount_value)
+                        .with_timestamp(timestamp.parse().unwrap()),
+                )
+                .unwrap();
+        }
+
+        assert_eq!(engine.account(1).unwrap().held_amount, amount("0.0"));
+        assert_eq!(
+            engine.rejections()[0].reason,
+            RejectionReason::OutsideDisputeWindow
+        );
+        assert_eq!(engine.rejections().len(), 1);
+
+        let timestamp = Some("2024-05-01T12:05:00Z".parse().unwrap());
+        assert_eq!(
+            engine.snapshot().unwrap().dispute_window,
+            DisputeWindowState {
+                records: 6,
+                time: timestamp,
+                window: vec![
+                    WindowEntry {
+                        record: 5,
+                        transaction_id: 2,
+                        timestamp,
+                    },
+                    WindowEntry {
+                        record: 5,
+                        transaction_id: 3,
+                        timestamp,
+                    },
+                ],
+                finalized: vec![(1, 1)],
+            }
+        );
+    }
+
+    #[test]
+    fn test_engine_dispute_window_duration_restored() {
+        let mut engine = Engine::new();
+        engine
+            .apply(RawTransaction::new("deposit", 1, 1, Some(amount("2.0"))))
+            .unwrap();
+
+        // Restored transactions without timestamp count from the first timestamp of the run
+        let mut engine = Engine::restore(engine.snapshot().unwrap(), ErrorPolicy::Abort)
+            .unwrap()
+            .with_dispute_window(DisputeWindow::Duration(TimeDelta::seconds(60)))
+            .unwrap();
+
+        for (r#type, transaction_id, amount_value, timestamp) in [
+            ("deposit", 2, Some(amount("1.0")), "2024-05-01T12:00:00Z"),
+            ("dispute", 1, None, "2024-05-01T12:01:00Z"),
+            ("resolve", 1, None, "2024-05-01T12:01:00Z"),
+            ("dispute", 1, None, "2024-05-01T12:01:01Z"),
+        ] {
+            engine
+                .apply(
+                    RawTransaction::new(r#type, 1, transaction_id, amount_value)
+                        .with_timestamp(timestamp.parse().unwrap()),
+                )
+                .unwrap();
+        }
+
+        assert_eq!(engine.account(1).unwrap().held_amount, amount("0.0"));
+        assert_eq!(
+            engine.rejections()[0].reason,
+            RejectionReason::OutsideDisputeWindow
+        );
+    }
+
+    #[test]
+    fn test_engine_chronological_order() {
+        let raw_transactions = vec![
+            RawTransaction::new("deposit", 1, 1, Some(amount("2.0")))
+                .with_timestamp("2024-05-01T12:00:00Z".parse().unwrap()),
+            RawTransaction::new("deposit", 1, 2, Some(amount("1.0")))
+                .with_timestamp("2024-05-01T12:02:00Z".parse().unwrap()),
+            // Beyond the tolerance of one minute and earlier than the booked transaction 1
+            RawTransaction::new("withdrawal", 1, 3, Some(amount("1.0")))
+                .with_timestamp("2024-05-01T11:59:30Z".parse().unwrap()),
+            RawTransaction::new("dispute", 1, 1, None)
+                .with_timestamp("2024-05-01T11:00:00Z".parse().unwrap()),
+            // Within the tolerance
+            RawTransaction::new("deposit", 1, 4, Some(amount("3.0")))
+                .with_timestamp("2024-05-01T12:01:30Z".parse().unwrap()),
+        ];
+
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip).with_chronological_order();
+        engine
+            .apply_all(ChronologicalSource::new(
+                MemorySource::new(raw_transactions.clone()),
+                TimeDelta::minutes(1),
+            ))
+            .unwrap();
+
+        assert_eq!(engine.account(1).unwrap().total_amount, amount("6.0"));
+        assert_eq!(engine.account(1).unwrap().held_amount, amount("0.0"));
+
+        let reasons: Vec<(u32, RejectionReason)> = engine
+            .rejections()
+            .iter()
+            .map(|rejection| (rejection.tx, rejection.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (3, RejectionReason::LateArrival),
+                (1, RejectionReason::LateArrival)
+            ]
+        );
+
+        // Late arrivals abort processing by default
+        let mut engine = Engine::new().with_chronological_order();
+        let res = engine.apply_all(ChronologicalSource::new(
+            MemorySource::new(raw_transactions),
+            TimeDelta::minutes(1),
+        ));
+        assert!(res.is_err());
+        assert!(matches!(
+            res.unwrap_err().root(),
+            EngineError::Order(OrderError::LateArrival(_, 3, _, _))
+        ));
+    }
+
+    #[test]
+    fn test_engine_chronological_order_restored() {
+        let mut engine = Engine::new().with_chronological_order();
+        engine
+            .apply(
+                RawTransaction::new("deposit", 1, 1, Some(amount("2.0")))
+                    .with_timestamp("2024-05-01T12:00:00Z".parse().unwrap()),
+            )
+            .unwrap();
+
+        let mut output = vec![];
+        engine.snapshot().unwrap().write(&mut output).unwrap();
+
+        let snapshot = Snapshot::read(output.as_slice()).unwrap();
+        assert_eq!(
+            snapshot.last_timestamp,
+            Some("2024-05-01T12:00:00Z".parse().unwrap())
+        );
+
+        // Records earlier than the last booked record of the previous run are late arrivals
+        let mut engine = Engine::restore(snapshot, ErrorPolicy::Skip)
+            .unwrap()
+            .with_chronological_order();
+        engine
+            .apply(
+                RawTransaction::new("deposit", 1, 2, Some(amount("1.0")))
+                    .with_timestamp("2024-05-01T11:59:59Z".parse().unwrap()),
+            )
+            .unwrap();
+
+        assert_eq!(engine.account(1).unwrap().total_amount, amount("2.0"));
+        assert_eq!(engine.rejections()[0].reason, RejectionReason::LateArrival);
+    }
+
+    #[test]
+    fn test_engine_timestamps_in_file_order() {
+        // Timestamps are not checked without chronological order
+        let mut engine = Engine::new();
+        engine
+            .apply(
+                RawTransaction::new("deposit", 1, 1, Some(amount("2.0")))
+                    .with_timestamp("2024-05-01T12:00:00Z".parse().unwrap()),
+            )
+            .unwrap();
+        engine
+            .apply(
+                RawTransaction::new("deposit", 1, 2, Some(amount("1.0")))
+                    .with_timestamp("2024-05-01T11:00:00Z".parse().unwrap()),
+            )
+            .unwrap();
+
+        assert_eq!(engine.account(1).unwrap().total_amount, amount("3.0"));
+    }
+
+    #[test]
+    fn test_engine_snapshot_restore() {
+        let mut engine = Engine::new();
+        engine
+            .apply(RawTransaction::new(
+                String::from("deposit"),
+                1,
+                1,
+                Some(amount("2.0")),
+            ))
+            .unwrap();
+        engine
+            .apply(RawTransaction::new(
+                String::from("deposit"),
+                1,
+                2,
+                Some(amount("1.0")),
+            ))
+            .unwrap();
+
+        let snapshot = engine.snapshot().unwrap();
+        assert_eq!(snapshot.accounts.len(), 1);
+        assert_eq!(snapshot.transactions.len(), 2);
+
+        // Dispute a transaction of the previous run
+        let res = Engine::restore(snapshot, ErrorPolicy::Abort);
+        assert!(res.is_ok());
+
+        let mut engine = res.unwrap();
+        let res = engine.apply(RawTransaction::new(String::from("dispute"), 1, 1, None));
+        assert!(res.is_ok());
+        assert_eq!(
+            engine.account(1).unwrap(),
+            &Account {
+                client_id: 1,
+                available_amount: amount("1.0"),
+                held_amount: amount("2.0"),
+                total_amount: amount("3.0"),
+                is_locked: false
+            }
+        );
+
+        // Transaction ids of the previous run remain unique
+        let res = engine.apply(RawTransaction::new(
+            String::from("deposit"),
+            1,
+            2,
+            Some(amount("1.0")),
+        ));
+        assert!(res.is_err());
+        assert!(matches!(
+            res.unwrap_err().root(),
+            EngineError::Format(FormatError::UniqueTransactionId(2))
+        ));
+    }
+
+    #[test]
+    fn test_engine_with_accounts() {
+        let raw_accounts = vec![
+            RawAccount::new(1, amount("1.0"), Amount::ZERO, amount("1.0"), false),
+            RawAccount::new(2, amount("2.0"), Amount::ZERO, amount("2.0"), true),
+        ];
+        let accounts = payment_engine::open_accounts(raw_accounts).unwrap();
+        let mut engine = Engine::with_accounts(accounts, ErrorPolicy::Skip);
+
+        engine
+            .apply(RawTransaction::new(
+                String::from("deposit"),
+                1,
+                1,
+                Some(amount("0.5")),
+            ))
+            .unwrap();
+        engine
+            .apply(RawTransaction::new(
+                String::from("deposit"),
+                2,
+                2,
+                Some(amount("0.5")),
+            ))
+            .unwrap();
+
+        assert_eq!(engine.account(1).unwrap().total_amount, amount("1.5"));
+
+        // Locked accounts stay locked
+        let account = engine.account(2).unwrap();
+        assert_eq!(account.total_amount, amount("2.0"));
+        assert!(account.is_locked);
+        assert_eq!(
+            engine.rejections()[0].reason,
+            RejectionReason::FrozenAccount
+        );
+    }
+
+    #[test]
+    fn test_engine_restore_invalid_snapshot() {
+        let account = Account::new(1);
+        let tx = Transaction::new(TransactionType::Deposit, 2, 1, amount("1.0"));
+
+        let res = Engine::restore(
+            Snapshot::new(vec![account.clone(), account.clone()], vec![]),
+            ErrorPolicy::Abort,
+        );
+        assert!(matches!(
+            res.unwrap_err(),
+            EngineError::Snapshot(SnapshotError::DuplicateClientId(1))
+        ));
+
+        let res = Engine::restore(Snapshot::new(vec![account], vec![tx]), ErrorPolicy::Abort);
+        assert!(matches!(
+            res.unwrap_err(),
+            EngineError::Snapshot(SnapshotError::UnknownClientId(1, 2))
+        ));
+    }
+
+    #[test]
+    fn test_process_stream() {
+        let raw_transactions: Vec<RawTransaction> = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("1.0"))),
+            RawTransaction::new(String::from("deposit"), 2, 2, Some(amount("2.0"))),
+            RawTransaction::new(String::from("deposit"), 1, 3, Some(amount("2.0"))),
+            RawTransaction::new(String::from("withdrawal"), 1, 4, Some(amount("1.5"))),
+            RawTransaction::new(String::from("dispute"), 1, 4, None),
+            RawTransaction::new(String::from("chargeback"), 1, 4, None),
+            RawTransaction::new(String::from("withdrawal"), 2, 5, Some(amount("2.0"))),
+        ];
+
+        let res = process_stream(
+            raw_transactions.into_iter().map(Ok::<_, EngineError>),
+            ErrorPolicy::Abort,
+            &mut vec![],
+        );
+        assert!(res.is_ok());
+
+        let accounts = res.unwrap();
+        assert_eq!(
+            accounts.get(&1).unwrap(),
+            &Account {
+                client_id: 1,
+                available_amount: amount("3.0"),
+                held_amount: amount("0.0"),
+                total_amount: amount("3.0"),
+                is_locked: true
+            }
+        );
+        assert_eq!(
+            accounts.get(&2).unwrap(),
+            &Account {
+                client_id: 2,
+                available_amount: amount("0.0"),
+                held_amount: amount("0.0"),
+                total_amount: amount("0.0"),
+                is_locked: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_process_stream_equals_batch() {
+        let raw_transactions: Vec<RawTransaction> = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("1.0"))),
+            RawTransaction::new(String::from("deposit"), 2, 2, Some(amount("2.0"))),
+            RawTransaction::new(String::from("deposit"), 1, 3, Some(amount("2.0"))),
+            RawTransaction::new(String::from("dispute"), 1, 1, None),
+            RawTransaction::new(String::from("withdrawal"), 1, 4, Some(amount("1.5"))),
+            RawTransaction::new(String::from("resolve"), 1, 1, None),
+            RawTransaction::new(String::from("withdrawal"), 2, 5, Some(amount("2.0"))),
+        ];
+        let stream_accounts = process_stream(
+            raw_transactions
+                .clone()
+                .into_iter()
+                .map(Ok::<_, EngineError>),
+            ErrorPolicy::Abort,
+            &mut vec![],
+        )
+        .unwrap();
+
+        let (transaction_history, mut transactions) =
+            payment_engine::preprocess(raw_transactions, EventOrder::FileOrder, &mut vec![])
+                .unwrap();
+        let batch_accounts = payment_engine::process_transactions(
+            &transaction_history,
+            &mut transactions,
+            HashMap::new(),
+            ErrorPolicy::Abort,
+            &mut vec![],
+        )
+        .unwrap();
+
+        assert_eq!(stream_accounts, batch_accounts);
+    }
+
+    #[test]
+    fn test_process_stream_ignores_invalid_events() {
+        let raw_transactions: Vec<RawTransaction> = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("1.0"))),
+            // Transaction event before the referenced transaction
+            RawTransaction::new(String::from("dispute"), 2, 2, None),
+            RawTransaction::new(String::from("deposit"), 2, 2, Some(amount("2.0"))),
+            // Client id doesn't coincide with the referenced transaction
+            RawTransaction::new(String::from("dispute"), 2, 1, None),
+            // Unknown transaction id
+            RawTransaction::new(String::from("dispute"), 1, 3, None),
+        ];
+
+        let mut rejections = vec![];
+        let res = process_stream(
+            raw_transactions.into_iter().map(Ok::<_, EngineError>),
+            ErrorPolicy::Abort,
+            &mut rejections,
+        );
+        assert!(res.is_ok());
+
+        let accounts = res.unwrap();
+        assert_eq!(accounts.get(&1).unwrap().held_amount, Amount::ZERO);
+        assert_eq!(accounts.get(&2).unwrap().held_amount, Amount::ZERO);
+
+        let reasons: Vec<(u32, RejectionReason)> = rejections
+            .iter()
+            .map(|rejection| (rejection.tx, rejection.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (2, RejectionReason::UnknownTransaction),
+                (1, RejectionReason::ClientMismatch),
+                (3, RejectionReason::UnknownTransaction),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_process_stream_invalid_transaction_id() {
+        let raw_transactions: Vec<RawTransaction> = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("1.0"))),
+            RawTransaction::new(String::from("deposit"), 2, 1, Some(amount("2.0"))),
+        ];
+
+        let res = process_stream(
+            raw_transactions.into_iter().map(Ok::<_, EngineError>),
+            ErrorPolicy::Abort,
+            &mut vec![],
+        );
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert!(matches!(
+            err.root(),
+            EngineError::Format(err) if *err == FormatError::UniqueTransactionId(1)
+        ));
+    }
+
+    #[test]
+    fn test_process_stream_skip_errors() {
+        let raw_transactions: Vec<RawTransaction> = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("1.0"))),
+            RawTransaction::new(String::from("withdrawal"), 1, 2, Some(amount("1.5"))),
+            RawTransaction::new(String::from("dispute"), 1, 2, None),
+            RawTransaction::new(String::from("withdrawal"), 1, 3, Some(amount("0.5"))),
+        ];
+
+        let res = process_stream(
+            raw_transactions
+                .clone()
+                .into_iter()
+                .map(Ok::<_, EngineError>),
+            ErrorPolicy::Abort,
+            &mut vec![],
+        );
+        assert!(res.is_err());
+
+        let res = process_stream(
+            raw_transactions.into_iter().map(Ok::<_, EngineError>),
+            ErrorPolicy::Skip,
+            &mut vec![],
+        );
+        assert!(res.is_ok());
+
+        let accounts = res.unwrap();
+        assert_eq!(
+            accounts.get(&1).unwrap(),
+            &Account {
+                client_id: 1,
+                available_amount: amount("0.5"),
+                held_amount: amount("0.0"),
+                total_amount: amount("0.5"),
+                is_locked: false
+            }
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+
+/*
+    Compact set of transaction ids
+    Ids are grouped into blocks of 65,536 consecutive ids; each block is a bitmap of 8 KiB which is allocated when the first id of the block is inserted and freed when the last one is removed
+    A set of n dense ids takes about n / 8 bytes, compared to about 8 bytes per id in a hash set
+*/
+
+const BLOCK_BITS: u32 = 16;
+const BLOCK_WORDS: usize = (1 << BLOCK_BITS) / 64;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Block {
+    words: Box<[u64; BLOCK_WORDS]>,
+    // Number of ids in the block
+    len: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IdSet {
+    blocks: BTreeMap<u32, Block>,
+    len: usize,
+}
+
+impl IdSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        let (block, word, bit) = locate(id);
+
+        self.blocks
+            .get(&block)
+            .is_some_and(|block| block.words[word] & bit != 0)
+    }
+
+    // Returns false if the id is contained already
+    pub fn insert(&mut self, id: u32) -> bool {
+        let (block, word, bit) = locate(id);
+        let block = self.blocks.entry(block).or_insert_with(|| Block {
+            words: Box::new([0; BLOCK_WORDS]),
+            len: 0,
+        });
+
+        if block.words[word] & bit != 0 {
+            return false;
+        }
+
+        block.words[word] |= bit;
+        block.len += 1;
+        self.len += 1;
+
+        true
+    }
+
+    // Returns false if the id is not contained
+    pub fn remove(&mut self, id: u32) -> bool {
+        let (index, word, bit) = locate(id);
+
+        let Some(block) = self.blocks.get_mut(&index) else {
+            return false;
+        };
+
+        if block.words[word] & bit == 0 {
+            return false;
+        }
+
+        block.words[word] &= !bit;
+        block.len -= 1;
+        self.len -= 1;
+
+        if block.len == 0 {
+            self.blocks.remove(&index);
+        }
+
+        true
+    }
+
+    // Ids in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.blocks.iter().flat_map(|(index, block)| {
+            block
+                .words
+                .iter()
+                .enumerate()
+                .flat_map(move |(word_index, word)| {
+                    let base = (index << BLOCK_BITS) | (word_index as u32 * 64);
+                    Bits(*word).map(move |bit| base | bit)
+                })
+        })
+    }
+
+    // Runs of consecutive ids as inclusive ranges in ascending order, e.g. to serialize the set compactly
+    pub fn ranges(&self) -> Vec<(u32, u32)> {
+        let mut ranges: Vec<(u32, u32)> = vec![];
+
+        for id in self.iter() {
+            match ranges.last_mut() {
+                Some((_, end)) if id == *end + 1 => *end = id,
+                _ => ranges.push((id, id)),
+            }
+        }
+
+        ranges
+    }
+
+    pub fn from_ranges(ranges: &[(u32, u32)]) -> Self {
+        ranges
+            .iter()
+            .flat_map(|&(start, end)| start..=end)
+            .collect()
+    }
+}
+
+impl FromIterator<u32> for IdSet {
+    fn from_iter<I: IntoIterator<Item = u32>>(ids: I) -> Self {
+        let mut id_set = Self::new();
+        id_set.extend(ids);
+        id_set
+    }
+}
+
+impl Extend<u32> for IdSet {
+    fn extend<I: IntoIterator<Item = u32>>(&mut self, ids: I) {
+        for id in ids {
+            self.insert(id);
+        }
+    }
+}
+
+// Block, word within the block and bit mask of the id
+fn locate(id: u32) -> (u32, usize, u64) {
+    let offset = id & ((1 << BLOCK_BITS) - 1);
+    (id >> BLOCK_BITS, offset as usize / 64, 1 << (offset % 64))
+}
+
+// Positions of the set bits of a word in ascending order
+struct Bits(u64);
+
+impl Iterator for Bits {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0 == 0 {
+            return None;
+        }
+
+        let bit = self.0.trailing_zeros();
+        self.0 &= self.0 - 1;
+
+        Some(bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_set() {
+        let mut id_set = IdSet::new();
+        assert!(id_set.is_empty());
+
+        for id in [u32::MAX, 65_536, 7, 0, 63, 64] {
+            assert!(id_set.insert(id));
+        }
+
+        assert!(!id_set.insert(7));
+        assert_eq!(id_set.len(), 6);
+        assert!(id_set.contains(u32::MAX));
+        assert!(!id_set.contains(65_535));
+        assert!(!id_set.contains(8));
+
+        // Ascending order across words and blocks
+        assert_eq!(
+            id_set.iter().collect::<Vec<_>>(),
+            vec![0, 7, 63, 64, 65_536, u32::MAX]
+        );
+
+        assert!(id_set.remove(65_536));
+        assert!(!id_set.remove(65_536));
+        assert!(!id_set.remove(1_000_000));
+        assert_eq!(id_set.len(), 5);
+
+        // Empty blocks are freed
+        assert_eq!(id_set.blocks.len(), 2);
+    }
+
+    #[test]
+    fn test_id_set_from_iter() {
+        let id_set: IdSet = (1_000..3_000).rev().collect();
+        assert_eq!(id_set.len(), 2_000);
+        assert_eq!(id_set.iter().next(), Some(1_000));
+        assert_eq!(id_set.iter().last(), Some(2_999));
+    }
+
+    #[test]
+    fn test_id_set_ranges() {
+        let id_set: IdSet = [1, 2, 3, 5, 65_535, 65_536, u32::MAX].into_iter().collect();
+
+        let ranges = id_set.ranges();
+        assert_eq!(
+            ranges,
+            vec![(1, 3), (5, 5), (65_535, 65_536), (u32::MAX, u32::MAX)]
+        );
+        assert_eq!(IdSet::from_ranges(&ranges), id_set);
+    }
+}
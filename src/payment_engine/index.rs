@@ -0,0 +1,436 @@
+use super::{
+    id_set::IdSet,
+    store::{Entry, ENTRY_BYTES},
+};
+use crate::errors::EngineError;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+/*
+    Disk index of the processed transactions for inputs whose transactions don't fit into memory
+    The index file has a fixed-size slot per transaction id at offset id * 12 bytes; empty slots are zeroed
+    Slots of unused transaction ids are never written, so the file is sparse on file systems with support for holes
+    The ids of the stored transactions are kept in a compact IdSet, so lookups of unknown ids and listing all entries don't scan the file
+    The most recently used entries are cached in memory; changed entries are written back when they are evicted from the cache
+    The index only lives for a single run: the file is truncated when the index is created and removed when it is dropped
+*/
+
+pub const DEFAULT_INDEX_CACHE: usize = 1_000_000;
+
+#[derive(Debug)]
+struct CachedEntry {
+    entry: Entry,
+    // Entry was changed since it was read from the index file
+    is_dirty: bool,
+    // Time of the last access; key in the access order
+    last_access: u64,
+}
+
+#[derive(Debug)]
+pub struct DiskIndex {
+    path: PathBuf,
+    file: File,
+    // Length of the index file in bytes; slots beyond are empty
+    file_len: u64,
+    // Ids of the stored transactions
+    ids: IdSet,
+    cache: HashMap<u32, CachedEntry>,
+    // Transaction ids of the cached entries by time of the last access; the least recently used entry comes first
+    access_order: BTreeMap<u64, u32>,
+    clock: u64,
+    cache_capacity: usize,
+}
+
+impl DiskIndex {
+    pub fn create(index_file: &str, cache_capacity: usize) -> Result<Self, EngineError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(index_file)
+            .map_err(|source| EngineError::File {
+                message: format!("Can't create index file at path '{}'", index_file),
+                source,
+            })?;
+
+        Ok(Self {
+            path: PathBuf::from(index_file),
+            file,
+            file_len: 0,
+            ids: IdSet::new(),
+            cache: HashMap::new(),
+            access_order: BTreeMap::new(),
+            clock: 0,
+            cache_capacity: cache_capacity.max(1),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub(crate) fn get(&mut self, transaction_id: u32) -> Result<Option<Entry>, EngineError> {
+        if let Some(cached) = self.cache.get(&transaction_id) {
+            let entry = cached.entry;
+            self.touch(transaction_id);
+            return Ok(Some(entry));
+        }
+
+        let entry = self.read(transaction_id)?;
+
+        if let Some(entry) = entry {
+            self.cache_entry(transaction_id, entry, false)?;
+        }
+
+        Ok(entry)
+    }
+
+    // Add the entry of a new transaction
+    pub(crate) fn insert(&mut self, transaction_id: u32, entry: Entry) -> Result<(), EngineError> {
+        self.ids.insert(transaction_id);
+        self.cache_entry(transaction_id, entry, true)
+    }
+
+    // Replace the entry of a stored transaction
+    pub(crate) fn update(&mut self, transaction_id: u32, entry: Entry) -> Result<(), EngineError> {
+        match self.cache.get_mut(&transaction_id) {
+            Some(cached) => {
+                cached.entry = entry;
+                cached.is_dirty = true;
+                self.touch(transaction_id);
+                Ok(())
+            }
+            None => {
+                self.ids.insert(transaction_id);
+                self.cache_entry(transaction_id, entry, true)
+            }
+        }
+    }
+
+    // Remove the entry of a stored transaction; its slot in the index file is cleared
+    pub(crate) fn remove(&mut self, transaction_id: u32) -> Result<Option<Entry>, EngineError> {
+        let entry = match self.cache.remove(&transaction_id) {
+            Some(cached) => {
+                self.access_order.remove(&cached.last_access);
+                Some(cached.entry)
+            }
+            None => self.read(transaction_id)?,
+        };
+
+        if entry.is_some() {
+            self.ids.remove(transaction_id);
+
+            let offset = slot_offset(transaction_id);
+
+            if offset + ENTRY_BYTES as u64 <= self.file_len {
+                self.file.seek(SeekFrom::Start(offset))?;
+                self.file.write_all(&[0; ENTRY_BYTES])?;
+            }
+        }
+
+        Ok(entry)
+    }
+
+    // All entries in the order of the transaction ids; only the slots of stored transactions which aren't cached are read
+    pub(crate) fn entries(&mut self) -> Result<Vec<(u32, Entry)>, EngineError> {
+        let mut entries = Vec::with_capacity(self.ids.len());
+
+        for transaction_id in self.ids.iter() {
+            let entry = match self.cache.get(&transaction_id) {
+                Some(cached) => Some(cached.entry),
+                None => read_slot(&mut self.file, self.file_len, transaction_id)?,
+            };
+
+            if let Some(entry) = entry {
+                entries.push((transaction_id, entry));
+            }
+        }
+
+        Ok(entries)
+    }
+
+    // Write all changed entries to the index file
+    pub fn flush(&mut self) -> Result<(), EngineError> {
+        let mut dirty: Vec<(u32, Entry)> = self
+            .cache
+            .iter_mut()
+            .filter(|(_, cached)| cached.is_dirty)
+            .map(|(transaction_id, cached)| {
+                cached.is_dirty = false;
+                (*transaction_id, cached.entry)
+            })
+            .collect();
+
+        // Write in the order of the slots
+        dirty.sort_by_key(|(transaction_id, _)| *transaction_id);
+
+        for (transaction_id, entry) in dirty {
+            self.write(transaction_id, entry)?;
+        }
+
+        Ok(())
+    }
+
+    fn cache_entry(
+        &mut self,
+        transaction_id: u32,
+        entry: Entry,
+        is_dirty: bool,
+    ) -> Result<(), EngineError> {
+        if self.cache.len() >= self.cache_capacity {
+            self.evict()?;
+        }
+
+        self.clock += 1;
+        self.access_order.insert(self.clock, transaction_id);
+        self.cache.insert(
+            transaction_id,
+            CachedEntry {
+                entry,
+                is_dirty,
+                last_access: self.clock,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn touch(&mut self, transaction_id: u32) {
+        if let Some(cached) = self.cache.get_mut(&transaction_id) {
+            self.access_order.remove(&cached.last_access);
+            self.clock += 1;
+            cached.last_access = self.clock;
+            self.access_order.insert(self.clock, transaction_id);
+        }
+    }
+
+    // Remove the least recently used entry from the cache
+    fn evict(&mut self) -> Result<(), EngineError> {
+        let Some((_, transaction_id)) = self.access_order.pop_first() else {
+            return Ok(());
+        };
+
+        if let Some(cached) = self.cache.remove(&transaction_id) {
+            if cached.is_dirty {
+                self.write(transaction_id, cached.entry)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read(&mut self, transaction_id: u32) -> Result<Option<Entry>, EngineError> {
+        // Unknown ids are never looked up in the index file
+        if !self.ids.contains(transaction_id) {
+            return Ok(None);
+        }
+
+        read_slot(&mut self.file, self.file_len, transaction_id)
+    }
+
+    fn write(&mut self, transaction_id: u32, entry: Entry) -> Result<(), EngineError> {
+        let offset = slot_offset(transaction_id);
+
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(&entry.to_bytes())?;
+        self.file_len = self.file_len.max(offset + ENTRY_BYTES as u64);
+
+        Ok(())
+    }
+}
+
+impl Drop for DiskIndex {
+    // The index is only valid for the current run
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn slot_offset(transaction_id: u32) -> u64 {
+    u64::from(transaction_id) * ENTRY_BYTES as u64
+}
+
+fn read_slot(
+    file: &mut File,
+    file_len: u64,
+    transaction_id: u32,
+) -> Result<Option<Entry>, EngineError> {
+    let offset = slot_offset(transaction_id);
+
+    if offset + ENTRY_BYTES as u64 > file_len {
+        return Ok(None);
+    }
+
+    let mut slot = [0; ENTRY_BYTES];
+    file.seek(SeekFrom::Start(offset))?;
+
+    match file.read_exact(&mut slot) {
+        Ok(()) => Ok(Entry::from_bytes(&slot)),
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{Transaction, TransactionStatus, TransactionType},
+        payment_engine::TransactionStore,
+    };
+    use std::path::Path;
+
+    fn transaction(transaction_id: u32) -> Transaction {
+        let mut tx = Transaction::new(
+            TransactionType::Deposit,
+            (transaction_id % 7) as u16,
+            transaction_id,
+            format!("{}.5", transaction_id).parse().unwrap(),
+        );
+        tx.status = TransactionStatus::Processed;
+        tx
+    }
+
+    #[test]
+    fn test_disk_index_eviction() {
+        fs::create_dir_all("tmp").unwrap();
+        let index_file = "tmp/index_eviction.bin";
+
+        // Most entries only exist in the index file
+        let mut store = TransactionStore::on_disk(DiskIndex::create(index_file, 3).unwrap());
+
+        for transaction_id in (1..=100).rev() {
+            assert!(store.insert(&transaction(transaction_id)).unwrap());
+        }
+
+        assert_eq!(store.len(), 100);
+        assert!(!store.insert(&transaction(50)).unwrap());
+        assert!(!store.contains(101).unwrap());
+
+        for transaction_id in 1..=100 {
+            assert_eq!(
+                store.get(transaction_id).unwrap(),
+                Some(transaction(transaction_id))
+            );
+        }
+
+        store.set_status(1, TransactionStatus::Disputed).unwrap();
+
+        // Evict the changed entry
+        for transaction_id in 2..=10 {
+            store.get(transaction_id).unwrap();
+        }
+
+        assert_eq!(
+            store.get(1).unwrap().unwrap().status,
+            TransactionStatus::Disputed
+        );
+
+        let transactions = store.transactions().unwrap();
+        assert_eq!(transactions.len(), 100);
+        assert_eq!(transactions[0].status, TransactionStatus::Disputed);
+        assert_eq!(transactions[99], transaction(100));
+
+        drop(store);
+        assert!(!Path::new(index_file).exists());
+    }
+
+    #[test]
+    fn test_disk_index_sparse_ids() {
+        fs::create_dir_all("tmp").unwrap();
+        let index_file = "tmp/index_sparse.bin";
+
+        let mut store = TransactionStore::on_disk(DiskIndex::create(index_file, 1).unwrap());
+
+        for transaction_id in [1_000_000, 3, 7] {
+            store.insert(&transaction(transaction_id)).unwrap();
+        }
+
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.get(5).unwrap(), None);
+        assert_eq!(store.get(2_000_000).unwrap(), None);
+
+        // Ordered by transaction id
+        assert_eq!(
+            store.transactions().unwrap(),
+            vec![transaction(3), transaction(7), transaction(1_000_000)]
+        );
+
+        // The file reaches up to the slot of the highest transaction id
+        assert_eq!(
+            fs::metadata(index_file).unwrap().len(),
+            1_000_001 * ENTRY_BYTES as u64
+        );
+    }
+
+    #[test]
+    fn test_disk_index_high_ids() {
+        fs::create_dir_all("tmp").unwrap();
+        let index_file = "tmp/index_high_ids.bin";
+
+        let mut store = TransactionStore::on_disk(DiskIndex::create(index_file, 1).unwrap());
+
+        for transaction_id in [4_000_000_000, 2, u32::MAX] {
+            store.insert(&transaction(transaction_id)).unwrap();
+        }
+
+        // Only the slots of the stored transactions are read instead of the whole file
+        assert_eq!(
+            store.transactions().unwrap(),
+            vec![
+                transaction(2),
+                transaction(4_000_000_000),
+                transaction(u32::MAX)
+            ]
+        );
+        assert_eq!(store.get(3_000_000_000).unwrap(), None);
+    }
+
+    #[test]
+    fn test_disk_index_remove() {
+        fs::create_dir_all("tmp").unwrap();
+        let index_file = "tmp/index_remove.bin";
+
+        let mut store = TransactionStore::on_disk(DiskIndex::create(index_file, 2).unwrap());
+
+        for transaction_id in 1..=5 {
+            store.insert(&transaction(transaction_id)).unwrap();
+        }
+
+        // Entry in the index file and entry in the cache
+        assert_eq!(store.remove(1).unwrap(), Some(transaction(1)));
+        assert_eq!(store.remove(5).unwrap(), Some(transaction(5)));
+        assert_eq!(store.remove(5).unwrap(), None);
+        assert_eq!(store.remove(6).unwrap(), None);
+
+        assert_eq!(store.len(), 3);
+        assert!(!store.contains(1).unwrap());
+        assert_eq!(
+            store.transactions().unwrap(),
+            vec![transaction(2), transaction(3), transaction(4)]
+        );
+
+        // The id can be taken again
+        assert!(store.insert(&transaction(1)).unwrap());
+        assert_eq!(store.get(1).unwrap(), Some(transaction(1)));
+    }
+
+    #[test]
+    fn test_disk_index_invalid_path() {
+        let res = DiskIndex::create("tmp/missing/index.bin", 1);
+        assert!(res.is_err());
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "Can't create index file at path 'tmp/missing/index.bin'"
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use super::engine::{check, shard};
+use super::{id_set::IdSet, Engine};
+use crate::{
+    errors::{EngineError, FormatError},
+    input::TransactionSource,
+    models::CheckedTransaction,
+};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    mem, panic,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+    },
+    thread,
+};
+
+/*
+    Parallel processing: client accounts never interact, so the records are partitioned by client id into shards
+    Each shard is processed by its own worker thread with its own engine; the order of the records of a client is preserved
+    The records are read and validated by the calling thread, which also checks the uniqueness of transaction ids across shards, including finalized ones
+    The dispute window of a restored engine is carried over to the result but not applied while processing in parallel
+    Transaction events are routed to the shard of the referenced transaction, so that client mismatches are rejected like in single-threaded processing
+    If processing is aborted, the error of the first record in the input is returned; the result is identical to single-threaded processing
+*/
+
+// Number of records sent to a worker at once
+const BATCH_SIZE: usize = 1024;
+
+// Number of batches buffered per worker before the reader is blocked
+const CHANNEL_CAPACITY: usize = 16;
+
+// Validated records with their sequence number in the input
+type Batch = Vec<(u64, CheckedTransaction)>;
+
+// Process all raw transactions of the source on the given number of worker threads
+pub fn process_parallel(
+    engine: Engine,
+    source: impl TransactionSource,
+    shards: usize,
+) -> Result<Engine, EngineError> {
+    let shards = shards.max(1);
+    let error_policy = engine.error_policy();
+    let dispute_window = engine.dispute_window();
+    let mut engines = engine.split(shards)?;
+    let finalized = engines[0].finalized().clone();
+
+    // Client id of every transaction; transaction ids have to be unique across shards
+    let mut transactions: HashMap<u32, u16> = HashMap::new();
+
+    for engine in &mut engines {
+        transactions.extend(engine.transaction_clients()?);
+    }
+
+    // Set by a worker which aborted processing so that reading stops early
+    let aborted = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        let mut senders = vec![];
+        let mut workers = vec![];
+
+        for engine in engines {
+            let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+            let aborted = &aborted;
+
+            workers.push(scope.spawn(move || work(engine, receiver, aborted)));
+            senders.push(sender);
+        }
+
+        let res = dispatch(source, &senders, &mut transactions, &finalized, &aborted);
+
+        // Close the channels so that the workers finish
+        drop(senders);
+
+        let mut merged =
+            Engine::with_error_policy(error_policy).with_dispute_window(dispute_window)?;
+        let mut first_err: Option<(u64, EngineError)> = None;
+
+        for worker in workers {
+            match worker
+                .join()
+                .unwrap_or_else(|err| panic::resume_unwind(err))
+            {
+                Ok(engine) => merged.merge(engine)?,
+                Err((sequence, err)) => match &first_err {
+                    Some((first, _)) if *first < sequence => {}
+                    _ => first_err = Some((sequence, err)),
+                },
+            }
+        }
+
+        // Records are dispatched in order, so the errors of the workers precede the error of the reader
+        if let Some((_, err)) = first_err {
+            return Err(err);
+        }
+
+        res?;
+
+        Ok(merged)
+    })
+}
+
+// Read, validate and route the records to the workers
+fn dispatch(
+    source: impl TransactionSource,
+    senders: &[SyncSender<Batch>],
+    transactions: &mut HashMap<u32, u16>,
+    finalized: &IdSet,
+    aborted: &AtomicBool,
+) -> Result<(), EngineError> {
+    let shards = senders.len();
+    let mut batches: Vec<Batch> = (0..shards)
+        .map(|_| Vec::with_capacity(BATCH_SIZE))
+        .collect();
+
+    let res = route(
+        source,
+        senders,
+        &mut batches,
+        transactions,
+        finalized,
+        aborted,
+    );
+
+    // Records preceding an error are still processed; send errors mean that the worker aborted already
+    for (sender, batch) in senders.iter().zip(batches) {
+        if !batch.is_empty() {
+            let _ = sender.send(batch);
+        }
+    }
+
+    res
+}
+
+fn route(
+    source: impl TransactionSource,
+    senders: &[SyncSender<Batch>],
+    batches: &mut [Batch],
+    transactions: &mut HashMap<u32, u16>,
+    finalized: &IdSet,
+    aborted: &AtomicBool,
+) -> Result<(), EngineError> {
+    let shards = senders.len();
+
+    for (sequence, raw_transaction) in (0..).zip(source) {
+        if aborted.load(Ordering::Relaxed) {
+            break;
+        }
+
+        let checked_transaction = check(raw_transaction?)?;
+
+        let index = match &checked_transaction {
+            CheckedTransaction::Transaction(tx) => match transactions.entry(tx.transaction_id) {
+                Entry::Occupied(_) => {
+                    return Err(EngineError::from(FormatError::UniqueTransactionId(
+                        tx.transaction_id,
+                    ))
+                    .at(&tx.position));
+                }
+                Entry::Vacant(_) if finalized.contains(tx.transaction_id) => {
+                    return Err(EngineError::from(FormatError::UniqueTransactionId(
+                        tx.transaction_id,
+                    ))
+                    .at(&tx.position));
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(tx.client_id);
+                    shard(tx.client_id, shards)
+                }
+            },
+            // Unknown transactions are rejected by the shard of the client of the transaction event
+            CheckedTransaction::TransactionEvent(event) => {
+                let client_id = transactions
+                    .get(&event.transaction_id)
+                    .unwrap_or(&event.client_id);
+                shard(*client_id, shards)
+            }
+        };
+
+        batches[index].push((sequence, checked_transaction));
+
+        if batches[index].len() >= BATCH_SIZE {
+            let batch = mem::replace(&mut batches[index], Vec::with_capacity(BATCH_SIZE));
+
+            if senders[index].send(batch).is_err() {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Book the records of a shard; returns the sequence number of the failed record on error
+fn work(
+    mut engine: Engine,
+    receiver: Receiver<Batch>,
+    aborted: &AtomicBool,
+) -> Result<Engine, (u64, EngineError)> {
+    for batch in receiver {
+        for (sequence, checked_transaction) in batch {
+            if let Err(err) = engine.apply_checked(checked_transaction) {
+                aborted.store(true, Ordering::Relaxed);
+                return Err((sequence, err));
+            }
+        }
+    }
+
+    Ok(engine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        input::MemorySource,
+        models::{Amount, Position, RawTransaction, RejectionReason},
+        payment_engine::{postprocess, DisputeWindow, ErrorPolicy},
+    };
+
+    fn raw_transactions(clients: u16, records: u32) -> Vec<RawTransaction> {
+        let amount: Amount = "1.0".parse().unwrap();
+
+        (1..=records)
+            .map(|i| {
+                let client = (i % clients as u32) as u16 + 1;
+                let mut raw_transaction = match i % 10 {
+                    // Dispute, resolve and chargeback of preceding transactions
+                    3 => RawTransaction::new(
+                        String::from("dispute"),
+                        client,
+                        i.saturating_sub(clients as u32),
+                        None,
+                    ),
+                    5 => RawTransaction::new(
+                        String::from("resolve"),
+                        client,
+                        i.saturating_sub(2 * clients as u32),
+                        None,
+                    ),
+                    7 => RawTransaction::new(
+                        String::from("chargeback"),
+                        client,
+                        i.saturating_sub(4 * clients as u32),
+                        None,
+                    ),
+                    // Client mismatch and unknown transaction
+                    8 => RawTransaction::new(String::from("dispute"), client, i - 1, None),
+                    9 => RawTransaction::new(String::from("dispute"), client, i + 1, None),
+                    // Insufficient funds
+                    4 => RawTransaction::new(
+                        String::from("withdrawal"),
+                        client,
+                        i,
+                        Some("3.0".parse().unwrap()),
+                    ),
+                    _ => RawTransaction::new(String::from("deposit"), client, i, Some(amount)),
+                };
+                raw_transaction.position = Position::new(i as u64 + 1);
+                raw_transaction
+            })
+            .collect()
+    }
+
+    fn sequential(
+        raw_transactions: Vec<RawTransaction>,
+        error_policy: ErrorPolicy,
+    ) -> Result<Engine, EngineError> {
+        let mut engine = Engine::with_error_policy(error_policy);
+        engine.apply_all(MemorySource::new(raw_transactions))?;
+        Ok(engine)
+    }
+
+    fn assert_identical(parallel: Engine, sequential: Engine) {
+        let (parallel_accounts, mut parallel_rejections) = parallel.finish();
+        let (sequential_accounts, mut sequential_rejections) = sequential.finish();
+
+        let mut parallel_accounts = postprocess(parallel_accounts).unwrap();
+        let mut sequential_accounts = postprocess(sequential_accounts).unwrap();
+        parallel_accounts.sort_by_key(|account| account.client);
+        sequential_accounts.sort_by_key(|account| account.client);
+        assert_eq!(parallel_accounts, sequential_accounts);
+
+        parallel_rejections.sort_by_key(|rejection| rejection.line);
+        sequential_rejections.sort_by_key(|rejection| rejection.line);
+        assert_eq!(parallel_rejections, sequential_rejections);
+    }
+
+    #[test]
+    fn test_process_parallel() {
+        let raw_transactions = raw_transactions(7, 5000);
+
+        for shards in [1, 2, 3, 8] {
+            let res = process_parallel(
+                Engine::with_error_policy(ErrorPolicy::Skip),
+                MemorySource::new(raw_transactions.clone()),
+                shards,
+            );
+            assert!(res.is_ok());
+
+            let expected = sequential(raw_transactions.clone(), ErrorPolicy::Skip).unwrap();
+            assert!(!expected.rejections().is_empty());
+            assert_identical(res.unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_process_parallel_abort() {
+        let raw_transactions = raw_transactions(7, 5000);
+
+        let res = process_parallel(
+            Engine::new(),
+            MemorySource::new(raw_transactions.clone()),
+            4,
+        );
+        assert!(res.is_err());
+
+        let expected = sequential(raw_transactions, ErrorPolicy::Abort).unwrap_err();
+        let err = res.unwrap_err();
+        assert_eq!(err.to_string(), expected.to_string());
+        assert_eq!(err.position(), expected.position());
+    }
+
+    #[test]
+    fn test_process_parallel_unique_transaction_id() {
+        let amount: Amount = "1.0".parse().unwrap();
+        let mut raw_transactions = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount)),
+            RawTransaction::new(String::from("deposit"), 2, 1, Some(amount)),
+        ];
+        raw_transactions[1].position = Position::new(3);
+
+        let res = process_parallel(Engine::new(), MemorySource::new(raw_transactions), 2);
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert!(matches!(
+            err.root(),
+            EngineError::Format(FormatError::UniqueTransactionId(1))
+        ));
+        assert_eq!(err.position(), Some(&Position::new(3)));
+    }
+
+    #[test]
+    fn test_process_parallel_restored() {
+        let raw_transactions = raw_transactions(5, 2000);
+        let (previous, current) = raw_transactions.split_at(1000);
+
+        let engine = sequential(previous.to_vec(), ErrorPolicy::Skip).unwrap();
+        let res = process_parallel(engine, MemorySource::new(current.to_vec()), 3);
+        assert!(res.is_ok());
+
+        let expected = sequential(raw_transactions, ErrorPolicy::Skip).unwrap();
+        assert_identical(res.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_process_parallel_restored_dispute_window() {
+        let raw_transactions = raw_transactions(5, 2000);
+        let (previous, current) = raw_transactions.split_at(1000);
+
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip)
+            .with_dispute_window(DisputeWindow::Records(100))
+            .unwrap();
+        engine
+            .apply_all(MemorySource::new(previous.to_vec()))
+            .unwrap();
+        let snapshot = engine.snapshot().unwrap();
+
+        // Disputes of transactions finalized in the previous run
+        let mut current = current.to_vec();
+        for (client, transaction_id, line) in [(2, 1, 2002), (3, 2, 2003)] {
+            let mut raw_transaction =
+                RawTransaction::new(String::from("dispute"), client, transaction_id, None);
+            raw_transaction.position = Position::new(line);
+            current.push(raw_transaction);
+        }
+
+        let restore = || Engine::restore(snapshot.clone(), ErrorPolicy::Skip).unwrap();
+
+        let mut parallel =
+            process_parallel(restore(), MemorySource::new(current.clone()), 3).unwrap();
+        let mut expected = restore();
+        expected.apply_all(MemorySource::new(current)).unwrap();
+
+        assert_eq!(parallel.snapshot().unwrap(), expected.snapshot().unwrap());
+        assert_eq!(
+            parallel
+                .rejections()
+                .iter()
+                .filter(|rejection| rejection.reason == RejectionReason::OutsideDisputeWindow)
+                .count(),
+            2
+        );
+        assert_identical(parallel, expected);
+
+        // Ids of finalized transactions can't be reused
+        let reused = vec![RawTransaction::new(
+            String::from("deposit"),
+            4,
+            1,
+            Some("1.0".parse().unwrap()),
+        )];
+
+        let res = process_parallel(restore(), MemorySource::new(reused.clone()), 3);
+        assert!(matches!(
+            res.unwrap_err().root(),
+            EngineError::Format(FormatError::UniqueTransactionId(1))
+        ));
+
+        let res = restore().apply_all(MemorySource::new(reused));
+        assert!(matches!(
+            res.unwrap_err().root(),
+            EngineError::Format(FormatError::UniqueTransactionId(1))
+        ));
+    }
+}
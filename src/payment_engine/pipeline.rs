@@ -0,0 +1,211 @@
+use super::engine::check;
+use super::Engine;
+use crate::{
+    errors::EngineError,
+    input::TransactionSource,
+    models::{CheckedTransaction, RawTransaction},
+};
+use std::{
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread,
+};
+
+/*
+    Pipelined processing: reading, validation and booking run on their own threads connected by bounded channels
+    This way deserialization of the input overlaps with booking; a slow stage blocks the preceding stages when its channel is full (backpressure)
+    Records are passed in batches to keep the synchronization overhead low; the order of the records is preserved
+    Errors are passed downstream like records, so the first error in the input aborts processing exactly like in single-threaded processing
+    The output is written once all records are booked, as the balances of the client accounts are only final then
+*/
+
+// Number of records passed between two stages at once
+const BATCH_SIZE: usize = 1024;
+
+// Number of batches buffered between two stages
+const CHANNEL_CAPACITY: usize = 16;
+
+type RawBatch = Vec<Result<RawTransaction, EngineError>>;
+type CheckedBatch = Vec<Result<CheckedTransaction, EngineError>>;
+
+// Process all raw transactions of the source in a pipeline of read, validate and book stages
+pub fn process_pipelined(
+    mut engine: Engine,
+    source: impl TransactionSource + Send,
+) -> Result<Engine, EngineError> {
+    thread::scope(|scope| {
+        let (raw_sender, raw_receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+        let (checked_sender, checked_receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+
+        scope.spawn(move || read(source, raw_sender));
+        scope.spawn(move || validate(raw_receiver, checked_sender));
+
+        // Returning early drops the receiver, which stops the preceding stages
+        book(&mut engine, checked_receiver)?;
+
+        Ok(engine)
+    })
+}
+
+// Read and deserialize the records of the input
+fn read(source: impl TransactionSource, sender: SyncSender<RawBatch>) {
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+
+    for raw_transaction in source {
+        let is_err = raw_transaction.is_err();
+        batch.push(raw_transaction);
+
+        if batch.len() >= BATCH_SIZE || is_err {
+            // The booking stage stopped already
+            if sender.send(batch).is_err() {
+                return;
+            }
+
+            // Nothing is read after the first error
+            if is_err {
+                return;
+            }
+
+            batch = Vec::with_capacity(BATCH_SIZE);
+        }
+    }
+
+    if !batch.is_empty() {
+        let _ = sender.send(batch);
+    }
+}
+
+// Check and verify input format via CheckedTransaction type
+fn validate(receiver: Receiver<RawBatch>, sender: SyncSender<CheckedBatch>) {
+    for raw_batch in receiver {
+        let mut is_err = false;
+        let mut batch = Vec::with_capacity(raw_batch.len());
+
+        for raw_transaction in raw_batch {
+            let checked_transaction = raw_transaction.and_then(check);
+            is_err = checked_transaction.is_err();
+            batch.push(checked_transaction);
+
+            if is_err {
+                break;
+            }
+        }
+
+        if sender.send(batch).is_err() || is_err {
+            return;
+        }
+    }
+}
+
+// Book the validated records on the client accounts
+fn book(engine: &mut Engine, receiver: Receiver<CheckedBatch>) -> Result<(), EngineError> {
+    for batch in receiver {
+        for checked_transaction in batch {
+            engine.apply_checked(checked_transaction?)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        csv::CsvSource,
+        errors::{FormatError, WithdrawalError},
+        input::MemorySource,
+        models::{Amount, Position},
+        payment_engine::ErrorPolicy,
+    };
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    #[test]
+    fn test_process_pipelined() {
+        let mut raw_transactions = vec![];
+
+        for i in 1..=5000 {
+            raw_transactions.push(RawTransaction::new(
+                String::from("deposit"),
+                (i % 3) as u16,
+                i,
+                Some(amount("1.0")),
+            ));
+
+            if i % 7 == 0 {
+                raw_transactions.push(RawTransaction::new(
+                    String::from("dispute"),
+                    (i % 3) as u16,
+                    i,
+                    None,
+                ));
+            }
+        }
+
+        let res = process_pipelined(Engine::new(), MemorySource::new(raw_transactions.clone()));
+        assert!(res.is_ok());
+
+        let mut expected = Engine::new();
+        expected
+            .apply_all(MemorySource::new(raw_transactions))
+            .unwrap();
+
+        let (accounts, _) = res.unwrap().finish();
+        let (expected_accounts, _) = expected.finish();
+        assert_eq!(accounts, expected_accounts);
+    }
+
+    #[test]
+    fn test_process_pipelined_csv() {
+        let source = CsvSource::open("test_data/transactions_resume.csv").unwrap();
+
+        let res = process_pipelined(Engine::with_error_policy(ErrorPolicy::Skip), source);
+        assert!(res.is_ok());
+
+        let mut expected = Engine::with_error_policy(ErrorPolicy::Skip);
+        expected
+            .apply_all(CsvSource::open("test_data/transactions_resume.csv").unwrap())
+            .unwrap();
+
+        let engine = res.unwrap();
+        assert_eq!(engine.rejections(), expected.rejections());
+        assert_eq!(engine.finish().0, expected.finish().0);
+    }
+
+    #[test]
+    fn test_process_pipelined_first_error() {
+        let mut raw_transactions = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("1.0"))),
+            RawTransaction::new(String::from("withdrawal"), 1, 2, Some(amount("2.0"))),
+            RawTransaction::new(String::from("deposit"), 1, 3, None),
+        ];
+        raw_transactions[1].position = Position::new(3);
+
+        let res = process_pipelined(Engine::new(), MemorySource::new(raw_transactions));
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert!(matches!(
+            err.root(),
+            EngineError::Withdrawal(WithdrawalError::InsufficientFunds(1))
+        ));
+        assert_eq!(err.position(), Some(&Position::new(3)));
+    }
+
+    #[test]
+    fn test_process_pipelined_format_error() {
+        let raw_transactions = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("1.0"))),
+            RawTransaction::new(String::from("deposit"), 1, 2, None),
+        ];
+
+        let res = process_pipelined(Engine::new(), MemorySource::new(raw_transactions));
+        assert!(res.is_err());
+        assert!(matches!(
+            res.unwrap_err().root(),
+            EngineError::Format(FormatError::MissingAmount(2, _))
+        ));
+    }
+}
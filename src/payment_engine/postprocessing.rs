@@ -1,7 +1,10 @@
-use crate::models::{Account, RawAccount};
+use crate::{
+    errors::EngineError,
+    models::{Account, RawAccount},
+};
 use std::collections::HashMap;
 
-pub fn postprocess(accounts: HashMap<u16, Account>) -> Result<Vec<RawAccount>, anyhow::Error> {
+pub fn postprocess(accounts: HashMap<u16, Account>) -> Result<Vec<RawAccount>, EngineError> {
     let mut raw_accounts: Vec<RawAccount> = vec![];
 
     for (_client_id, account) in accounts {
@@ -15,6 +18,7 @@ pub fn postprocess(accounts: HashMap<u16, Account>) -> Result<Vec<RawAccount>, a
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::Amount;
 
     #[test]
     fn test_postprocess() {
@@ -29,11 +33,11 @@ mod tests {
 
         assert_eq!(
             raw_accounts.iter().find(|el| el.client == 1).unwrap(),
-            &RawAccount::new(1, 0.0, 0.0, 0.0, false)
+            &RawAccount::new(1, Amount::ZERO, Amount::ZERO, Amount::ZERO, false)
         );
         assert_eq!(
             raw_accounts.iter().find(|el| el.client == 2).unwrap(),
-            &RawAccount::new(2, 0.0, 0.0, 0.0, false)
+            &RawAccount::new(2, Amount::ZERO, Amount::ZERO, Amount::ZERO, false)
         );
     }
 }
@@ -1,30 +1,50 @@
-use std::collections::{hash_map::Entry, HashMap};
-
+use super::EventOrder;
 use crate::{
-    errors::FormatError,
-    models::{CheckedTransaction, RawTransaction, Transaction},
+    errors::{EngineError, FormatError},
+    models::{
+        Account, CheckedTransaction, RawAccount, RawTransaction, Rejection, RejectionReason,
+        Transaction, TransactionEvent,
+    },
 };
+use std::collections::{hash_map::Entry, HashMap};
+
+// Entry of the transaction history; transactions and transaction events are processed in this order
+#[derive(Debug, PartialEq)]
+pub enum HistoryEntry {
+    Transaction(u32),
+    // Only used for EventOrder::FileOrder
+    Event(TransactionEvent),
+}
 
 pub fn preprocess(
     raw_transactions: Vec<RawTransaction>,
-) -> Result<(Vec<u32>, HashMap<u32, Transaction>), anyhow::Error> {
+    event_order: EventOrder,
+    rejections: &mut Vec<Rejection>,
+) -> Result<(Vec<HistoryEntry>, HashMap<u32, Transaction>), EngineError> {
     // Collect time-ordered transaction ids in transaction_history; transactions have to be processed in chronological order
-    let mut transaction_history: Vec<u32> = vec![];
+    let mut transaction_history: Vec<HistoryEntry> = vec![];
 
     // The transaction events (dispute, resolve, chargeback) are aggregated into the transactions so that transaction_id is unique in the input data. This way transactions can be stored in a hash map. Otherwise, search in array would be O(n).
     let mut transactions: HashMap<u32, Transaction> = HashMap::new();
 
     for raw_transaction in raw_transactions {
+        let position = raw_transaction.position.clone();
+
         // Check and verify input format via CheckedTransaction type
-        let checked_transaction: CheckedTransaction = raw_transaction.try_into()?;
+        let checked_transaction: CheckedTransaction = raw_transaction
+            .try_into()
+            .map_err(|err| EngineError::from(err).at(&position))?;
 
         match checked_transaction {
             CheckedTransaction::Transaction(tx) => match transactions.entry(tx.transaction_id) {
                 Entry::Occupied(_) => {
-                    return Err(FormatError::UniqueTransactionId(tx.transaction_id).into());
+                    return Err(EngineError::from(FormatError::UniqueTransactionId(
+                        tx.transaction_id,
+                    ))
+                    .at(&tx.position));
                 }
                 Entry::Vacant(entry) => {
-                    transaction_history.push(tx.transaction_id);
+                    transaction_history.push(HistoryEntry::Transaction(tx.transaction_id));
                     entry.insert(tx);
                 }
             },
@@ -32,15 +52,27 @@ pub fn preprocess(
                 match transactions.get_mut(&event.transaction_id) {
                     Some(transaction) => {
                         if transaction.client_id == event.client_id {
-                            // Events are aggregated in chronological order
-                            transaction.events.push(event.event_type)
+                            match event_order {
+                                // Events are aggregated in chronological order
+                                EventOrder::Grouped => transaction.events.push(event),
+                                // Events are interleaved with the transactions in chronological order
+                                EventOrder::FileOrder => {
+                                    transaction_history.push(HistoryEntry::Event(event))
+                                }
+                            }
                         } else {
                             // Assumption: client_id and transaction_id of the transaction event have to coincide with the actual transaction; ignore if this is not the case
-                            continue;
+                            rejections.push(Rejection::from_event(
+                                &event,
+                                RejectionReason::ClientMismatch,
+                            ));
                         }
                     }
                     // Assumption: transaction events which do not reference a valid transaction_id can be ignored
-                    None => continue,
+                    None => rejections.push(Rejection::from_event(
+                        &event,
+                        RejectionReason::UnknownTransaction,
+                    )),
                 }
             }
         };
@@ -49,44 +81,124 @@ pub fn preprocess(
     Ok((transaction_history, transactions))
 }
 
+// Convert the opening balances of the client accounts to business objects
+pub fn open_accounts(raw_accounts: Vec<RawAccount>) -> Result<HashMap<u16, Account>, EngineError> {
+    let mut accounts: HashMap<u16, Account> = HashMap::new();
+
+    for raw_account in raw_accounts {
+        // Check that the balances add up via the TryFrom conversion
+        let account = Account::try_from(raw_account)?;
+
+        match accounts.entry(account.client_id) {
+            Entry::Occupied(_) => return Err(FormatError::UniqueClientId(account.client_id).into()),
+            Entry::Vacant(entry) => {
+                entry.insert(account);
+            }
+        }
+    }
+
+    Ok(accounts)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::{EventType, TransactionType};
+    use crate::models::{Amount, EventType, TransactionType};
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    #[test]
+    fn test_open_accounts() {
+        let raw_accounts = vec![
+            RawAccount::new(1, amount("1.0"), amount("0.5"), amount("1.5"), false),
+            RawAccount::new(2, Amount::ZERO, Amount::ZERO, Amount::ZERO, true),
+        ];
+
+        let res = open_accounts(raw_accounts);
+        assert!(res.is_ok());
+
+        let accounts = res.unwrap();
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts.get(&1).unwrap().held_amount, amount("0.5"));
+        assert!(accounts.get(&2).unwrap().is_locked);
+    }
+
+    #[test]
+    fn test_open_accounts_invalid() {
+        let res = open_accounts(vec![RawAccount::new(
+            1,
+            amount("1.0"),
+            amount("0.5"),
+            amount("1.0"),
+            false,
+        )]);
+        assert!(matches!(
+            res.unwrap_err(),
+            EngineError::Format(FormatError::UnbalancedAccount(1))
+        ));
+
+        let res = open_accounts(vec![
+            RawAccount::new(1, Amount::ZERO, Amount::ZERO, Amount::ZERO, false),
+            RawAccount::new(1, Amount::ZERO, Amount::ZERO, Amount::ZERO, false),
+        ]);
+        assert!(matches!(
+            res.unwrap_err(),
+            EngineError::Format(FormatError::UniqueClientId(1))
+        ));
+    }
 
     #[test]
     fn test_preprocess() {
         let raw_transactions: Vec<RawTransaction> = vec![
-            RawTransaction::new(String::from("deposit"), 1, 1, Some(1.0)),
-            RawTransaction::new(String::from("deposit"), 2, 2, Some(2.0)),
-            RawTransaction::new(String::from("deposit"), 1, 3, Some(2.0)),
-            RawTransaction::new(String::from("withdrawal"), 1, 4, Some(1.5)),
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("1.0"))),
+            RawTransaction::new(String::from("deposit"), 2, 2, Some(amount("2.0"))),
+            RawTransaction::new(String::from("deposit"), 1, 3, Some(amount("2.0"))),
+            RawTransaction::new(String::from("withdrawal"), 1, 4, Some(amount("1.5"))),
             RawTransaction::new(String::from("dispute"), 1, 4, None),
             RawTransaction::new(String::from("chargeback"), 1, 4, None),
-            RawTransaction::new(String::from("withdrawal"), 2, 5, Some(2.0)),
+            RawTransaction::new(String::from("withdrawal"), 2, 5, Some(amount("2.0"))),
         ];
 
-        let res = preprocess(raw_transactions);
+        let res = preprocess(raw_transactions, EventOrder::Grouped, &mut vec![]);
         assert!(res.is_ok());
 
         let (transaction_history, transactions) = res.unwrap();
-        assert_eq!(transaction_history, vec![1, 2, 3, 4, 5]);
+        assert_eq!(
+            transaction_history,
+            vec![
+                HistoryEntry::Transaction(1),
+                HistoryEntry::Transaction(2),
+                HistoryEntry::Transaction(3),
+                HistoryEntry::Transaction(4),
+                HistoryEntry::Transaction(5),
+            ]
+        );
 
-        let target = vec![
-            Transaction::new(TransactionType::Deposit, 1, 1, 1.0),
-            Transaction::new(TransactionType::Deposit, 2, 2, 2.0),
-            Transaction::new(TransactionType::Deposit, 1, 3, 2.0),
+        let target = [
+            Transaction::new(TransactionType::Deposit, 1, 1, amount("1.0")),
+            Transaction::new(TransactionType::Deposit, 2, 2, amount("2.0")),
+            Transaction::new(TransactionType::Deposit, 1, 3, amount("2.0")),
             Transaction::with_events(
                 TransactionType::Withdrawal,
                 1,
                 4,
-                1.5,
-                vec![EventType::Dispute, EventType::Chargeback],
+                amount("1.5"),
+                vec![
+                    TransactionEvent::new(EventType::Dispute, 1, 4),
+                    TransactionEvent::new(EventType::Chargeback, 1, 4),
+                ],
             ),
-            Transaction::new(TransactionType::Withdrawal, 2, 5, 2.0),
+            Transaction::new(TransactionType::Withdrawal, 2, 5, amount("2.0")),
         ];
 
-        for transaction_id in transaction_history {
+        for entry in transaction_history {
+            let transaction_id = match entry {
+                HistoryEntry::Transaction(transaction_id) => transaction_id,
+                HistoryEntry::Event(..) => panic!("Unexpected transaction event in history"),
+            };
+
             assert_eq!(
                 transactions.get(&transaction_id).unwrap(),
                 target
@@ -96,4 +208,41 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_preprocess_file_order() {
+        let raw_transactions: Vec<RawTransaction> = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("1.0"))),
+            RawTransaction::new(String::from("deposit"), 1, 2, Some(amount("2.0"))),
+            RawTransaction::new(String::from("dispute"), 1, 1, None),
+            RawTransaction::new(String::from("withdrawal"), 1, 3, Some(amount("1.5"))),
+            RawTransaction::new(String::from("resolve"), 1, 1, None),
+            // Ignore transaction event if client id doesn't coincide
+            RawTransaction::new(String::from("dispute"), 2, 2, None),
+        ];
+
+        let mut rejections = vec![];
+        let res = preprocess(raw_transactions, EventOrder::FileOrder, &mut rejections);
+        assert!(res.is_ok());
+
+        let (transaction_history, transactions) = res.unwrap();
+        assert_eq!(
+            transaction_history,
+            vec![
+                HistoryEntry::Transaction(1),
+                HistoryEntry::Transaction(2),
+                HistoryEntry::Event(TransactionEvent::new(EventType::Dispute, 1, 1)),
+                HistoryEntry::Transaction(3),
+                HistoryEntry::Event(TransactionEvent::new(EventType::Resolve, 1, 1)),
+            ]
+        );
+        assert!(transactions.values().all(|tx| tx.events.is_empty()));
+        assert_eq!(
+            rejections,
+            vec![Rejection::from_event(
+                &TransactionEvent::new(EventType::Dispute, 2, 2),
+                RejectionReason::ClientMismatch
+            )]
+        );
+    }
 }
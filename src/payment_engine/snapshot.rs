@@ -0,0 +1,246 @@
+use crate::{
+    errors::{EngineError, SnapshotError},
+    models::{Account, Timestamp, Transaction},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+};
+
+/*
+    A snapshot is the state of the engine at the end of a run: the client accounts and the processed transactions
+    Loading the snapshot at the start of the next run allows transaction events to reference transactions of previous runs
+    Snapshots are stored as JSON; the version is bumped on incompatible changes of the format
+    Positions and rejections are not part of the snapshot as they only relate to the input file of a single run
+    With a dispute window, the transactions within the window and the ids of finalized transactions are part of the snapshot as well
+    In chronological order, the timestamp of the last booked record is kept so that late arrivals are still rejected in the next run
+*/
+
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub accounts: Vec<Account>,
+    pub transactions: Vec<Transaction>,
+    #[serde(default, skip_serializing_if = "DisputeWindowState::is_empty")]
+    pub dispute_window: DisputeWindowState,
+    // Timestamp of the last booked record in chronological order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_timestamp: Option<Timestamp>,
+}
+
+// State of a limited dispute window; empty if transactions can be disputed forever
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DisputeWindowState {
+    // Number of records applied so far
+    pub records: u64,
+    // Latest timestamp applied so far
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<Timestamp>,
+    // Transactions within the dispute window in the order of booking
+    pub window: Vec<WindowEntry>,
+    // Ids of finalized transactions as inclusive ranges
+    pub finalized: Vec<(u32, u32)>,
+}
+
+impl DisputeWindowState {
+    pub fn is_empty(&self) -> bool {
+        self.window.is_empty() && self.finalized.is_empty()
+    }
+}
+
+// Transaction within the dispute window with the number of the record and the time at which it entered the window
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowEntry {
+    pub record: u64,
+    pub transaction_id: u32,
+    // Not set until the first record with timestamp has been applied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<Timestamp>,
+}
+
+impl Snapshot {
+    pub fn new(mut accounts: Vec<Account>, mut transactions: Vec<Transaction>) -> Self {
+        // Sort by id for deterministic output
+        accounts.sort_by_key(|account| account.client_id);
+        transactions.sort_by_key(|tx| tx.transaction_id);
+
+        Self {
+            version: SNAPSHOT_VERSION,
+            accounts,
+            transactions,
+            dispute_window: DisputeWindowState::default(),
+            last_timestamp: None,
+        }
+    }
+
+    pub fn read(reader: impl Read) -> Result<Self, EngineError> {
+        let snapshot: Snapshot = serde_json::from_reader(reader)?;
+        snapshot.check_version()?;
+
+        Ok(snapshot)
+    }
+
+    pub(crate) fn check_version(&self) -> Result<(), SnapshotError> {
+        if self.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(
+                self.version,
+                SNAPSHOT_VERSION,
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> Result<(), EngineError> {
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    pub fn load(snapshot_file: &str) -> Result<Self, EngineError> {
+        let file = File::open(snapshot_file).map_err(|source| EngineError::File {
+            message: format!("Can't find snapshot file at path '{}'", snapshot_file),
+            source,
+        })?;
+
+        Self::read(BufReader::new(file))
+    }
+
+    pub fn save(&self, snapshot_file: &str) -> Result<(), EngineError> {
+        let file = File::create(snapshot_file).map_err(|source| EngineError::File {
+            message: format!("Can't create snapshot file at path '{}'", snapshot_file),
+            source,
+        })?;
+
+        self.write(BufWriter::new(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Amount, EventType, TransactionEvent, TransactionStatus, TransactionType};
+    use std::fs;
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    fn snapshot() -> Snapshot {
+        Snapshot::new(
+            vec![Account {
+                client_id: 1,
+                available_amount: amount("1.0"),
+                held_amount: amount("2.0"),
+                total_amount: amount("3.0"),
+                is_locked: false,
+            }],
+            vec![
+                Transaction {
+                    transaction_type: TransactionType::Deposit,
+                    client_id: 1,
+                    transaction_id: 2,
+                    amount: amount("2.0"),
+                    events: vec![TransactionEvent::new(EventType::Dispute, 1, 2)],
+                    status: TransactionStatus::Disputed,
+                    timestamp: None,
+                    position: Default::default(),
+                },
+                Transaction::new(TransactionType::Deposit, 1, 1, amount("1.0")),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_snapshot_sorted() {
+        let snapshot = snapshot();
+        assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+
+        let ids: Vec<u32> = snapshot
+            .transactions
+            .iter()
+            .map(|tx| tx.transaction_id)
+            .collect();
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_write_read() {
+        let snapshot = snapshot();
+        let mut output = vec![];
+
+        let res = snapshot.write(&mut output);
+        assert!(res.is_ok());
+
+        let res = Snapshot::read(output.as_slice());
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap(), snapshot);
+    }
+
+    #[test]
+    fn test_write_format() {
+        let snapshot = Snapshot::new(
+            vec![],
+            vec![Transaction::new(
+                TransactionType::Withdrawal,
+                1,
+                1,
+                amount("1.5"),
+            )],
+        );
+        let mut output = vec![];
+
+        snapshot.write(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"{"version":2,"accounts":[],"transactions":[{"transaction_type":"withdrawal","client_id":1,"transaction_id":1,"amount":"1.5000","status":"initiated"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_read_unsupported_version() {
+        let res = Snapshot::read(r#"{"version":1,"accounts":[],"transactions":[]}"#.as_bytes());
+        assert!(res.is_err());
+
+        let err = res.unwrap_err();
+        assert!(matches!(
+            err,
+            EngineError::Snapshot(SnapshotError::UnsupportedVersion(1, 2))
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Can't restore snapshot: unsupported version 1 (expected version 2)"
+        );
+    }
+
+    #[test]
+    fn test_save_load() {
+        fs::create_dir_all("tmp").unwrap();
+        let snapshot_path = "tmp/snapshot.json";
+
+        let snapshot = snapshot();
+
+        let res = snapshot.save(snapshot_path);
+        assert!(res.is_ok());
+
+        let res = Snapshot::load(snapshot_path);
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap(), snapshot);
+
+        fs::remove_file(snapshot_path).unwrap();
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let res = Snapshot::load("test_data/missing.json");
+        assert!(res.is_err());
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "Can't find snapshot file at path 'test_data/missing.json'"
+        );
+    }
+}
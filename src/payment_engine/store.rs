@@ -0,0 +1,463 @@
+use super::DiskIndex;
+use crate::{
+    errors::EngineError,
+    models::{Amount, Transaction, TransactionStatus, TransactionType},
+};
+use std::collections::{hash_map, HashMap};
+
+/*
+    Compact store of the processed transactions which can be referenced by subsequent transaction events
+    Disputes only need the client, amount, type and status of a transaction; events and positions are not kept
+    Type and status are packed into a single byte, so an entry takes 12 bytes next to the 4 bytes of the transaction id
+    instead of the full Transaction type with its event list and position in the input file
+    Transaction ids are arbitrary u32 values, so the entries are indexed by a hash map rather than a dense table
+    If the transactions don't fit into memory, the entries are kept in a disk index instead (see DiskIndex)
+*/
+
+// Entry of a stored transaction; packed to an alignment of 4 bytes so that it fits next to the key without padding
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C, packed(4))]
+pub(crate) struct Entry {
+    amount: Amount,
+    client_id: u16,
+    // Transaction type in the lowest bit, status in the bits above
+    flags: u8,
+}
+
+// Size of an entry in the disk index
+pub(crate) const ENTRY_BYTES: usize = 12;
+
+// Marks a used slot in the disk index; empty slots are zeroed
+const PRESENT: u8 = 0x80;
+
+impl Entry {
+    fn new(tx: &Transaction) -> Self {
+        Self {
+            amount: tx.amount,
+            client_id: tx.client_id,
+            flags: pack(tx.transaction_type, tx.status),
+        }
+    }
+
+    fn transaction(self, transaction_id: u32) -> Transaction {
+        let (transaction_type, status) = unpack(self.flags);
+
+        let mut tx = Transaction::new(
+            transaction_type,
+            self.client_id,
+            transaction_id,
+            self.amount,
+        );
+        tx.status = status;
+        tx
+    }
+
+    fn with_status(self, status: TransactionStatus) -> Self {
+        let (transaction_type, _) = unpack(self.flags);
+
+        Self {
+            flags: pack(transaction_type, status),
+            ..self
+        }
+    }
+
+    pub(crate) fn to_bytes(self) -> [u8; ENTRY_BYTES] {
+        let mut bytes = [0; ENTRY_BYTES];
+        bytes[..8].copy_from_slice(&self.amount.to_raw().to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.client_id.to_le_bytes());
+        bytes[10] = self.flags | PRESENT;
+        bytes
+    }
+
+    // None for an empty slot
+    pub(crate) fn from_bytes(bytes: &[u8; ENTRY_BYTES]) -> Option<Self> {
+        if bytes[10] & PRESENT == 0 {
+            return None;
+        }
+
+        let mut amount = [0; 8];
+        amount.copy_from_slice(&bytes[..8]);
+
+        Some(Self {
+            amount: Amount::from_raw(i64::from_le_bytes(amount)),
+            client_id: u16::from_le_bytes([bytes[8], bytes[9]]),
+            flags: bytes[10] & !PRESENT,
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Backend {
+    Memory(HashMap<u32, Entry>),
+    Disk(DiskIndex),
+}
+
+#[derive(Debug)]
+pub struct TransactionStore {
+    backend: Backend,
+}
+
+impl Default for TransactionStore {
+    fn default() -> Self {
+        Self {
+            backend: Backend::Memory(HashMap::new()),
+        }
+    }
+}
+
+impl TransactionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Keep the transactions in the disk index rather than in memory
+    pub fn on_disk(index: DiskIndex) -> Self {
+        Self {
+            backend: Backend::Disk(index),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.backend {
+            Backend::Memory(entries) => entries.len(),
+            Backend::Disk(index) => index.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&mut self, transaction_id: u32) -> Result<bool, EngineError> {
+        Ok(self.entry(transaction_id)?.is_some())
+    }
+
+    // Store the transaction; returns false if the transaction id is already taken
+    pub fn insert(&mut self, tx: &Transaction) -> Result<bool, EngineError> {
+        match &mut self.backend {
+            Backend::Memory(entries) => match entries.entry(tx.transaction_id) {
+                hash_map::Entry::Occupied(_) => Ok(false),
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(Entry::new(tx));
+                    Ok(true)
+                }
+            },
+            Backend::Disk(index) => {
+                if index.get(tx.transaction_id)?.is_some() {
+                    return Ok(false);
+                }
+
+                index.insert(tx.transaction_id, Entry::new(tx))?;
+                Ok(true)
+            }
+        }
+    }
+
+    // Transaction without events and position, e.g. to apply a transaction event
+    pub fn get(&mut self, transaction_id: u32) -> Result<Option<Transaction>, EngineError> {
+        Ok(self
+            .entry(transaction_id)?
+            .map(|entry| entry.transaction(transaction_id)))
+    }
+
+    pub fn set_status(
+        &mut self,
+        transaction_id: u32,
+        status: TransactionStatus,
+    ) -> Result<(), EngineError> {
+        match &mut self.backend {
+            Backend::Memory(entries) => {
+                if let Some(entry) = entries.get_mut(&transaction_id) {
+                    *entry = entry.with_status(status);
+                }
+            }
+            Backend::Disk(index) => {
+                if let Some(entry) = index.get(transaction_id)? {
+                    index.update(transaction_id, entry.with_status(status))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Remove the transaction, e.g. once it can't be referenced by transaction events anymore
+    pub fn remove(&mut self, transaction_id: u32) -> Result<Option<Transaction>, EngineError> {
+        let entry = match &mut self.backend {
+            Backend::Memory(entries) => entries.remove(&transaction_id),
+            Backend::Disk(index) => index.remove(transaction_id)?,
+        };
+
+        Ok(entry.map(|entry| entry.transaction(transaction_id)))
+    }
+
+    // All transactions; the entries of a disk index are loaded into memory
+    pub fn transactions(&mut self) -> Result<Vec<Transaction>, EngineError> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .map(|(transaction_id, entry)| entry.transaction(transaction_id))
+            .collect())
+    }
+
+    // Client id of every transaction
+    pub fn clients(&mut self) -> Result<Vec<(u32, u16)>, EngineError> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .map(|(transaction_id, entry)| (transaction_id, entry.client_id))
+            .collect())
+    }
+
+    // Move the transactions of the other store; stores of different shards don't share transaction ids
+    pub fn extend(&mut self, mut other: TransactionStore) -> Result<(), EngineError> {
+        let other_entries = match other.backend {
+            Backend::Memory(entries) => entries,
+            Backend::Disk(_) => other.entries()?.into_iter().collect(),
+        };
+
+        match &mut self.backend {
+            Backend::Memory(entries) => entries.extend(other_entries),
+            Backend::Disk(index) => {
+                for (transaction_id, entry) in other_entries {
+                    index.update(transaction_id, entry)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Split the transactions by the index returned for the client id; the split stores are kept in memory
+    pub fn split(
+        mut self,
+        stores: usize,
+        index: impl Fn(u16) -> usize,
+    ) -> Result<Vec<TransactionStore>, EngineError> {
+        let mut split: Vec<HashMap<u32, Entry>> = (0..stores).map(|_| HashMap::new()).collect();
+
+        let entries = match self.backend {
+            Backend::Memory(entries) => entries,
+            Backend::Disk(_) => self.entries()?.into_iter().collect(),
+        };
+
+        for (transaction_id, entry) in entries {
+            split[index(entry.client_id)].insert(transaction_id, entry);
+        }
+
+        Ok(split
+            .into_iter()
+            .map(|entries| Self {
+                backend: Backend::Memory(entries),
+            })
+            .collect())
+    }
+
+    fn entry(&mut self, transaction_id: u32) -> Result<Option<Entry>, EngineError> {
+        match &mut self.backend {
+            Backend::Memory(entries) => Ok(entries.get(&transaction_id).copied()),
+            Backend::Disk(index) => index.get(transaction_id),
+        }
+    }
+
+    fn entries(&mut self) -> Result<Vec<(u32, Entry)>, EngineError> {
+        match &mut self.backend {
+            Backend::Memory(entries) => Ok(entries
+                .iter()
+                .map(|(transaction_id, entry)| (*transaction_id, *entry))
+                .collect()),
+            Backend::Disk(index) => index.entries(),
+        }
+    }
+}
+
+fn pack(transaction_type: TransactionType, status: TransactionStatus) -> u8 {
+    let transaction_type = match transaction_type {
+        TransactionType::Deposit => 0,
+        TransactionType::Withdrawal => 1,
+    };
+    let status = match status {
+        TransactionStatus::Initiated => 0,
+        TransactionStatus::Processed => 1,
+        TransactionStatus::Disputed => 2,
+        TransactionStatus::Resolved => 3,
+        TransactionStatus::Reversed => 4,
+        TransactionStatus::Rejected => 5,
+    };
+
+    status << 1 | transaction_type
+}
+
+fn unpack(flags: u8) -> (TransactionType, TransactionStatus) {
+    let transaction_type = match flags & 1 {
+        0 => TransactionType::Deposit,
+        _ => TransactionType::Withdrawal,
+    };
+    let status = match flags >> 1 {
+        0 => TransactionStatus::Initiated,
+        1 => TransactionStatus::Processed,
+        2 => TransactionStatus::Disputed,
+        3 => TransactionStatus::Resolved,
+        4 => TransactionStatus::Reversed,
+        _ => TransactionStatus::Rejected,
+    };
+
+    (transaction_type, status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{EventType, Position, TransactionEvent};
+    use std::mem;
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    // Approximate heap size of a hash map: key, value and one control byte per bucket
+    fn footprint<K, V>(map: &HashMap<K, V>) -> usize {
+        map.capacity() * (mem::size_of::<(K, V)>() + 1)
+    }
+
+    #[test]
+    fn test_entry_size() {
+        assert_eq!(mem::size_of::<Entry>(), 12);
+        assert_eq!(mem::size_of::<(u32, Entry)>(), 16);
+    }
+
+    #[test]
+    fn test_pack_unpack() {
+        for transaction_type in [TransactionType::Deposit, TransactionType::Withdrawal] {
+            for status in [
+                TransactionStatus::Initiated,
+                TransactionStatus::Processed,
+                TransactionStatus::Disputed,
+                TransactionStatus::Resolved,
+                TransactionStatus::Reversed,
+                TransactionStatus::Rejected,
+            ] {
+                assert_eq!(
+                    unpack(pack(transaction_type, status)),
+                    (transaction_type, status)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_insert_get() {
+        let mut store = TransactionStore::new();
+
+        let mut tx = Transaction::new(TransactionType::Withdrawal, 2, 7, amount("-1.2345"));
+        tx.status = TransactionStatus::Processed;
+        tx.position = Position::new(3);
+        tx.events = vec![TransactionEvent::new(EventType::Dispute, 2, 7)];
+
+        assert!(store.insert(&tx).unwrap());
+        assert!(!store.insert(&tx).unwrap());
+        assert_eq!(store.len(), 1);
+        assert!(store.contains(7).unwrap());
+        assert_eq!(store.clients().unwrap(), vec![(7, 2)]);
+
+        // Events and position are not stored
+        let stored = store.get(7).unwrap().unwrap();
+        assert_eq!(stored.transaction_type, TransactionType::Withdrawal);
+        assert_eq!(stored.client_id, 2);
+        assert_eq!(stored.transaction_id, 7);
+        assert_eq!(stored.amount, amount("-1.2345"));
+        assert_eq!(stored.status, TransactionStatus::Processed);
+        assert!(stored.events.is_empty());
+        assert_eq!(stored.position, Position::default());
+
+        store.set_status(7, TransactionStatus::Disputed).unwrap();
+        assert_eq!(
+            store.get(7).unwrap().unwrap().status,
+            TransactionStatus::Disputed
+        );
+        assert!(store.get(8).unwrap().is_none());
+
+        let removed = store.remove(7).unwrap().unwrap();
+        assert_eq!(removed.status, TransactionStatus::Disputed);
+        assert!(store.is_empty());
+        assert!(store.remove(7).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_split_extend() {
+        let mut store = TransactionStore::new();
+
+        for transaction_id in 1..=10 {
+            let client_id = (transaction_id % 3) as u16;
+            store
+                .insert(&Transaction::new(
+                    TransactionType::Deposit,
+                    client_id,
+                    transaction_id,
+                    amount("1.0"),
+                ))
+                .unwrap();
+        }
+
+        let mut split = store.split(3, |client_id| client_id as usize).unwrap();
+        assert_eq!(split.iter().map(TransactionStore::len).sum::<usize>(), 10);
+
+        for (index, store) in split.iter_mut().enumerate() {
+            assert!(store
+                .clients()
+                .unwrap()
+                .iter()
+                .all(|(_, client_id)| *client_id as usize == index));
+        }
+
+        let mut merged = TransactionStore::new();
+
+        for store in split {
+            merged.extend(store).unwrap();
+        }
+
+        assert_eq!(merged.len(), 10);
+    }
+
+    #[test]
+    #[ignore = "memory benchmark"]
+    fn test_footprint() {
+        let records = 10_000_000;
+        let file: Option<std::sync::Arc<str>> = Some("transactions.csv".into());
+
+        let mut store = TransactionStore::new();
+        let mut transactions: HashMap<u32, Transaction> = HashMap::new();
+
+        for transaction_id in 1..=records {
+            let mut tx = Transaction::new(
+                TransactionType::Deposit,
+                (transaction_id % 1000) as u16,
+                transaction_id,
+                amount("1.0"),
+            );
+            tx.status = TransactionStatus::Processed;
+            tx.position = Position::new(transaction_id as u64 + 1).with_file(file.clone());
+
+            store.insert(&tx).unwrap();
+            transactions.insert(transaction_id, tx);
+        }
+
+        let compact = match &store.backend {
+            Backend::Memory(entries) => footprint(entries),
+            Backend::Disk(_) => unreachable!(),
+        };
+        let full = footprint(&transactions);
+
+        println!(
+            "transactions: {} MB ({} bytes per transaction)",
+            full / 1_000_000,
+            full / records as usize
+        );
+        println!(
+            "transaction store: {} MB ({} bytes per transaction)",
+            compact / 1_000_000,
+            compact / records as usize
+        );
+        assert!(compact * 4 < full);
+    }
+}
@@ -0,0 +1,431 @@
+use super::{Checkpoint, Engine};
+use crate::{
+    errors::{EngineError, WalError},
+    input::TransactionSource,
+    models::{Position, RawTransaction},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/*
+    Write-ahead log for crash-safe processing of large input files
+    Every applied raw transaction is appended to the log; every N records the engine state is written to a checkpoint and the log is truncated
+    After a crash, the engine is restored from the last checkpoint and the log is replayed; input records which were applied before the crash are skipped so that no transaction is booked twice
+    Entries which didn't reach the log before the crash are read from the input again
+    The log is flushed and synced to disk every SYNC_INTERVAL records and at the end of the input, so a host crash loses at most SYNC_INTERVAL entries
+*/
+
+const CHECKPOINT_FILE: &str = "checkpoint.json";
+const LOG_FILE: &str = "wal.jsonl";
+const SYNC_INTERVAL: u64 = 1_000;
+
+// Raw transaction which was applied to the engine; serialized by reference
+#[derive(Debug, Serialize, Deserialize)]
+struct LogEntry<T> {
+    // Number of the input record starting at 1
+    record: u64,
+    // Line in the input file; used for the rejection report
+    line: u64,
+    transaction: T,
+}
+
+pub struct Wal {
+    dir: PathBuf,
+    log: BufWriter<File>,
+    // Number of input records applied to the engine
+    records: u64,
+    // Number of input records included in the last checkpoint
+    checkpoint_records: u64,
+    // Number of input records whose log entries are synced to disk
+    synced_records: u64,
+    checkpoint_interval: u64,
+    // Serialized entry of the raw transaction which is currently applied
+    entry: Vec<u8>,
+}
+
+impl Wal {
+    // Open the log directory and recover the state of an interrupted run into the engine
+    // The state of the engine is only replaced by a checkpoint and extended by log entries of the directory; its configuration, e.g. the transaction store, is kept either way
+    pub fn open(
+        wal_dir: &str,
+        checkpoint_interval: u64,
+        engine: &mut Engine,
+    ) -> Result<Self, EngineError> {
+        let dir = PathBuf::from(wal_dir);
+        fs::create_dir_all(&dir).map_err(|source| EngineError::File {
+            message: format!(
+                "Can't create write-ahead log directory at path '{}'",
+                wal_dir
+            ),
+            source,
+        })?;
+
+        let mut records = 0;
+
+        if let Some(checkpoint) = Checkpoint::load(&dir.join(CHECKPOINT_FILE))? {
+            records = checkpoint.records;
+            checkpoint.restore(engine)?;
+        }
+
+        let checkpoint_records = records;
+        let log_path = dir.join(LOG_FILE);
+        let len = replay(&log_path, engine, &mut records)?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)?;
+
+        // Cut off an incomplete entry which was written during the crash
+        file.set_len(len)?;
+
+        Ok(Self {
+            dir,
+            log: BufWriter::new(file),
+            records,
+            checkpoint_records,
+            synced_records: records,
+            checkpoint_interval: checkpoint_interval.max(1),
+            entry: vec![],
+        })
+    }
+
+    // Number of input records applied to the engine, including the records of an interrupted run
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    // Apply the raw transactions of the source; records which were applied before the interruption are skipped
+    pub fn apply_all(
+        &mut self,
+        engine: &mut Engine,
+        source: impl TransactionSource,
+    ) -> Result<(), EngineError> {
+        for raw_transaction in source.skip(self.records as usize) {
+            self.apply(engine, raw_transaction?)?;
+        }
+
+        self.sync()
+    }
+
+    // Apply the raw transaction and append it to the log
+    pub fn apply(
+        &mut self,
+        engine: &mut Engine,
+        raw_transaction: RawTransaction,
+    ) -> Result<(), EngineError> {
+        // Serialize before applying as the raw transaction is consumed by the engine
+        self.entry.clear();
+        serde_json::to_writer(
+            &mut self.entry,
+            &LogEntry {
+                record: self.records + 1,
+                line: raw_transaction.position.line,
+                transaction: &raw_transaction,
+            },
+        )?;
+        self.entry.push(b'\n');
+
+        // Only successfully applied raw transactions are logged
+        engine.apply(raw_transaction)?;
+
+        self.log.write_all(&self.entry)?;
+        self.records += 1;
+
+        if self.records - self.checkpoint_records >= self.checkpoint_interval {
+            self.checkpoint(engine)?;
+        } else if self.records - self.synced_records >= SYNC_INTERVAL {
+            self.sync()?;
+        }
+
+        Ok(())
+    }
+
+    // Flush the log and sync its entries to disk
+    fn sync(&mut self) -> Result<(), EngineError> {
+        self.log.flush()?;
+        self.log.get_ref().sync_data()?;
+        self.synced_records = self.records;
+
+        Ok(())
+    }
+
+    // Write the state of the engine to the checkpoint and truncate the log
+    pub fn checkpoint(&mut self, engine: &mut Engine) -> Result<(), EngineError> {
+        // The position of the next input record is not needed as applied records are skipped
+        Checkpoint::new(engine, self.records, None)?.save(&self.dir.join(CHECKPOINT_FILE))?;
+
+        // Entries up to the checkpoint are skipped during replay if the log isn't truncated before a crash
+        self.log.flush()?;
+        self.log.get_ref().set_len(0)?;
+        self.checkpoint_records = self.records;
+        self.synced_records = self.records;
+
+        Ok(())
+    }
+
+    // Remove checkpoint and log after the run completed
+    pub fn close(mut self) -> Result<(), EngineError> {
+        self.log.flush()?;
+
+        for file in [CHECKPOINT_FILE, LOG_FILE] {
+            let path = self.dir.join(file);
+
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Apply the log entries following the checkpoint and return the length of the complete entries in bytes
+fn replay(path: &Path, engine: &mut Engine, records: &mut u64) -> Result<u64, EngineError> {
+    if !path.exists() {
+        return Ok(0);
+    }
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut line = String::new();
+    let mut len = 0;
+    let mut line_number = 0;
+
+    loop {
+        line.clear();
+        let bytes = reader.read_line(&mut line)?;
+
+        // An entry without line break is incomplete; it wasn't applied before the crash
+        if bytes == 0 || !line.ends_with('\n') {
+            break;
+        }
+
+        line_number += 1;
+
+        let entry: LogEntry<RawTransaction> =
+            serde_json::from_str(&line).map_err(|_| WalError::InvalidEntry(line_number))?;
+
+        // Entry is part of the checkpoint already
+        if entry.record <= *records {
+            len += bytes as u64;
+            continue;
+        }
+
+        if entry.record != *records + 1 {
+            return Err(WalError::UnexpectedRecord(*records + 1, entry.record).into());
+        }
+
+        let mut raw_transaction = entry.transaction;
+        raw_transaction.position = Position::new(entry.line);
+        engine.apply(raw_transaction)?;
+
+        *records += 1;
+        len += bytes as u64;
+    }
+
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        input::MemorySource,
+        models::{Amount, RejectionReason},
+        payment_engine::{DisputeWindow, ErrorPolicy},
+    };
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    fn raw_transactions() -> Vec<RawTransaction> {
+        let raw_transactions = vec![
+            RawTransaction::new(String::from("deposit"), 1, 1, Some(amount("1.0"))),
+            RawTransaction::new(String::from("deposit"), 2, 2, Some(amount("2.0"))),
+            RawTransaction::new(String::from("withdrawal"), 2, 3, Some(amount("3.0"))),
+            RawTransaction::new(String::from("deposit"), 1, 4, Some(amount("2.0"))),
+            RawTransaction::new(String::from("dispute"), 1, 1, None),
+            RawTransaction::new(String::from("withdrawal"), 1, 5, Some(amount("1.5"))),
+            RawTransaction::new(String::from("deposit"), 2, 6, Some(amount("0.5"))),
+        ];
+
+        raw_transactions
+            .into_iter()
+            .enumerate()
+            .map(|(i, mut raw_transaction)| {
+                raw_transaction.position = Position::new(i as u64 + 2);
+                raw_transaction
+            })
+            .collect()
+    }
+
+    // Positions of restored transactions are not part of the checkpoint; compare the serialized state
+    fn state(engine: &mut Engine) -> String {
+        let mut output = vec![];
+        engine.snapshot().unwrap().write(&mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn uninterrupted() -> Engine {
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip);
+        engine
+            .apply_all(MemorySource::new(raw_transactions()))
+            .unwrap();
+        engine
+    }
+
+    #[test]
+    fn test_recover_after_crash() {
+        let wal_dir = "tmp/wal_recover";
+        let _ = fs::remove_dir_all(wal_dir);
+
+        // Crash after 5 records with a checkpoint after 3 records
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip);
+        let mut wal = Wal::open(wal_dir, 3, &mut engine).unwrap();
+        let res = wal.apply_all(&mut engine, MemorySource::new(raw_transactions()).take(5));
+        assert!(res.is_ok());
+        drop(wal);
+
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip);
+        let mut wal = Wal::open(wal_dir, 3, &mut engine).unwrap();
+        assert_eq!(wal.records(), 5);
+        assert_eq!(engine.snapshot().unwrap().transactions.len(), 4);
+
+        // Restart with the same input; applied records are skipped
+        let res = wal.apply_all(&mut engine, MemorySource::new(raw_transactions()));
+        assert!(res.is_ok());
+        assert_eq!(wal.records(), 7);
+        assert_eq!(state(&mut engine), state(&mut uninterrupted()));
+        assert_eq!(engine.rejections(), uninterrupted().rejections());
+        assert_eq!(
+            engine.rejections()[0].reason,
+            RejectionReason::InsufficientFunds
+        );
+
+        let res = wal.close();
+        assert!(res.is_ok());
+        assert!(!Path::new(wal_dir).join(CHECKPOINT_FILE).exists());
+        assert!(!Path::new(wal_dir).join(LOG_FILE).exists());
+
+        fs::remove_dir_all(wal_dir).unwrap();
+    }
+
+    #[test]
+    fn test_recover_keeps_configuration() {
+        let wal_dir = "tmp/wal_configuration";
+        let _ = fs::remove_dir_all(wal_dir);
+
+        let configured = || {
+            Engine::with_error_policy(ErrorPolicy::Skip)
+                .with_dispute_window(DisputeWindow::Records(3))
+                .unwrap()
+        };
+
+        // Crash after 5 records with a checkpoint after 3 records
+        let mut engine = configured();
+        let mut wal = Wal::open(wal_dir, 3, &mut engine).unwrap();
+        let res = wal.apply_all(&mut engine, MemorySource::new(raw_transactions()).take(5));
+        assert!(res.is_ok());
+        drop(wal);
+
+        let mut engine = configured();
+        let mut wal = Wal::open(wal_dir, 3, &mut engine).unwrap();
+        assert_eq!(engine.dispute_window(), DisputeWindow::Records(3));
+
+        let res = wal.apply_all(&mut engine, MemorySource::new(raw_transactions()));
+        assert!(res.is_ok());
+
+        let mut expected = configured();
+        expected
+            .apply_all(MemorySource::new(raw_transactions()))
+            .unwrap();
+        assert_eq!(state(&mut engine), state(&mut expected));
+        assert_eq!(engine.rejections(), expected.rejections());
+        assert_eq!(
+            engine.rejections()[1].reason,
+            RejectionReason::OutsideDisputeWindow
+        );
+
+        fs::remove_dir_all(wal_dir).unwrap();
+    }
+
+    #[test]
+    fn test_sync_interval() {
+        let wal_dir = "tmp/wal_sync";
+        let _ = fs::remove_dir_all(wal_dir);
+
+        let raw_transactions = (1..=SYNC_INTERVAL as u32 + 1)
+            .map(|tx| RawTransaction::new(String::from("deposit"), 1, tx, Some(amount("1.0"))));
+
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip);
+        let mut wal = Wal::open(wal_dir, u64::MAX, &mut engine).unwrap();
+
+        for raw_transaction in raw_transactions {
+            wal.apply(&mut engine, raw_transaction).unwrap();
+        }
+
+        // Entries up to the sync interval are on disk while the log is still open
+        let log = fs::read_to_string(Path::new(wal_dir).join(LOG_FILE)).unwrap();
+        assert_eq!(log.lines().count(), SYNC_INTERVAL as usize);
+        drop(wal);
+
+        fs::remove_dir_all(wal_dir).unwrap();
+    }
+
+    #[test]
+    fn test_recover_incomplete_entry() {
+        let wal_dir = "tmp/wal_incomplete";
+        let _ = fs::remove_dir_all(wal_dir);
+
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip);
+        let mut wal = Wal::open(wal_dir, 100, &mut engine).unwrap();
+        wal.apply_all(&mut engine, MemorySource::new(raw_transactions()).take(2))
+            .unwrap();
+        drop(wal);
+
+        // Crash while the third entry is written
+        let mut log = OpenOptions::new()
+            .append(true)
+            .open(Path::new(wal_dir).join(LOG_FILE))
+            .unwrap();
+        log.write_all(br#"{"record":3,"line":4,"transaction":{"type":"with"#)
+            .unwrap();
+
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip);
+        let mut wal = Wal::open(wal_dir, 100, &mut engine).unwrap();
+        assert_eq!(wal.records(), 2);
+
+        wal.apply_all(&mut engine, MemorySource::new(raw_transactions()))
+            .unwrap();
+        assert_eq!(state(&mut engine), state(&mut uninterrupted()));
+        drop(wal);
+
+        // The incomplete entry was cut off before appending
+        let mut engine = Engine::with_error_policy(ErrorPolicy::Skip);
+        let wal = Wal::open(wal_dir, 100, &mut engine).unwrap();
+        assert_eq!(wal.records(), 7);
+
+        fs::remove_dir_all(wal_dir).unwrap();
+    }
+
+    #[test]
+    fn test_recover_invalid_entry() {
+        let wal_dir = "tmp/wal_invalid";
+        let _ = fs::remove_dir_all(wal_dir);
+        fs::create_dir_all(wal_dir).unwrap();
+        fs::write(Path::new(wal_dir).join(LOG_FILE), "invalid\n").unwrap();
+
+        let res = Wal::open(wal_dir, 100, &mut Engine::new());
+        assert!(matches!(
+            res.err().unwrap(),
+            EngineError::Wal(WalError::InvalidEntry(1))
+        ));
+
+        fs::remove_dir_all(wal_dir).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+use crate::{
+    errors::{EngineError, FormatError, ReferenceError},
+    models::{CheckedTransaction, RawTransaction},
+};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt,
+};
+
+/*
+    Validation checks the input file without booking any transactions
+    In contrast to processing, validation doesn't stop at the first error; all invalid records are collected in the report
+    Transaction events have to reference a preceding transaction of the same client
+*/
+
+// Invalid record of the input file
+#[derive(Debug, PartialEq)]
+pub struct Issue {
+    pub line: u64,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    // Number of records in the input file
+    pub records: usize,
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    fn push(&mut self, line: u64, err: impl fmt::Display) {
+        self.issues.push(Issue {
+            line,
+            message: err.to_string(),
+        });
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "{}", issue)?;
+        }
+
+        write!(
+            f,
+            "{} records checked, {} valid, {} invalid",
+            self.records,
+            self.records - self.issues.len(),
+            self.issues.len()
+        )
+    }
+}
+
+pub fn validate(
+    raw_transactions: impl IntoIterator<Item = Result<RawTransaction, EngineError>>,
+) -> Result<ValidationReport, EngineError> {
+    let mut report = ValidationReport::default();
+
+    // Client id and line of every valid transaction; used to check uniqueness and references of transaction events
+    let mut transactions: HashMap<u32, (u16, u64)> = HashMap::new();
+
+    for raw_transaction in raw_transactions {
+        report.records += 1;
+
+        let raw_transaction = match raw_transaction {
+            Ok(raw_transaction) => raw_transaction,
+            Err(EngineError::Record { position, source }) => {
+                report.push(position.line, source);
+                continue;
+            }
+            // The input file can't be read any further
+            Err(err) => return Err(err),
+        };
+
+        let line = raw_transaction.position.line;
+
+        // Check and verify input format via CheckedTransaction type
+        let checked_transaction = match CheckedTransaction::try_from(raw_transaction) {
+            Ok(checked_transaction) => checked_transaction,
+            Err(err) => {
+                report.push(line, err);
+                continue;
+            }
+        };
+
+        match checked_transaction {
+            CheckedTransaction::Transaction(tx) => match transactions.entry(tx.transaction_id) {
+                Entry::Occupied(_) => {
+                    report.push(line, FormatError::UniqueTransactionId(tx.transaction_id))
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert((tx.client_id, line));
+                }
+            },
+            CheckedTransaction::TransactionEvent(event) => {
+                match transactions.get(&event.transaction_id) {
+                    Some((client_id, _)) if *client_id != event.client_id => report.push(
+                        line,
+                        ReferenceError::ClientMismatch(
+                            event.event_type.to_string(),
+                            event.client_id,
+                            event.transaction_id,
+                            *client_id,
+                        ),
+                    ),
+                    Some(_) => {}
+                    None => report.push(
+                        line,
+                        ReferenceError::UnknownTransaction(
+                            event.event_type.to_string(),
+                            event.transaction_id,
+                        ),
+                    ),
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let res =
+            validate(crate::csv::read_stream("test_data/transactions_with_events.csv").unwrap());
+        assert!(res.is_ok());
+
+        let report = res.unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.records, 7);
+    }
+
+    #[test]
+    fn test_validate_collects_all_issues() {
+        let res = validate(crate::csv::read_stream("test_data/transactions_validate.csv").unwrap());
+        assert!(res.is_ok());
+
+        let report = res.unwrap();
+        assert!(!report.is_valid());
+        assert_eq!(report.records, 9);
+
+        let lines: Vec<u64> = report.issues.iter().map(|issue| issue.line).collect();
+        assert_eq!(lines, vec![3, 4, 5, 6, 7, 8, 9]);
+
+        assert_eq!(
+            report.issues[0].message,
+            "Unexpected format: invalid amount 'abc'"
+        );
+        assert_eq!(
+            report.issues[1].message,
+            "Unexpected format: transaction id 1 is not unique"
+        );
+        assert_eq!(
+            report.issues[2].message,
+            "Unexpected format: missing amount for transaction id 4 and transaction type 'withdrawal'"
+        );
+        assert_eq!(
+            report.issues[3].message,
+            "Unexpected reference: dispute references unknown transaction id 5"
+        );
+        assert_eq!(
+            report.issues[4].message,
+            "Unexpected reference: dispute of client id 2 references transaction id 1 of client id 1"
+        );
+        assert_eq!(
+            report.issues[5].message,
+            "Unexpected format: invalid transaction type 'transfer' in transaction id 6"
+        );
+        assert_eq!(
+            report.issues[6].message,
+            "Unexpected format: amount is negative for transaction id 7 and transaction type 'deposit'"
+        );
+    }
+
+    #[test]
+    fn test_validation_report_summary() {
+        let report = ValidationReport {
+            records: 3,
+            issues: vec![Issue {
+                line: 3,
+                message: String::from("Unexpected format: transaction id 1 is not unique"),
+            }],
+        };
+
+        assert_eq!(
+            report.to_string(),
+            "line 3: Unexpected format: transaction id 1 is not unique\n3 records checked, 2 valid, 1 invalid"
+        );
+    }
+}